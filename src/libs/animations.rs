@@ -1,64 +1,500 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use super::snapshot;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AnimationFrame {
+    pub tile: [usize; 2],
+    pub duration: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopMode {
+    Loop,
+    Once,
+    PingPong,
+}
+
+impl LoopMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "loop" => Some(LoopMode::Loop),
+            "once" => Some(LoopMode::Once),
+            "ping_pong" => Some(LoopMode::PingPong),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct SpriteAnimation {
-    pub name: &'static str,
-    animations: Vec<[usize; 2]>,
+    pub name: String,
+    frames: Vec<AnimationFrame>,
+    loop_mode: LoopMode,
     animation_lt: f64,
-    animation_interval: f64,
     animation_idx: usize,
+    reverse: bool,
+    speed: f64,
     state: AnimationState,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum AnimationState {
-    IDLE,
-    RUNNING,
+    Idle,
+    Running,
+    Finished,
+}
+
+impl AnimationState {
+    fn name(&self) -> &'static str {
+        match self {
+            AnimationState::Idle => "idle",
+            AnimationState::Running => "running",
+            AnimationState::Finished => "finished",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "idle" => Some(AnimationState::Idle),
+            "running" => Some(AnimationState::Running),
+            "finished" => Some(AnimationState::Finished),
+            _ => None,
+        }
+    }
+}
+
+/// How far a clip has played, as kept in snapshots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationProgress {
+    frame: usize,
+    time: f64,
+    reverse: bool,
+    speed: f64,
+    state: AnimationState,
+}
+
+impl AnimationProgress {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "frame": self.frame,
+            "time": self.time,
+            "reverse": self.reverse,
+            "speed": self.speed,
+            "state": self.state.name(),
+        })
+    }
+
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let state = snapshot::text(json, "state")?;
+        Ok(Self {
+            frame: snapshot::number(json, "frame")? as usize,
+            time: snapshot::number(json, "time")?,
+            reverse: snapshot::flag(json, "reverse")?,
+            speed: snapshot::number(json, "speed")?,
+            state: AnimationState::from_name(state)
+                .ok_or_else(|| format!("unknown animation state `{}`", state))?,
+        })
+    }
 }
 
 impl SpriteAnimation {
-    pub fn new(name: &'static str, animations: Vec<[usize; 2]>) -> Self {
+    pub fn new(name: &str, frames: Vec<AnimationFrame>, loop_mode: LoopMode) -> Self {
         Self {
-            name: name,
-            animations: animations,
+            name: name.to_string(),
+            frames,
+            loop_mode,
             animation_lt: 0.0,
-            animation_interval: 0.0,
             animation_idx: 0,
-            state: AnimationState::IDLE,
+            reverse: false,
+            speed: 1.0,
+            state: AnimationState::Idle,
         }
     }
 
-    pub fn set_animation_interval(&mut self, t: f64) {
-        self.animation_interval = t;
+    /// Parses a clip of the form
+    /// `{ "loop": "once", "frames": [{ "tile": [row, col], "duration": 0.1 }] }`.
+    pub fn from_json(name: &str, value: &Value) -> Result<Self, String> {
+        let loop_mode = match value.get("loop").and_then(Value::as_str) {
+            Some(mode) => LoopMode::from_name(mode)
+                .ok_or_else(|| format!("animation `{}`: unknown loop mode `{}`", name, mode))?,
+            None => LoopMode::Loop,
+        };
+
+        let mut frames = Vec::new();
+        for frame in value
+            .get("frames")
+            .and_then(Value::as_array)
+            .ok_or_else(|| format!("animation `{}`: missing `frames`", name))?
+        {
+            let tile = frame
+                .get("tile")
+                .and_then(Value::as_array)
+                .filter(|x| x.len() == 2)
+                .and_then(|x| Some([x[0].as_u64()? as usize, x[1].as_u64()? as usize]))
+                .ok_or_else(|| format!("animation `{}`: frame needs `tile: [row, col]`", name))?;
+            let duration = frame
+                .get("duration")
+                .and_then(Value::as_f64)
+                .filter(|x| *x > 0.0)
+                .ok_or_else(|| {
+                    format!("animation `{}`: frame needs a positive `duration`", name)
+                })?;
+            frames.push(AnimationFrame { tile, duration });
+        }
+        if frames.is_empty() {
+            return Err(format!("animation `{}` has no frames", name));
+        }
+
+        Ok(Self::new(name, frames, loop_mode))
     }
 
     pub fn get_animation(&self) -> Option<&[usize; 2]> {
-        self.animations.get(self.animation_idx)
+        self.frames.get(self.animation_idx).map(|x| &x.tile)
+    }
+
+    /// Scales playback speed, e.g. to speed up a walk cycle with velocity.
+    /// A speed of zero holds the current frame.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.max(0.0);
     }
 
     pub fn play(&mut self) {
-        self.state = AnimationState::RUNNING;
+        if self.state == AnimationState::Idle {
+            self.state = AnimationState::Running;
+        }
+    }
+
+    pub fn progress(&self) -> AnimationProgress {
+        AnimationProgress {
+            frame: self.animation_idx,
+            time: self.animation_lt,
+            reverse: self.reverse,
+            speed: self.speed,
+            state: self.state,
+        }
+    }
+
+    /// Carries on from `progress`. A frame past the end, e.g. after the
+    /// clip was edited, starts the clip over instead.
+    pub fn set_progress(&mut self, progress: &AnimationProgress) {
+        if progress.frame >= self.frames.len() {
+            self.stop();
+            self.play();
+            return;
+        }
+        self.animation_idx = progress.frame;
+        self.animation_lt = progress.time;
+        self.reverse = progress.reverse;
+        self.speed = progress.speed.max(0.0);
+        self.state = progress.state;
     }
 
     pub fn stop(&mut self) {
-        self.state = AnimationState::IDLE;
+        self.state = AnimationState::Idle;
         self.animation_idx = 0;
+        self.animation_lt = 0.0;
+        self.reverse = false;
     }
 
-    pub fn update(&mut self, dt: f64) {
+    /// Advances the clip and returns `true` on the tick a `once` clip
+    /// finishes.
+    pub fn update(&mut self, dt: f64) -> bool {
         match self.state {
-            AnimationState::RUNNING => {
-                let t: f64 = if self.animation_interval > 0.0 {
-                    self.animation_interval
-                } else {
-                    1.0 / self.animations.len() as f64
-                };
+            AnimationState::Running => {
+                self.animation_lt += dt * self.speed;
+
+                while self.animation_lt >= self.frames[self.animation_idx].duration {
+                    self.animation_lt -= self.frames[self.animation_idx].duration;
 
-                self.animation_lt += dt;
+                    if !self.next_frame() {
+                        self.animation_lt = 0.0;
+                        self.state = AnimationState::Finished;
+                        return true;
+                    }
+                }
+                false
+            }
+            AnimationState::Idle | AnimationState::Finished => false,
+        }
+    }
 
-                if self.animation_lt >= t {
-                    self.animation_idx = (self.animation_idx + 1) % self.animations.len();
-                    self.animation_lt -= t;
+    fn next_frame(&mut self) -> bool {
+        let last = self.frames.len() - 1;
+        match self.loop_mode {
+            LoopMode::Loop => self.animation_idx = (self.animation_idx + 1) % self.frames.len(),
+            LoopMode::Once => {
+                if self.animation_idx == last {
+                    return false;
+                }
+                self.animation_idx += 1;
+            }
+            LoopMode::PingPong => {
+                if last == 0 {
+                    return true;
+                }
+                if self.animation_idx == last {
+                    self.reverse = true;
+                } else if self.animation_idx == 0 {
+                    self.reverse = false;
                 }
+                if self.reverse {
+                    self.animation_idx -= 1;
+                } else {
+                    self.animation_idx += 1;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Tracks the active clip and which clips it may switch to. States without
+/// explicit transitions may switch to any clip; `next` names the clip a
+/// finished one-shot clip hands over to.
+#[derive(Default, Clone)]
+pub struct AnimationStateMachine {
+    current: Option<String>,
+    transitions: HashMap<String, Vec<String>>,
+    next: HashMap<String, String>,
+}
+
+impl AnimationStateMachine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the optional `transitions` and `next` keys of a clip definition.
+    pub fn add_state_json(&mut self, name: &str, value: &Value) -> Result<(), String> {
+        if let Some(targets) = value.get("transitions") {
+            let targets = targets
+                .as_array()
+                .and_then(|x| {
+                    x.iter()
+                        .map(|t| t.as_str().map(String::from))
+                        .collect::<Option<Vec<String>>>()
+                })
+                .ok_or_else(|| {
+                    format!("animation `{}`: `transitions` must list clip names", name)
+                })?;
+            self.transitions.insert(name.to_string(), targets);
+        }
+        if let Some(next) = value.get("next") {
+            let next = next
+                .as_str()
+                .ok_or_else(|| format!("animation `{}`: `next` must be a clip name", name))?;
+            self.next.insert(name.to_string(), next.to_string());
+        }
+        Ok(())
+    }
+
+    /// Checks that every transition target names a known clip.
+    pub fn validate(&self, clips: &[SpriteAnimation]) -> Result<(), String> {
+        let known = |name: &String| clips.iter().any(|x| &x.name == name);
+        for (from, targets) in &self.transitions {
+            if let Some(to) = targets.iter().find(|x| !known(x)) {
+                return Err(format!("animation `{}`: unknown transition `{}`", from, to));
+            }
+        }
+        for (from, to) in &self.next {
+            if !known(to) {
+                return Err(format!("animation `{}`: unknown next clip `{}`", from, to));
             }
-            AnimationState::IDLE => {}
         }
+        Ok(())
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    pub fn next(&self, name: &str) -> Option<&str> {
+        self.next.get(name).map(String::as_str)
+    }
+
+    pub fn can_transition(&self, to: &str) -> bool {
+        match &self.current {
+            Some(from) if from == to => true,
+            Some(from) => self
+                .transitions
+                .get(from)
+                .is_none_or(|targets| targets.iter().any(|x| x == to)),
+            None => true,
+        }
+    }
+
+    /// Moves to `to` if allowed. Returns `true` only when the state actually
+    /// changed, which is when the new clip should restart.
+    pub fn transition(&mut self, to: &str) -> bool {
+        if self.current.as_deref() == Some(to) || !self.can_transition(to) {
+            return false;
+        }
+        self.current = Some(to.to_string());
+        true
+    }
+
+    /// Switches to `to` regardless of the transition table, e.g. on respawn.
+    pub fn force(&mut self, to: &str) {
+        self.current = Some(to.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_clips() {
+        let clip = SpriteAnimation::from_json(
+            "walk",
+            &json!({
+                "loop": "ping_pong",
+                "frames": [
+                    { "tile": [0, 1], "duration": 0.1 },
+                    { "tile": [0, 2], "duration": 0.2 }
+                ]
+            }),
+        )
+        .unwrap();
+        assert_eq!(clip.loop_mode, LoopMode::PingPong);
+        assert_eq!(clip.frames.len(), 2);
+        assert_eq!(clip.get_animation(), Some(&[0, 1]));
+
+        let clip = SpriteAnimation::from_json(
+            "idle",
+            &json!({ "frames": [{ "tile": [0, 0], "duration": 1.0 }] }),
+        )
+        .unwrap();
+        assert_eq!(clip.loop_mode, LoopMode::Loop);
+    }
+
+    #[test]
+    fn rejects_bad_clips() {
+        let frame = json!({ "tile": [0, 0], "duration": 1.0 });
+        let bad = [
+            json!({ "loop": "bounce", "frames": [frame] }),
+            json!({ "frames": [] }),
+            json!({}),
+            json!({ "frames": [{ "tile": [0], "duration": 1.0 }] }),
+            json!({ "frames": [{ "tile": [0, 0], "duration": 0.0 }] }),
+        ];
+        for value in &bad {
+            assert!(SpriteAnimation::from_json("x", value).is_err(), "{}", value);
+        }
+    }
+
+    fn clip(frames: usize, loop_mode: LoopMode) -> SpriteAnimation {
+        let frames = (0..frames)
+            .map(|x| AnimationFrame {
+                tile: [0, x],
+                duration: 1.0,
+            })
+            .collect();
+        let mut clip = SpriteAnimation::new("clip", frames, loop_mode);
+        clip.play();
+        clip
+    }
+
+    fn columns(clip: &mut SpriteAnimation, steps: usize) -> Vec<usize> {
+        (0..steps)
+            .map(|_| {
+                clip.update(1.0);
+                clip.get_animation().unwrap()[1]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ping_pong_turns_at_both_ends() {
+        let mut three = clip(3, LoopMode::PingPong);
+        assert_eq!(columns(&mut three, 6), vec![1, 2, 1, 0, 1, 2]);
+
+        let mut single = clip(1, LoopMode::PingPong);
+        assert_eq!(columns(&mut single, 3), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn loop_wraps_around() {
+        let mut clip = clip(3, LoopMode::Loop);
+        assert_eq!(columns(&mut clip, 4), vec![1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn once_finishes_on_its_last_frame() {
+        let mut clip = clip(2, LoopMode::Once);
+        assert!(!clip.update(1.0));
+        assert!(clip.update(1.0));
+        assert!(!clip.update(1.0));
+        assert_eq!(clip.get_animation(), Some(&[0, 1]));
+    }
+
+    #[test]
+    fn speed_scales_playback() {
+        let mut clip = clip(4, LoopMode::Loop);
+        clip.set_speed(2.0);
+        clip.update(1.0);
+        assert_eq!(clip.get_animation(), Some(&[0, 2]));
+        clip.set_speed(0.0);
+        clip.update(5.0);
+        assert_eq!(clip.get_animation(), Some(&[0, 2]));
+    }
+
+    #[test]
+    fn transitions_follow_the_table() {
+        let mut machine = AnimationStateMachine::new();
+        machine
+            .add_state_json("jump", &json!({ "transitions": ["idle"] }))
+            .unwrap();
+        machine
+            .add_state_json("land", &json!({ "next": "idle" }))
+            .unwrap();
+        let clips: Vec<SpriteAnimation> = ["idle", "jump", "land"]
+            .iter()
+            .map(|name| SpriteAnimation::new(name, Vec::new(), LoopMode::Loop))
+            .collect();
+        machine.validate(&clips).unwrap();
+
+        assert!(machine.transition("jump"));
+        assert!(!machine.transition("jump"));
+        assert!(!machine.transition("land"));
+        assert_eq!(machine.current(), Some("jump"));
+        assert!(machine.transition("idle"));
+        machine.force("land");
+        assert_eq!(machine.next("land"), Some("idle"));
+
+        machine
+            .add_state_json("idle", &json!({ "transitions": ["run"] }))
+            .unwrap();
+        assert!(machine.validate(&clips).is_err());
+    }
+
+    #[test]
+    fn progress_round_trips() {
+        let mut played = clip(3, LoopMode::PingPong);
+        played.set_speed(1.5);
+        played.update(2.5);
+        let json = played.progress().to_json();
+        let progress = AnimationProgress::from_json(&json).unwrap();
+        assert_eq!(progress, played.progress());
+
+        let mut restored = clip(3, LoopMode::PingPong);
+        restored.set_progress(&progress);
+        assert_eq!(restored.get_animation(), played.get_animation());
+        played.update(1.0);
+        restored.update(1.0);
+        assert_eq!(restored.get_animation(), played.get_animation());
+    }
+
+    #[test]
+    fn progress_past_the_last_frame_restarts() {
+        let mut long = clip(4, LoopMode::Loop);
+        long.update(3.0);
+        let mut short = clip(2, LoopMode::Loop);
+        short.update(1.0);
+        short.set_progress(&long.progress());
+        assert_eq!(short.get_animation(), Some(&[0, 0]));
+        assert!(AnimationProgress::from_json(&json!({ "frame": 0 })).is_err());
     }
 }
@@ -0,0 +1,432 @@
+use find_folder::Search;
+use piston_window::{
+    Filter, Flip, G2dTexture, G2dTextureContext, Glyphs, Texture, TextureSettings,
+};
+use serde_json::{from_reader, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::atlas::Atlas;
+
+#[derive(Debug)]
+pub enum AssetError {
+    NotFound(PathBuf),
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, String),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetError::NotFound(path) => write!(f, "asset not found: {}", path.display()),
+            AssetError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            AssetError::Parse(path, e) => write!(f, "failed to parse {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+impl AssetError {
+    pub fn from_io(path: &Path, e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => AssetError::NotFound(path.to_path_buf()),
+            _ => AssetError::Io(path.to_path_buf(), e),
+        }
+    }
+}
+
+/// Reads and parses a JSON file without caching it.
+pub fn read_json(path: &Path) -> Result<Value, AssetError> {
+    let file = File::open(path).map_err(|e| AssetError::from_io(path, e))?;
+    from_reader(file).map_err(|e| AssetError::Parse(path.to_path_buf(), e.to_string()))
+}
+
+/// Lightweight reference to a cached asset. Handles stay valid until the
+/// asset is unloaded; looking up an unloaded handle returns `None`.
+pub struct Handle<T> {
+    index: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+/// `Global` assets live for the whole run, `Level` assets are dropped by
+/// `AssetManager::unload_level`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AssetScope {
+    Global,
+    Level,
+}
+
+struct Entry<T> {
+    path: PathBuf,
+    value: Rc<T>,
+    scope: AssetScope,
+}
+
+struct Cache<T> {
+    entries: Vec<Option<Entry<T>>>,
+    paths: HashMap<PathBuf, usize>,
+}
+
+impl<T> Cache<T> {
+    fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            paths: HashMap::new(),
+        }
+    }
+
+    fn load<F>(
+        &mut self,
+        path: PathBuf,
+        scope: AssetScope,
+        load: F,
+    ) -> Result<Handle<T>, AssetError>
+    where
+        F: FnOnce(&Path) -> Result<T, AssetError>,
+    {
+        if let Some(&index) = self.paths.get(&path) {
+            if let Some(entry) = self.entries[index].as_mut() {
+                if scope == AssetScope::Global {
+                    entry.scope = AssetScope::Global;
+                }
+            }
+            return Ok(Self::handle(index));
+        }
+
+        let value = load(&path)?;
+        // Slots are never reused so a stale handle can't alias a newer asset.
+        let index = self.entries.len();
+        self.paths.insert(path.clone(), index);
+        self.entries.push(Some(Entry {
+            path,
+            value: Rc::new(value),
+            scope,
+        }));
+        Ok(Self::handle(index))
+    }
+
+    /// Re-reads an already cached asset in place so existing handles see the
+    /// new value. Returns `Ok(false)` if the path isn't cached.
+    fn reload<F>(&mut self, path: &Path, load: F) -> Result<bool, AssetError>
+    where
+        F: FnOnce(&Path) -> Result<T, AssetError>,
+    {
+        let index = match self.paths.get(path) {
+            Some(&index) => index,
+            None => return Ok(false),
+        };
+        let value = load(path)?;
+        if let Some(entry) = self.entries[index].as_mut() {
+            entry.value = Rc::new(value);
+        }
+        Ok(true)
+    }
+
+    /// Rebuilds every cached asset `stale` picks out from its old value.
+    /// Returns whether there were any.
+    fn reload_where<P, F>(&mut self, stale: P, mut load: F) -> Result<bool, AssetError>
+    where
+        P: Fn(&T) -> bool,
+        F: FnMut(&T) -> Result<T, AssetError>,
+    {
+        let mut reloaded = false;
+        for entry in self.entries.iter_mut().flatten() {
+            if stale(&entry.value) {
+                entry.value = Rc::new(load(&entry.value)?);
+                reloaded = true;
+            }
+        }
+        Ok(reloaded)
+    }
+
+    fn get(&self, handle: Handle<T>) -> Option<Rc<T>> {
+        self.entries
+            .get(handle.index)
+            .and_then(Option::as_ref)
+            .map(|x| x.value.clone())
+    }
+
+    fn unload(&mut self, scope: AssetScope, keep: &[PathBuf]) {
+        for slot in self.entries.iter_mut() {
+            if slot
+                .as_ref()
+                .is_some_and(|x| x.scope == scope && !keep.contains(&x.path))
+            {
+                if let Some(entry) = slot.take() {
+                    self.paths.remove(&entry.path);
+                }
+            }
+        }
+    }
+
+    fn handle(index: usize) -> Handle<T> {
+        Handle {
+            index,
+            _marker: PhantomData,
+        }
+    }
+}
+
+pub type Font = RefCell<Glyphs>;
+
+/// Loads assets from the `assets` folder once and hands out handles to the
+/// cached copies.
+pub struct AssetManager {
+    root: PathBuf,
+    textures: Cache<G2dTexture>,
+    atlases: Cache<Atlas>,
+    maps: Cache<Value>,
+    tilesets: Cache<Value>,
+    fonts: Cache<Font>,
+    sounds: Cache<PathBuf>,
+}
+
+impl AssetManager {
+    pub fn new() -> Result<Self, AssetError> {
+        let root = Search::Parents(1)
+            .for_folder("assets")
+            .map_err(|_| AssetError::NotFound(PathBuf::from("assets")))?;
+        Ok(Self {
+            root,
+            textures: Cache::new(),
+            atlases: Cache::new(),
+            maps: Cache::new(),
+            tilesets: Cache::new(),
+            fonts: Cache::new(),
+            sounds: Cache::new(),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    pub fn load_texture(
+        &mut self,
+        context: &mut G2dTextureContext,
+        name: &str,
+        scope: AssetScope,
+    ) -> Result<Handle<G2dTexture>, AssetError> {
+        let path = self.path(name);
+        self.textures
+            .load(path, scope, |path| Self::read_texture(context, path))
+    }
+
+    fn read_texture(
+        context: &mut G2dTextureContext,
+        path: &Path,
+    ) -> Result<G2dTexture, AssetError> {
+        if !path.exists() {
+            return Err(AssetError::NotFound(path.to_path_buf()));
+        }
+        let mut texture_settings = TextureSettings::new();
+        texture_settings.set_mag(Filter::Nearest);
+        Texture::from_path(context, path, Flip::None, &texture_settings)
+            .map_err(|e| AssetError::Parse(path.to_path_buf(), e.to_string()))
+    }
+
+    pub fn texture(&self, handle: Handle<G2dTexture>) -> Option<Rc<G2dTexture>> {
+        self.textures.get(handle)
+    }
+
+    /// Packs `images` into one texture named `name`. Asking again with a
+    /// different list of images repacks the atlas in place.
+    pub fn load_atlas(
+        &mut self,
+        context: &mut G2dTextureContext,
+        name: &str,
+        images: &[String],
+        scope: AssetScope,
+    ) -> Result<Handle<Atlas>, AssetError> {
+        let path = self.path(name);
+        let root = self.root.clone();
+        let handle = self.atlases.load(path.clone(), scope, |_| {
+            Atlas::build(context, &root, images)
+        })?;
+        if self.atlas(handle).is_some_and(|x| x.images() != images) {
+            self.atlases
+                .reload(&path, |_| Atlas::build(context, &root, images))?;
+        }
+        Ok(handle)
+    }
+
+    pub fn atlas(&self, handle: Handle<Atlas>) -> Option<Rc<Atlas>> {
+        self.atlases.get(handle)
+    }
+
+    pub fn load_map(&mut self, name: &str, scope: AssetScope) -> Result<Handle<Value>, AssetError> {
+        let path = self.path(name);
+        self.maps.load(path, scope, read_json)
+    }
+
+    pub fn map(&self, handle: Handle<Value>) -> Option<Rc<Value>> {
+        self.maps.get(handle)
+    }
+
+    pub fn load_tileset(
+        &mut self,
+        name: &str,
+        scope: AssetScope,
+    ) -> Result<Handle<Value>, AssetError> {
+        let path = self.path(name);
+        self.tilesets.load(path, scope, read_json)
+    }
+
+    pub fn tileset(&self, handle: Handle<Value>) -> Option<Rc<Value>> {
+        self.tilesets.get(handle)
+    }
+
+    pub fn load_font(
+        &mut self,
+        context: G2dTextureContext,
+        name: &str,
+        scope: AssetScope,
+    ) -> Result<Handle<Font>, AssetError> {
+        let path = self.path(name);
+        self.fonts.load(path, scope, |path| {
+            if !path.exists() {
+                return Err(AssetError::NotFound(path.to_path_buf()));
+            }
+            Glyphs::new(path, context, TextureSettings::new())
+                .map(RefCell::new)
+                .map_err(|e| AssetError::Io(path.to_path_buf(), e))
+        })
+    }
+
+    pub fn font(&self, handle: Handle<Font>) -> Option<Rc<Font>> {
+        self.fonts.get(handle)
+    }
+
+    /// Sounds are streamed by the audio backend, so only the checked path is
+    /// cached.
+    pub fn load_sound(
+        &mut self,
+        name: &str,
+        scope: AssetScope,
+    ) -> Result<Handle<PathBuf>, AssetError> {
+        let path = self.path(name);
+        self.sounds.load(path, scope, |path| {
+            if path.is_file() {
+                Ok(path.to_path_buf())
+            } else {
+                Err(AssetError::NotFound(path.to_path_buf()))
+            }
+        })
+    }
+
+    pub fn sound(&self, handle: Handle<PathBuf>) -> Option<Rc<PathBuf>> {
+        self.sounds.get(handle)
+    }
+
+    /// Re-reads a cached texture, map or tileset from disk, and repacks the
+    /// atlases it is part of. Returns `Ok(false)` when nothing cached uses
+    /// `path`.
+    pub fn reload(
+        &mut self,
+        context: &mut G2dTextureContext,
+        path: &Path,
+    ) -> Result<bool, AssetError> {
+        let texture = self
+            .textures
+            .reload(path, |path| Self::read_texture(context, path))?;
+        let root = &self.root;
+        let atlas = self.atlases.reload_where(
+            |x| x.is_packed(path),
+            |x| Atlas::build(context, root, x.images()),
+        )?;
+        let map = self.maps.reload(path, read_json)?;
+        let tileset = self.tilesets.reload(path, read_json)?;
+        Ok(texture || atlas || map || tileset)
+    }
+
+    /// Drops every asset loaded with `AssetScope::Level` except the files
+    /// named in `keep`, e.g. those of the level being switched to.
+    pub fn unload_level(&mut self, keep: &[String]) {
+        let keep: Vec<PathBuf> = keep.iter().map(|x| self.path(x)).collect();
+        self.textures.unload(AssetScope::Level, &keep);
+        self.atlases.unload(AssetScope::Level, &keep);
+        self.maps.unload(AssetScope::Level, &keep);
+        self.tilesets.unload(AssetScope::Level, &keep);
+        self.fonts.unload(AssetScope::Level, &keep);
+        self.sounds.unload(AssetScope::Level, &keep);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(cache: &mut Cache<String>, name: &str, scope: AssetScope) -> Handle<String> {
+        cache
+            .load(PathBuf::from(name), scope, |x| Ok(x.display().to_string()))
+            .unwrap()
+    }
+
+    #[test]
+    fn loading_twice_returns_the_same_handle() {
+        let mut cache = Cache::new();
+        let first = load(&mut cache, "a.json", AssetScope::Level);
+        let second = cache
+            .load(PathBuf::from("a.json"), AssetScope::Level, |_| {
+                panic!("a cached asset was read again")
+            })
+            .unwrap();
+        assert_eq!(first, second);
+        assert_eq!(cache.get(second).unwrap().as_str(), "a.json");
+    }
+
+    #[test]
+    fn unloading_a_scope_frees_its_entries() {
+        let mut cache = Cache::new();
+        let global = load(&mut cache, "font.ttf", AssetScope::Global);
+        let level = load(&mut cache, "map.tmj", AssetScope::Level);
+        let kept = load(&mut cache, "tiles.tsj", AssetScope::Level);
+        cache.unload(AssetScope::Level, &[PathBuf::from("tiles.tsj")]);
+        assert!(cache.get(global).is_some());
+        assert!(cache.get(level).is_none());
+        assert!(cache.get(kept).is_some());
+        assert!(!cache.paths.contains_key(Path::new("map.tmj")));
+    }
+
+    #[test]
+    fn stale_handle_returns_none() {
+        let mut cache = Cache::new();
+        let stale = load(&mut cache, "map.tmj", AssetScope::Level);
+        cache.unload(AssetScope::Level, &[]);
+        let fresh = load(&mut cache, "map.tmj", AssetScope::Level);
+        assert_ne!(stale, fresh);
+        assert!(cache.get(stale).is_none());
+        assert!(cache.get(fresh).is_some());
+    }
+}
@@ -0,0 +1,156 @@
+use image::{imageops, RgbaImage};
+use piston_window::{Filter, G2dTexture, G2dTextureContext, Texture, TextureSettings};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::assets::AssetError;
+
+/// Narrowest atlas texture. Wider images widen the atlas to fit.
+const MIN_WIDTH: u32 = 1024;
+/// Transparent gap around every image so neighbours never bleed in.
+const PADDING: u32 = 1;
+
+/// Several images packed into one texture, so sprites cut from different
+/// sheets all draw from the same texture. Images are looked up by the file
+/// name they were packed from.
+pub struct Atlas {
+    texture: Rc<G2dTexture>,
+    images: Vec<String>,
+    /// `[x, y, w, h]` of each image in the texture, in `images` order.
+    regions: Vec<[f64; 4]>,
+    sources: Vec<PathBuf>,
+}
+
+impl Atlas {
+    /// Reads `images` from `root` and packs them into a new texture.
+    pub fn build(
+        context: &mut G2dTextureContext,
+        root: &Path,
+        images: &[String],
+    ) -> Result<Self, AssetError> {
+        let sources: Vec<PathBuf> = images.iter().map(|x| root.join(x)).collect();
+        let pictures = sources
+            .iter()
+            .map(|path| {
+                image::open(path)
+                    .map(|x| x.to_rgba8())
+                    .map_err(|e| match e {
+                        image::ImageError::IoError(e) => AssetError::from_io(path, e),
+                        e => AssetError::Parse(path.clone(), e.to_string()),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let sizes: Vec<[u32; 2]> = pictures.iter().map(|x| [x.width(), x.height()]).collect();
+        let (positions, size) = pack(&sizes);
+        let mut canvas = RgbaImage::new(size[0].max(1), size[1].max(1));
+        for (picture, position) in pictures.iter().zip(&positions) {
+            imageops::replace(&mut canvas, picture, position[0] as i64, position[1] as i64);
+        }
+
+        let mut settings = TextureSettings::new();
+        settings.set_mag(Filter::Nearest);
+        let texture = Texture::from_image(context, &canvas, &settings)
+            .map_err(|e| AssetError::Parse(root.to_path_buf(), e.to_string()))?;
+        let regions = positions
+            .iter()
+            .zip(&sizes)
+            .map(|(at, size)| [at[0] as f64, at[1] as f64, size[0] as f64, size[1] as f64])
+            .collect();
+        Ok(Self {
+            texture: Rc::new(texture),
+            images: images.to_vec(),
+            regions,
+            sources,
+        })
+    }
+
+    pub fn texture(&self) -> Rc<G2dTexture> {
+        self.texture.clone()
+    }
+
+    /// Where `image` was packed, as `[x, y, w, h]`.
+    pub fn region(&self, image: &str) -> Option<[f64; 4]> {
+        let index = self.images.iter().position(|x| x == image)?;
+        Some(self.regions[index])
+    }
+
+    /// The images packed, in the order they were asked for.
+    pub fn images(&self) -> &[String] {
+        &self.images
+    }
+
+    /// Whether the file at `path` is one of the packed images.
+    pub fn is_packed(&self, path: &Path) -> bool {
+        self.sources.iter().any(|x| x == path)
+    }
+}
+
+/// Shelf packing: images go left to right, tallest first, starting a new
+/// row when the current one is full. Returns where each image goes, in the
+/// order given, and the size of the texture they fit in.
+fn pack(sizes: &[[u32; 2]]) -> (Vec<[u32; 2]>, [u32; 2]) {
+    let width = sizes
+        .iter()
+        .map(|x| x[0] + PADDING * 2)
+        .fold(MIN_WIDTH, u32::max);
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|i| std::cmp::Reverse(sizes[*i][1]));
+
+    let mut positions = vec![[0, 0]; sizes.len()];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for i in order {
+        let [w, h] = [sizes[i][0] + PADDING * 2, sizes[i][1] + PADDING * 2];
+        if x + w > width {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+        positions[i] = [x + PADDING, y + PADDING];
+        x += w;
+        row_height = row_height.max(h);
+    }
+    (positions, [width, y + row_height])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: [u32; 4], b: [u32; 4]) -> bool {
+        a[0] < b[0] + b[2] && b[0] < a[0] + a[2] && a[1] < b[1] + b[3] && b[1] < a[1] + a[3]
+    }
+
+    #[test]
+    fn packs_without_overlap_inside_the_texture() {
+        let sizes = [[528, 448], [808, 128], [96, 16], [16, 16]];
+        let (positions, size) = pack(&sizes);
+        let rects: Vec<[u32; 4]> = positions
+            .iter()
+            .zip(&sizes)
+            .map(|(at, size)| [at[0], at[1], size[0], size[1]])
+            .collect();
+        for (i, a) in rects.iter().enumerate() {
+            assert!(a[0] + a[2] + PADDING <= size[0] && a[1] + a[3] + PADDING <= size[1]);
+            for b in &rects[i + 1..] {
+                assert!(!overlaps(*a, *b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn widens_for_wide_images() {
+        let (positions, size) = pack(&[[2000, 10], [10, 10]]);
+        assert_eq!(size[0], 2000 + PADDING * 2);
+        assert_eq!(positions[0], [PADDING, PADDING]);
+        assert_eq!(positions[1][1], 10 + PADDING * 3);
+    }
+
+    #[test]
+    fn tallest_images_start_the_first_row() {
+        let (positions, size) = pack(&[[10, 5], [10, 20]]);
+        assert_eq!(positions[1], [PADDING, PADDING]);
+        assert_eq!(positions[0], [10 + PADDING * 3, PADDING]);
+        assert_eq!(size, [MIN_WIDTH, 20 + PADDING * 2]);
+    }
+}
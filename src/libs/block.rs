@@ -0,0 +1,259 @@
+use graphics::math::Matrix2d;
+use graphics::{Graphics, Transformed};
+use piston_window::ImageSize;
+use serde_json::{json, Value};
+
+use super::{
+    entities::MapObject,
+    item::ItemKind,
+    player::PlayerForm,
+    snapshot,
+    sprites_manager::{AnimationSnapshot, SpriteDefinitions, SpriteManager},
+    spritesheet::SpriteSheet,
+    transform::{Rect, Transform},
+};
+
+/// How long a block ignores further hits after being hit.
+const HIT_COOLDOWN: f64 = 0.25;
+const MULTI_COIN_TIME: f64 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockContents {
+    Coin,
+    MultiCoin,
+    PowerUp,
+    Star,
+    OneUp,
+}
+
+impl BlockContents {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "coin" => Some(BlockContents::Coin),
+            "coins" => Some(BlockContents::MultiCoin),
+            "powerup" => Some(BlockContents::PowerUp),
+            "star" => Some(BlockContents::Star),
+            "1up" => Some(BlockContents::OneUp),
+            _ => None,
+        }
+    }
+
+    /// The item that comes out for a player in `form`. Power-up blocks give
+    /// small players a mushroom and bigger ones a fire flower.
+    pub fn item(&self, form: PlayerForm) -> Option<ItemKind> {
+        match self {
+            BlockContents::Coin | BlockContents::MultiCoin => None,
+            BlockContents::PowerUp if form == PlayerForm::Small => Some(ItemKind::Mushroom),
+            BlockContents::PowerUp => Some(ItemKind::FireFlower),
+            BlockContents::Star => Some(ItemKind::Star),
+            BlockContents::OneUp => Some(ItemKind::OneUp),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockStyle {
+    Question,
+    Brick,
+    Hidden,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockState {
+    Full,
+    /// A multi-coin block that has been hit, with the time left before its
+    /// next hit is the last.
+    Emptying(f64),
+    Used,
+}
+
+/// A block snapshot read back and checked.
+pub struct BlockSnapshot {
+    pub id: u64,
+    state: BlockState,
+    cooldown: f64,
+    animation: Option<AnimationSnapshot>,
+}
+
+impl BlockSnapshot {
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let id = snapshot::number(json, "id")? as u64;
+        let state = match snapshot::text(json, "state")? {
+            "full" => BlockState::Full,
+            "emptying" => BlockState::Emptying(snapshot::number(json, "time")?),
+            "used" => BlockState::Used,
+            state => return Err(format!("block {}: unknown state `{}`", id, state)),
+        };
+        Ok(Self {
+            id,
+            state,
+            cooldown: snapshot::number(json, "cooldown")?,
+            animation: AnimationSnapshot::read(json)?,
+        })
+    }
+}
+
+/// A `block` object that gives out its `contents` when hit from below.
+/// Question blocks are animated; `style: brick` and `style: hidden` blocks
+/// are left to the map image until they are used up.
+pub struct Block<I: ImageSize> {
+    pub id: u64,
+    sprites: SpriteManager<I>,
+    transform: Transform,
+    contents: BlockContents,
+    style: BlockStyle,
+    state: BlockState,
+    multi_coin_time: f64,
+    cooldown: f64,
+}
+
+impl<I: ImageSize> Block<I> {
+    pub fn from_object(
+        object: &MapObject,
+        sprite_sheet: SpriteSheet<I>,
+        definitions: SpriteDefinitions,
+        palette: &str,
+    ) -> Result<Self, String> {
+        let contents = object
+            .property("contents")
+            .and_then(Value::as_str)
+            .unwrap_or("coin");
+        let contents = BlockContents::from_name(contents)
+            .ok_or_else(|| format!("block {}: unknown contents `{}`", object.id, contents))?;
+        let style = match object.property("style").and_then(Value::as_str) {
+            None | Some("question") => BlockStyle::Question,
+            Some("brick") => BlockStyle::Brick,
+            Some("hidden") => BlockStyle::Hidden,
+            Some(style) => return Err(format!("block {}: unknown style `{}`", object.id, style)),
+        };
+
+        let mut sprites = SpriteManager::new();
+        sprites.set_spritesheet(sprite_sheet);
+        sprites.set_definitions(definitions);
+        sprites.set_current_config(palette);
+        sprites.play_animation("question");
+
+        Ok(Self {
+            id: object.id,
+            sprites,
+            transform: object.transform,
+            contents,
+            style,
+            state: BlockState::Full,
+            multi_coin_time: object
+                .property("time")
+                .and_then(Value::as_f64)
+                .unwrap_or(MULTI_COIN_TIME),
+            cooldown: 0.0,
+        })
+    }
+
+    pub fn get_transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn is_used(&self) -> bool {
+        self.state == BlockState::Used
+    }
+
+    pub fn set_used(&mut self) {
+        self.state = BlockState::Used;
+        self.sprites.force_animation("used");
+    }
+
+    /// Time left on a multi-coin block that has started paying out.
+    pub fn emptying_time(&self) -> Option<f64> {
+        match self.state {
+            BlockState::Emptying(time) => Some(time),
+            _ => None,
+        }
+    }
+
+    /// Picks a multi-coin block up where it was left, e.g. after the
+    /// player warps back to its area.
+    pub fn set_emptying(&mut self, time: f64) {
+        self.state = BlockState::Emptying(time);
+    }
+
+    pub fn snapshot(&self) -> Value {
+        let (state, time) = match self.state {
+            BlockState::Full => ("full", 0.0),
+            BlockState::Emptying(time) => ("emptying", time),
+            BlockState::Used => ("used", 0.0),
+        };
+        json!({
+            "id": self.id,
+            "state": state,
+            "time": time,
+            "cooldown": self.cooldown,
+            "animation": self.sprites.animation().to_json(),
+        })
+    }
+
+    pub fn restore(&mut self, snapshot: &BlockSnapshot) {
+        self.state = snapshot.state;
+        self.cooldown = snapshot.cooldown;
+        match &snapshot.animation {
+            Some(animation) => self.sprites.restore_animation(animation),
+            None if self.is_used() => self.sprites.force_animation("used"),
+            None => self.sprites.force_animation("question"),
+        }
+    }
+
+    /// Hits the block from below and returns what comes out of it. Multi-coin
+    /// blocks keep giving coins until their time runs out.
+    pub fn hit(&mut self) -> Option<BlockContents> {
+        if self.cooldown > 0.0 || self.is_used() {
+            return None;
+        }
+        self.cooldown = HIT_COOLDOWN;
+        match (self.contents, self.state) {
+            (BlockContents::MultiCoin, BlockState::Full) => {
+                self.state = BlockState::Emptying(self.multi_coin_time)
+            }
+            (BlockContents::MultiCoin, BlockState::Emptying(time)) if time > 0.0 => {}
+            _ => self.set_used(),
+        }
+        Some(self.contents)
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        self.cooldown -= dt;
+        if let BlockState::Emptying(time) = self.state {
+            self.state = BlockState::Emptying(time - dt);
+        }
+        self.sprites.update(dt);
+    }
+
+    pub fn draw<B: Graphics<Texture = I>>(&mut self, t: Matrix2d, b: &mut B) {
+        if self.style == BlockStyle::Question || self.is_used() {
+            let (x, y) = (self.transform.x(), self.transform.y());
+            self.sprites.draw(t.trans(x, y), b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_block_snapshots() {
+        let block = BlockSnapshot::from_json(
+            &json!({ "id": 7, "state": "emptying", "time": 2.5, "cooldown": 0.1 }),
+        )
+        .unwrap();
+        assert_eq!(block.id, 7);
+        assert_eq!(block.state, BlockState::Emptying(2.5));
+        assert!(block.animation.is_none());
+
+        let bad = [
+            json!({ "id": 7, "state": "broken", "time": 0.0, "cooldown": 0.0 }),
+            json!({ "id": 7, "state": "emptying", "cooldown": 0.0 }),
+            json!({ "id": 7, "state": "used", "time": 0.0 }),
+        ];
+        for value in &bad {
+            assert!(BlockSnapshot::from_json(value).is_err(), "{}", value);
+        }
+    }
+}
@@ -30,11 +30,43 @@ impl Camera {
         }
     }
 
+    pub fn set_world_size(&mut self, world_width: f64, world_height: f64) {
+        self.world_width = world_width;
+        self.world_height = world_height;
+    }
+
     pub fn follow_player<I: ImageSize>(&mut self, player: &Player<I>) {
         // Calculate the camera's new position based on the player's position
         let target_x = player.get_transform().xw() - self.viewport_width / 2.0;
         let target_y = player.get_transform().yh() - self.viewport_height / 2.0;
+        self.move_to(target_x, target_y);
+    }
+
+    /// Frames both players when they fit on screen. When they don't, `lead`
+    /// stays in view and the other is left at the edge, to be pulled along
+    /// with `pull_into_view`.
+    pub fn follow_players<I: ImageSize>(&mut self, lead: &Player<I>, other: &Player<I>) {
+        let (lead, other) = (lead.get_transform(), other.get_transform());
+        let center = (lead.x().min(other.x()) + lead.xw().max(other.xw())) / 2.0;
+        let target_x = (center - self.viewport_width / 2.0)
+            .max(lead.xw() - self.viewport_width)
+            .min(lead.x());
+        let target_y = lead.yh() - self.viewport_height / 2.0;
+        self.move_to(target_x, target_y);
+    }
+
+    /// Keeps a player who fell behind on screen by dragging them along
+    /// with the view.
+    pub fn pull_into_view<I: ImageSize>(&self, player: &mut Player<I>) {
+        let body = *player.get_transform();
+        let right = self.position.x + self.viewport_width - body.w();
+        let x = body.x().clamp(self.position.x, right.max(self.position.x));
+        if x != body.x() {
+            player.set_position(x, body.y());
+        }
+    }
 
+    fn move_to(&mut self, target_x: f64, target_y: f64) {
         // Adjust the camera's position to stay within the game world boundaries
         if target_x < 0.0 {
             self.position.x = 0.0;
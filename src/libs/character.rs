@@ -0,0 +1,49 @@
+use serde_json::Value;
+use std::path::Path;
+
+use super::assets::{read_json, AssetError};
+
+/// A playable character as listed in `characters.json`: the name shown on
+/// the HUD and the palette block of `player.png` its frames are drawn from.
+#[derive(Debug, Clone)]
+pub struct Character {
+    pub name: String,
+    pub palette: usize,
+}
+
+impl Character {
+    /// Parses `{ "name": "Luigi", "palette": 1 }`.
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let name = value
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or("missing or invalid field `name`")?;
+        let palette = value
+            .get("palette")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| format!("character `{}`: missing or invalid field `palette`", name))?;
+        Ok(Self {
+            name: name.to_string(),
+            palette: palette as usize,
+        })
+    }
+}
+
+/// The characters in order; the first is player one.
+pub fn load_characters(path: &Path) -> Result<Vec<Character>, AssetError> {
+    let error = |e: String| AssetError::Parse(path.to_path_buf(), e);
+    let characters = read_json(path)?
+        .get("characters")
+        .and_then(Value::as_array)
+        .ok_or_else(|| error("missing `characters` array".to_string()))?
+        .iter()
+        .map(Character::from_json)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(error)?;
+    if characters.len() < 2 {
+        return Err(error(
+            "needs a character for each of the two players".to_string(),
+        ));
+    }
+    Ok(characters)
+}
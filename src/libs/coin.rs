@@ -0,0 +1,140 @@
+use graphics::math::Matrix2d;
+use graphics::{Graphics, Transformed};
+use piston_window::ImageSize;
+use serde_json::{json, Value};
+
+use super::{
+    snapshot,
+    sprites_manager::{AnimationSnapshot, SpriteDefinitions, SpriteManager},
+    spritesheet::SpriteSheet,
+    transform::{Rect, Transform},
+};
+
+/// Pixels per second.
+const POP_SPEED: f64 = 210.0;
+/// Pixels per second squared.
+const POP_GRAVITY: f64 = 720.0;
+const POP_TIME: f64 = 0.6;
+
+fn coin_sprites<I: ImageSize>(
+    sprite_sheet: SpriteSheet<I>,
+    definitions: SpriteDefinitions,
+    palette: &str,
+) -> SpriteManager<I> {
+    let mut sprites = SpriteManager::new();
+    sprites.set_spritesheet(sprite_sheet);
+    sprites.set_definitions(definitions);
+    sprites.set_current_config(palette);
+    sprites.play_animation("spin");
+    sprites
+}
+
+/// A coin placed in the map with a `coin` object, collected by touching it.
+pub struct Coin<I: ImageSize> {
+    pub id: u64,
+    sprites: SpriteManager<I>,
+    transform: Transform,
+}
+
+impl<I: ImageSize> Coin<I> {
+    pub fn new(
+        id: u64,
+        sprite_sheet: SpriteSheet<I>,
+        definitions: SpriteDefinitions,
+        palette: &str,
+        object: &Transform,
+    ) -> Self {
+        Self {
+            id,
+            sprites: coin_sprites(sprite_sheet, definitions, palette),
+            transform: *object,
+        }
+    }
+
+    pub fn get_transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    /// Coins spin in step, so one coin's animation stands for all of them.
+    pub fn animation(&self) -> AnimationSnapshot {
+        self.sprites.animation()
+    }
+
+    pub fn set_animation(&mut self, animation: &AnimationSnapshot) {
+        self.sprites.restore_animation(animation);
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        self.sprites.update(dt);
+    }
+
+    pub fn draw<B: Graphics<Texture = I>>(&mut self, t: Matrix2d, b: &mut B) {
+        let (x, y) = (self.transform.x(), self.transform.y());
+        self.sprites.draw(t.trans(x, y), b);
+    }
+}
+
+/// The coin that jumps out of a block when it is hit, then vanishes.
+pub struct CoinPop<I: ImageSize> {
+    sprites: SpriteManager<I>,
+    x: f64,
+    y: f64,
+    velocity: f64,
+    time: f64,
+}
+
+impl<I: ImageSize> CoinPop<I> {
+    /// Starts a coin just above the block at `block`.
+    pub fn new(
+        sprite_sheet: SpriteSheet<I>,
+        definitions: SpriteDefinitions,
+        palette: &str,
+        block: &Transform,
+    ) -> Self {
+        let mut sprites = coin_sprites(sprite_sheet, definitions, palette);
+        sprites.set_animation_speed(3.0);
+        Self {
+            sprites,
+            x: block.x(),
+            y: block.y() - block.h(),
+            velocity: -POP_SPEED,
+            time: POP_TIME,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.time <= 0.0
+    }
+
+    pub fn snapshot(&self) -> Value {
+        json!({
+            "x": self.x,
+            "y": self.y,
+            "velocity": self.velocity,
+            "time": self.time,
+            "animation": self.sprites.animation().to_json(),
+        })
+    }
+
+    pub fn restore(&mut self, json: &Value) -> Result<(), String> {
+        self.x = snapshot::number(json, "x")?;
+        self.y = snapshot::number(json, "y")?;
+        self.velocity = snapshot::number(json, "velocity")?;
+        self.time = snapshot::number(json, "time")?;
+        if let Some(animation) = AnimationSnapshot::read(json)? {
+            self.sprites.restore_animation(&animation);
+        }
+        Ok(())
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        self.velocity += POP_GRAVITY * dt;
+        self.y += self.velocity * dt;
+        self.time -= dt;
+        self.sprites.update(dt);
+    }
+
+    pub fn draw<B: Graphics<Texture = I>>(&mut self, t: Matrix2d, b: &mut B) {
+        self.sprites.draw(t.trans(self.x, self.y), b);
+    }
+}
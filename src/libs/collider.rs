@@ -1,6 +1,6 @@
 use crate::libs::transform::{Rect, Transform};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Side {
     LEFT,
     RIGHT,
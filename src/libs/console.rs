@@ -0,0 +1,183 @@
+use graphics::{Context, Transformed};
+use piston_window::{rectangle, G2d, Glyphs, Key, Text};
+use std::collections::BTreeMap;
+
+const MAX_OUTPUT: usize = 64;
+const MAX_HISTORY: usize = 32;
+const VISIBLE_LINES: usize = 10;
+const LINE_HEIGHT: f64 = 18.0;
+
+/// A console command. It receives the host and the whitespace separated
+/// arguments after the command name and returns the text to print.
+pub type CommandFn<C> = fn(&mut C, &[&str]) -> Result<String, String>;
+
+/// Hosts that own their console, so `help` can list its commands.
+pub trait ConsoleHost: Sized {
+    fn console(&self) -> &Console<Self>;
+}
+
+/// Registers the console's own commands.
+pub fn register_commands<C: ConsoleHost>(console: &mut Console<C>) {
+    console.register("help", "- list commands", |host, _| {
+        Ok(host.console().usage())
+    });
+}
+
+/// Parses a command argument as a number.
+pub fn parse_number(text: &str) -> Result<f64, String> {
+    text.parse()
+        .map_err(|_| format!("`{}` is not a number", text))
+}
+
+struct Command<C> {
+    usage: &'static str,
+    run: CommandFn<C>,
+}
+
+/// Drop-down developer console. Subsystems register commands against the
+/// host type `C`; the host looks commands up and runs them on itself so the
+/// console never needs to borrow it.
+pub struct Console<C> {
+    pub open: bool,
+    input: String,
+    output: Vec<String>,
+    history: Vec<String>,
+    history_idx: Option<usize>,
+    commands: BTreeMap<&'static str, Command<C>>,
+}
+
+impl<C> Console<C> {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            input: String::new(),
+            output: Vec::new(),
+            history: Vec::new(),
+            history_idx: None,
+            commands: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `name`, replacing any command already registered under it.
+    pub fn register(&mut self, name: &'static str, usage: &'static str, run: CommandFn<C>) {
+        self.commands.insert(name, Command { usage, run });
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.input.clear();
+        self.history_idx = None;
+    }
+
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            self.output.push(line.to_string());
+        }
+        if self.output.len() > MAX_OUTPUT {
+            self.output.drain(..self.output.len() - MAX_OUTPUT);
+        }
+    }
+
+    /// One line per command with its usage, for `help`.
+    pub fn usage(&self) -> String {
+        self.commands
+            .iter()
+            .map(|(name, x)| format!("{} {}", name, x.usage))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Appends typed text. The toggle key's character is ignored so opening
+    /// the console doesn't type into it.
+    pub fn text_input(&mut self, text: &str) {
+        self.input
+            .extend(text.chars().filter(|x| *x != '`' && !x.is_control()));
+    }
+
+    /// Handles editing keys and returns the submitted line on Return.
+    pub fn key_input(&mut self, key: Key) -> Option<String> {
+        match key {
+            Key::Backspace => {
+                self.input.pop();
+            }
+            Key::Up if !self.history.is_empty() => {
+                let idx = match self.history_idx {
+                    Some(idx) => idx.saturating_sub(1),
+                    None => self.history.len() - 1,
+                };
+                self.history_idx = Some(idx);
+                self.input = self.history[idx].clone();
+            }
+            Key::Down => match self.history_idx {
+                Some(idx) if idx + 1 < self.history.len() => {
+                    self.history_idx = Some(idx + 1);
+                    self.input = self.history[idx + 1].clone();
+                }
+                _ => {
+                    self.history_idx = None;
+                    self.input.clear();
+                }
+            },
+            Key::Return => {
+                let line = std::mem::take(&mut self.input).trim().to_string();
+                self.history_idx = None;
+                if line.is_empty() {
+                    return None;
+                }
+                if self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                    if self.history.len() > MAX_HISTORY {
+                        self.history.remove(0);
+                    }
+                }
+                return Some(line);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Splits a submitted line into its command and arguments.
+    pub fn parse(&self, line: &str) -> Result<(CommandFn<C>, Vec<String>), String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let command = self
+            .commands
+            .get(name)
+            .ok_or_else(|| format!("unknown command `{}`, try `help`", name))?;
+        Ok((command.run, words.map(String::from).collect()))
+    }
+
+    pub fn draw(&self, glyphs: &mut Glyphs, width: f64, c: Context, g: &mut G2d) {
+        let height = LINE_HEIGHT * (VISIBLE_LINES + 1) as f64 + 8.0;
+        rectangle(
+            [0.0, 0.0, 0.0, 0.85],
+            [0.0, 0.0, width, height],
+            c.transform,
+            g,
+        );
+
+        let text = Text::new_color([0.8, 0.8, 0.8, 1.0], 14);
+        let start = self.output.len().saturating_sub(VISIBLE_LINES);
+        for (i, line) in self.output[start..].iter().enumerate() {
+            text.draw(
+                line,
+                glyphs,
+                &c.draw_state,
+                c.transform.trans(10.0, LINE_HEIGHT * (i + 1) as f64),
+                g,
+            )
+            .ok();
+        }
+
+        Text::new_color([1.0, 1.0, 0.6, 1.0], 14)
+            .draw(
+                &format!("> {}_", self.input),
+                glyphs,
+                &c.draw_state,
+                c.transform.trans(10.0, height - 8.0),
+                g,
+            )
+            .ok();
+    }
+}
@@ -1,6 +1,5 @@
-use piston_window::{ButtonState, ImageSize, Key};
+use crate::libs::input::Action;
 
-use crate::libs::player::{Player, PlayerDirection};
 #[derive(Debug)]
 pub struct Controller {
     pub left: bool,
@@ -23,14 +22,54 @@ impl Controller {
         }
     }
 
-    pub fn keyboard_event(&mut self, key: Key, state: ButtonState) {
-        match key {
-            Key::A | Key::Left => self.left = state == ButtonState::Press,
-            Key::D | Key::Right => self.right = state == ButtonState::Press,
-            Key::Space | Key::Up => self.jump = state == ButtonState::Press,
-            Key::S | Key::Down => self.crouch = state == ButtonState::Press,
-            Key::X => self.shoot = state == ButtonState::Press,
-            Key::LShift | Key::RShift => self.run = state == ButtonState::Press,
+    /// The actions a controller tracks, in the order of their bits.
+    pub const ACTIONS: [Action; 6] = [
+        Action::Left,
+        Action::Right,
+        Action::Down,
+        Action::Jump,
+        Action::Run,
+        Action::Shoot,
+    ];
+
+    /// The held buttons packed one per bit, as snapshots and netplay
+    /// packets carry them.
+    pub fn bits(&self) -> u8 {
+        Self::ACTIONS
+            .iter()
+            .enumerate()
+            .filter(|(_, action)| self.is_held(**action))
+            .fold(0, |bits, (i, _)| bits | 1 << i)
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        let mut controller = Self::new();
+        for (i, action) in Self::ACTIONS.iter().enumerate() {
+            controller.action_event(*action, bits & 1 << i != 0);
+        }
+        controller
+    }
+
+    pub fn is_held(&self, action: Action) -> bool {
+        match action {
+            Action::Left => self.left,
+            Action::Right => self.right,
+            Action::Jump => self.jump,
+            Action::Down => self.crouch,
+            Action::Shoot => self.shoot,
+            Action::Run => self.run,
+            _ => false,
+        }
+    }
+
+    pub fn action_event(&mut self, action: Action, pressed: bool) {
+        match action {
+            Action::Left => self.left = pressed,
+            Action::Right => self.right = pressed,
+            Action::Jump => self.jump = pressed,
+            Action::Down => self.crouch = pressed,
+            Action::Shoot => self.shoot = pressed,
+            Action::Run => self.run = pressed,
             _ => {}
         }
     }
@@ -0,0 +1,285 @@
+use piston_window::ImageSize;
+use serde_json::{json, Value};
+
+use super::{
+    collider::Collision,
+    death::PlayerDeath,
+    player::{Player, PlayerSnapshot},
+    snapshot,
+    transform::{Rect, Transform},
+};
+
+/// Which player something happened to. Player one is the only player
+/// outside co-op games.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerId {
+    One,
+    Two,
+}
+
+/// Player two in a co-op game, with their own lives, score and coins.
+/// While player one leads they die on their own and come back next to
+/// player one.
+pub struct Partner<I: ImageSize> {
+    pub player: Player<I>,
+    pub character: usize,
+    pub lives: u32,
+    pub score: u32,
+    pub coins: u32,
+    pub death: Option<PlayerDeath>,
+}
+
+impl<I: ImageSize> Partner<I> {
+    pub fn new(player: Player<I>, character: usize, lives: u32) -> Self {
+        Self {
+            player,
+            character,
+            lives,
+            score: 0,
+            coins: 0,
+            death: None,
+        }
+    }
+
+    /// On their feet: not dying and not out of lives.
+    pub fn in_play(&self) -> bool {
+        self.lives > 0 && self.death.is_none()
+    }
+
+    /// Their own death. Play goes on around them.
+    pub fn kill(&mut self, fell: bool) {
+        self.player.die();
+        self.death = Some(PlayerDeath::new(fell));
+    }
+
+    /// Takes the life of a death still in progress, e.g. when the level
+    /// restarts before their death is over.
+    pub fn settle_death(&mut self) {
+        if self.death.take().is_some() {
+            self.lives = self.lives.saturating_sub(1);
+        }
+    }
+
+    /// Puts them back next to `lead` after a death, a warp or a level load,
+    /// taking a life for a death still in progress. Without lives left they
+    /// sit the rest of the game out and `false` is returned.
+    pub fn rejoin(&mut self, lead: &Transform) -> bool {
+        self.settle_death();
+        if self.lives == 0 {
+            self.player.set_visible(false);
+            self.player.set_input_enabled(false);
+            return false;
+        }
+        self.player.revive();
+        place_beside(&mut self.player, lead);
+        true
+    }
+
+    pub fn snapshot(&self) -> Value {
+        json!({
+            "character": self.character,
+            "lives": self.lives,
+            "score": self.score,
+            "coins": self.coins,
+            "player": self.player.snapshot(),
+        })
+    }
+}
+
+/// Player two as read back from a snapshot.
+pub struct PartnerSnapshot<I: ImageSize> {
+    pub character: usize,
+    lives: u32,
+    score: u32,
+    coins: u32,
+    player: PlayerSnapshot,
+    /// A player for them when they weren't in the game yet.
+    pub new_player: Option<Player<I>>,
+}
+
+impl<I: ImageSize> PartnerSnapshot<I> {
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        Ok(Self {
+            character: snapshot::number(json, "character")? as usize,
+            lives: snapshot::number(json, "lives")? as u32,
+            score: snapshot::number(json, "score")? as u32,
+            coins: snapshot::number(json, "coins")? as u32,
+            player: PlayerSnapshot::from_json(&json["player"])?,
+            new_player: None,
+        })
+    }
+
+    /// Player two back as the snapshot found them, reusing `player` if they
+    /// are in the game already.
+    pub fn restore(self, player: Option<Player<I>>) -> Option<Partner<I>> {
+        let mut player = player.or(self.new_player)?;
+        player.restore(&self.player);
+        Some(Partner {
+            player,
+            character: self.character,
+            lives: self.lives,
+            score: self.score,
+            coins: self.coins,
+            death: None,
+        })
+    }
+}
+
+/// Who leads outside pipes and the flagpole: player one, or player two
+/// once player one, with `lives` left, is out.
+pub fn default_lead<I: ImageSize>(lives: u32, partner: Option<&Partner<I>>) -> PlayerId {
+    match partner {
+        Some(partner) if lives == 0 && partner.lives > 0 => PlayerId::Two,
+        _ => PlayerId::One,
+    }
+}
+
+/// Whether the game is over: player one, with `lives` left, and player two
+/// are both out of lives.
+pub fn all_out<I: ImageSize>(lives: u32, partner: Option<&Partner<I>>) -> bool {
+    lives == 0 && partner.is_none_or(|x| x.lives == 0)
+}
+
+/// The players in play, player one first. `one_in_play` is false once
+/// player one is out of lives in a co-op game.
+pub fn players_mut<'a, I: ImageSize>(
+    player: &'a mut Player<I>,
+    partner: &'a mut Option<Partner<I>>,
+    one_in_play: bool,
+) -> Vec<(PlayerId, &'a mut Player<I>)> {
+    let mut players = Vec::new();
+    if one_in_play {
+        players.push((PlayerId::One, player));
+    }
+    if let Some(partner) = partner.as_mut().filter(|x| x.in_play()) {
+        players.push((PlayerId::Two, &mut partner.player));
+    }
+    players
+}
+
+/// The ids of the players in play, player one first.
+pub fn player_ids<I: ImageSize>(partner: Option<&Partner<I>>, one_in_play: bool) -> Vec<PlayerId> {
+    let mut ids = Vec::new();
+    if one_in_play {
+        ids.push(PlayerId::One);
+    }
+    if partner.is_some_and(|x| x.in_play()) {
+        ids.push(PlayerId::Two);
+    }
+    ids
+}
+
+/// The player `id` stands for. Player one when there is no player two.
+pub fn player_mut<'a, I: ImageSize>(
+    id: PlayerId,
+    player: &'a mut Player<I>,
+    partner: &'a mut Option<Partner<I>>,
+) -> &'a mut Player<I> {
+    match (id, partner.as_mut()) {
+        (PlayerId::Two, Some(partner)) => &mut partner.player,
+        _ => player,
+    }
+}
+
+/// Stands `player` just behind `lead`, on the same ground.
+pub fn place_beside<I: ImageSize>(player: &mut Player<I>, lead: &Transform) {
+    let body = *player.get_transform();
+    player.set_position((lead.x() - body.w()).max(0.0), lead.yh() - body.h());
+}
+
+/// A player landing on the other's head bounces off, like off an enemy.
+pub fn bounce_players<I: ImageSize>(one: &mut Player<I>, two: &mut Player<I>) {
+    let (a, b) = (*one.get_transform(), *two.get_transform());
+    if !Collision::aabb(&a, &b).0 {
+        return;
+    }
+    if one.get_velocity().y > 0.0 && a.yh() < b.center_yh() {
+        one.bounce();
+    } else if two.get_velocity().y > 0.0 && b.yh() < a.center_yh() {
+        two.bounce();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::{
+        sprites_manager::SpriteDefinitions, spritesheet::SpriteSheet, transform::Trans,
+    };
+    use std::path::Path;
+    use std::rc::Rc;
+
+    struct TestTexture;
+
+    impl ImageSize for TestTexture {
+        fn get_size(&self) -> (u32, u32) {
+            (512, 512)
+        }
+    }
+
+    fn player() -> Player<TestTexture> {
+        let definitions =
+            SpriteDefinitions::load(Path::new("assets/player_animations.json")).unwrap();
+        Player::new(SpriteSheet::new(Rc::new(TestTexture)), definitions)
+    }
+
+    #[test]
+    fn player_two_leads_once_player_one_is_out() {
+        let mut partner = Partner::new(player(), 1, 2);
+        assert_eq!(default_lead(1, Some(&partner)), PlayerId::One);
+        assert_eq!(default_lead(0, Some(&partner)), PlayerId::Two);
+        assert_eq!(player_ids(Some(&partner), false), [PlayerId::Two]);
+        assert!(!all_out(0, Some(&partner)));
+
+        partner.lives = 0;
+        assert_eq!(default_lead(0, Some(&partner)), PlayerId::One);
+        assert!(all_out(0, Some(&partner)));
+        assert!(all_out::<TestTexture>(0, None));
+        assert!(!all_out(1, Some(&partner)));
+    }
+
+    #[test]
+    fn players_in_play_leave_out_whoever_is_out() {
+        let mut one = player();
+        let mut partner = Some(Partner::new(player(), 1, 1));
+        let ids = |players: Vec<(PlayerId, &mut Player<TestTexture>)>| {
+            players.into_iter().map(|(id, _)| id).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(players_mut(&mut one, &mut partner, true)),
+            [PlayerId::One, PlayerId::Two]
+        );
+        assert_eq!(
+            ids(players_mut(&mut one, &mut partner, false)),
+            [PlayerId::Two]
+        );
+
+        partner.as_mut().unwrap().kill(false);
+        assert_eq!(
+            ids(players_mut(&mut one, &mut partner, true)),
+            [PlayerId::One]
+        );
+        assert!(player_ids(partner.as_ref(), false).is_empty());
+    }
+
+    #[test]
+    fn a_death_in_progress_costs_a_life_once() {
+        let mut partner = Partner::new(player(), 1, 2);
+        partner.kill(true);
+        partner.settle_death();
+        assert_eq!(partner.lives, 1);
+
+        let mut lead = Transform::new();
+        lead.set_size(16.0, 16.0);
+        lead.set_position(100.0, 50.0);
+        assert!(partner.rejoin(&lead));
+        assert_eq!(partner.lives, 1);
+        assert!(partner.in_play());
+        assert_eq!(partner.player.get_transform().xw(), 100.0);
+
+        partner.kill(false);
+        assert!(!partner.rejoin(&lead));
+        assert_eq!(partner.lives, 0);
+        assert!(!partner.in_play());
+    }
+}
@@ -0,0 +1,47 @@
+use piston_window::ImageSize;
+
+use super::{player::Player, transform::Rect};
+
+const JUMP_SPEED: f64 = 9.0;
+const GRAVITY: f64 = 0.6;
+const FALL_TIME: f64 = 2.5;
+
+/// The player's death: a freeze on the death frame until its clip has
+/// played, then a hop up and a fall off the bottom of the screen. Falling
+/// into a pit skips the hop since the player is already out of view.
+pub struct PlayerDeath {
+    pause: bool,
+    velocity: f64,
+    remaining: f64,
+    fell: bool,
+}
+
+impl PlayerDeath {
+    pub fn new(fell: bool) -> Self {
+        Self {
+            pause: true,
+            velocity: -JUMP_SPEED,
+            remaining: FALL_TIME,
+            fell,
+        }
+    }
+
+    /// Advances the animation, returning `true` once it is over.
+    pub fn update<I: ImageSize>(&mut self, dt: f64, player: &mut Player<I>) -> bool {
+        if self.pause {
+            player.animate(dt);
+            // Without a death clip there is nothing to wait for.
+            self.pause =
+                player.current_animation() == Some("death") && !player.animation_finished("death");
+            return false;
+        }
+        self.remaining -= dt;
+        if !self.fell {
+            self.velocity += GRAVITY;
+            let body = player.get_transform();
+            let (x, y) = (body.x(), body.y() + self.velocity);
+            player.set_position(x, y);
+        }
+        self.remaining <= 0.0
+    }
+}
@@ -0,0 +1,171 @@
+use fps_counter::FPSCounter;
+use graphics::math::Matrix2d;
+use graphics::{Context, Graphics, Line, Rectangle, Transformed};
+use piston_window::{DrawState, G2d, G2dTexture, Glyphs, ImageSize, Text};
+use std::time::Duration;
+
+use super::{
+    camera::Camera,
+    collider::Side,
+    console::Console,
+    object::Object,
+    player::Player,
+    transform::{Rect, Trans},
+};
+
+const COLLIDER_COLOR: [f32; 4] = [0.2, 1.0, 0.2, 1.0];
+const HITBOX_COLOR: [f32; 4] = [1.0, 1.0, 0.2, 1.0];
+const CONTACT_COLOR: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+const VELOCITY_COLOR: [f32; 4] = [0.2, 0.8, 1.0, 1.0];
+const CAMERA_COLOR: [f32; 4] = [1.0, 0.4, 1.0, 1.0];
+
+/// Toggleable overlay showing colliders, contacts, velocities, camera bounds
+/// and frame timings.
+pub struct DebugOverlay {
+    pub enabled: bool,
+    fps_counter: FPSCounter,
+    fps: usize,
+    tick_time: f64,
+}
+
+/// Hosts that own a debug overlay the console can toggle.
+pub trait DebugHost {
+    fn debug_overlay(&mut self) -> &mut DebugOverlay;
+}
+
+/// Registers `overlay`.
+pub fn register_commands<C: DebugHost>(console: &mut Console<C>) {
+    console.register(
+        "overlay",
+        "<debug> - toggle an overlay",
+        |host, args| match args {
+            ["debug"] => {
+                let overlay = host.debug_overlay();
+                overlay.toggle();
+                Ok(format!("debug overlay {}", overlay.enabled))
+            }
+            _ => Err("usage: overlay <debug>".to_string()),
+        },
+    );
+}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            fps_counter: FPSCounter::default(),
+            fps: 0,
+            tick_time: 0.0,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    /// Counts a rendered frame.
+    pub fn frame(&mut self) {
+        self.fps = self.fps_counter.tick();
+    }
+
+    /// Records how long a simulation tick took, smoothed over recent ticks.
+    pub fn record_tick(&mut self, duration: Duration) {
+        let ms = duration.as_secs_f64() * 1000.0;
+        self.tick_time += (ms - self.tick_time) * 0.1;
+    }
+
+    /// Draws in world space, `t` being the camera transform.
+    pub fn draw_world<I, G>(
+        &self,
+        objects: &[Object<I>],
+        player: &Player<I>,
+        camera: &Camera,
+        t: Matrix2d,
+        g: &mut G,
+    ) where
+        I: ImageSize,
+        G: Graphics<Texture = I>,
+    {
+        let draw_state = DrawState::default();
+        let border = |color| Rectangle::new_border(color, 0.5);
+
+        for object in objects {
+            border(COLLIDER_COLOR).draw(object.get_transform().rect(), &draw_state, t, g);
+        }
+
+        let hitbox = player.get_transform();
+        border(HITBOX_COLOR).draw(hitbox.rect(), &draw_state, t, g);
+
+        let contact = Line::new(CONTACT_COLOR, 1.0);
+        for side in player.get_contacts() {
+            let line = match side {
+                Side::LEFT => [hitbox.x(), hitbox.y(), hitbox.x(), hitbox.yh()],
+                Side::RIGHT => [hitbox.xw(), hitbox.y(), hitbox.xw(), hitbox.yh()],
+                Side::TOP => [hitbox.x(), hitbox.y(), hitbox.xw(), hitbox.y()],
+                Side::BOTTOM => [hitbox.x(), hitbox.yh(), hitbox.xw(), hitbox.yh()],
+            };
+            contact.draw(line, &draw_state, t, g);
+        }
+
+        let velocity = player.get_velocity();
+        let (cx, cy) = (hitbox.center_xw(), hitbox.center_yh());
+        Line::new(VELOCITY_COLOR, 0.5).draw(
+            [cx, cy, cx + velocity.x * 4.0, cy + velocity.y * 4.0],
+            &draw_state,
+            t,
+            g,
+        );
+
+        border(CAMERA_COLOR).draw(
+            [0.0, 0.0, camera.world_width, camera.world_height],
+            &draw_state,
+            t,
+            g,
+        );
+        border(CAMERA_COLOR).draw(
+            [
+                camera.position.x + 1.0,
+                camera.position.y + 1.0,
+                camera.viewport_width - 2.0,
+                camera.viewport_height - 2.0,
+            ],
+            &draw_state,
+            t,
+            g,
+        );
+    }
+
+    /// Draws the text readout in window space.
+    pub fn draw_text(
+        &self,
+        player: &Player<G2dTexture>,
+        glyphs: &mut Glyphs,
+        c: Context,
+        g: &mut G2d,
+    ) {
+        let position = player.get_transform().get_position();
+        let velocity = player.get_velocity();
+        let lines = [
+            format!("FPS {:3}  tick {:5.2} ms", self.fps, self.tick_time),
+            format!("pos {:7.1} {:7.1}", position.x, position.y),
+            format!("vel {:7.2} {:7.2}", velocity.x, velocity.y),
+            format!(
+                "state {:?}  contacts {:?}",
+                player.get_state(),
+                player.get_contacts()
+            ),
+        ];
+
+        let text = Text::new_color([1.0, 1.0, 1.0, 1.0], 14);
+        for (i, line) in lines.iter().enumerate() {
+            text.draw(
+                line,
+                glyphs,
+                &c.draw_state,
+                c.transform.trans(10.0, 20.0 + 18.0 * i as f64),
+                g,
+            )
+            .ok();
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use cgmath::Vector2;
+use graphics::math::Matrix2d;
+use graphics::{Graphics, Transformed};
+use piston_window::ImageSize;
+use serde_json::{json, Value};
+
+use super::{
+    collider::{Collision, Side},
+    object::Object,
+    snapshot::{self, read_transform, write_transform},
+    sprites_manager::{AnimationSnapshot, SpriteDefinitions, SpriteManager},
+    spritesheet::SpriteSheet,
+    transform::{Rect, Trans, Transform},
+};
+
+const WALK_SPEED: f64 = 1.0;
+const GRAVITY: f64 = 1.2;
+const MAX_FALL_SPEED: f64 = 10.0;
+const SQUISHED_TIME: f64 = 0.5;
+const KNOCKED_SPEED: f64 = 8.0;
+const KNOCKED_TIME: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnemyKind {
+    Goomba,
+}
+
+impl EnemyKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "goomba" => Some(EnemyKind::Goomba),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnemyKind::Goomba => "goomba",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnemyState {
+    Walking,
+    Squished(f64),
+    /// Flipped over and falling off the screen.
+    Knocked(f64),
+    Removed,
+}
+
+/// A ground enemy that walks until it hits a wall, then turns around.
+pub struct Enemy<I: ImageSize> {
+    kind: EnemyKind,
+    sprites: SpriteManager<I>,
+    transform: Transform,
+    velocity: Vector2<f64>,
+    state: EnemyState,
+}
+
+impl<I> Enemy<I>
+where
+    I: ImageSize,
+{
+    pub fn new(
+        kind: EnemyKind,
+        sprite_sheet: SpriteSheet<I>,
+        definitions: SpriteDefinitions,
+        x: f64,
+        y: f64,
+    ) -> Self {
+        let mut sprites = SpriteManager::new();
+        sprites.set_spritesheet(sprite_sheet);
+        sprites.set_definitions(definitions);
+        sprites.play_animation("walk");
+
+        let mut transform = Transform::new();
+        transform.set_position(x, y);
+
+        Self {
+            kind,
+            sprites,
+            transform,
+            velocity: Vector2::new(-WALK_SPEED, 0.0),
+            state: EnemyState::Walking,
+        }
+    }
+
+    pub fn get_transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn snapshot(&self) -> Value {
+        let (state, time) = match self.state {
+            EnemyState::Walking => ("walking", 0.0),
+            EnemyState::Squished(time) => ("squished", time),
+            EnemyState::Knocked(time) => ("knocked", time),
+            EnemyState::Removed => ("removed", 0.0),
+        };
+        json!({
+            "kind": self.kind.name(),
+            "transform": write_transform(&self.transform),
+            "velocity": [self.velocity.x, self.velocity.y],
+            "state": state,
+            "time": time,
+            "animation": self.sprites.animation().to_json(),
+        })
+    }
+
+    pub fn restore(&mut self, json: &Value) -> Result<(), String> {
+        let time = snapshot::number(json, "time")?;
+        self.state = match snapshot::text(json, "state")? {
+            "walking" => EnemyState::Walking,
+            "squished" => EnemyState::Squished(time),
+            "knocked" => EnemyState::Knocked(time),
+            "removed" => EnemyState::Removed,
+            state => return Err(format!("unknown enemy state `{}`", state)),
+        };
+        let (x, y) = snapshot::pair(json, "velocity")?;
+        self.velocity = Vector2::new(x, y);
+        self.transform = read_transform(json, "transform")?;
+        match (self.state, AnimationSnapshot::read(json)?) {
+            (_, Some(animation)) => self.sprites.restore_animation(&animation),
+            (EnemyState::Squished(_), None) => self.sprites.force_animation("squished"),
+            _ => {}
+        }
+        if let EnemyState::Knocked(_) = self.state {
+            if let Some(sprite) = self.sprites.get_sprite() {
+                sprite.set_flip_y(true);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the enemy can still hurt or be hit by the player.
+    pub fn is_alive(&self) -> bool {
+        self.state == EnemyState::Walking
+    }
+
+    pub fn is_removed(&self) -> bool {
+        self.state == EnemyState::Removed
+    }
+
+    /// Flattens the enemy after the player lands on it.
+    pub fn stomp(&mut self) {
+        if self.is_alive() {
+            self.state = EnemyState::Squished(SQUISHED_TIME);
+            self.velocity = Vector2::new(0.0, 0.0);
+            self.sprites.force_animation("squished");
+        }
+    }
+
+    /// Flips the enemy over and drops it through the floor, as when a star
+    /// player runs into it.
+    pub fn knock(&mut self) {
+        if self.is_alive() {
+            self.state = EnemyState::Knocked(KNOCKED_TIME);
+            self.velocity = Vector2::new(self.velocity.x.signum(), -KNOCKED_SPEED);
+            if let Some(sprite) = self.sprites.get_sprite() {
+                sprite.set_flip_y(true);
+            }
+        }
+    }
+
+    pub fn collide_with(&mut self, obj: &Object<I>) {
+        if !self.is_alive() {
+            return;
+        }
+        let (collide, side) = Collision::aabb(&self.transform, &obj.get_transform());
+        if !collide {
+            return;
+        }
+        let other = obj.get_transform();
+        match side {
+            Some(Side::RIGHT) => {
+                self.transform
+                    .translate(other.x() - self.transform.xw(), 0.0);
+                self.velocity.x = -self.velocity.x.abs();
+            }
+            Some(Side::LEFT) => {
+                self.transform
+                    .translate(other.xw() - self.transform.x(), 0.0);
+                self.velocity.x = self.velocity.x.abs();
+            }
+            Some(Side::TOP) => {
+                self.transform
+                    .translate(0.0, other.yh() - self.transform.y());
+                self.velocity.y = 0.0;
+            }
+            Some(Side::BOTTOM) => {
+                self.transform
+                    .translate(0.0, other.y() - self.transform.yh());
+                self.velocity.y = 0.0;
+            }
+            None => {}
+        }
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        match self.state {
+            EnemyState::Walking => {
+                self.velocity.y = (self.velocity.y + GRAVITY).min(MAX_FALL_SPEED);
+                self.transform.translate(self.velocity.x, self.velocity.y);
+            }
+            EnemyState::Squished(time) if time <= dt => self.state = EnemyState::Removed,
+            EnemyState::Squished(time) => self.state = EnemyState::Squished(time - dt),
+            EnemyState::Knocked(time) if time <= dt => self.state = EnemyState::Removed,
+            EnemyState::Knocked(time) => {
+                self.velocity.y = (self.velocity.y + GRAVITY).min(MAX_FALL_SPEED);
+                self.transform.translate(self.velocity.x, self.velocity.y);
+                self.state = EnemyState::Knocked(time - dt);
+            }
+            EnemyState::Removed => {}
+        }
+        self.sprites.update(dt);
+    }
+
+    pub fn draw<B: Graphics<Texture = I>>(&mut self, t: Matrix2d, b: &mut B) {
+        let position = self.transform.get_position();
+        self.sprites.draw(t.trans(position.x, position.y), b);
+    }
+}
@@ -0,0 +1,86 @@
+use serde_json::{Map, Value};
+
+use super::transform::{Trans, Transform};
+
+/// An object from a Tiled object layer, with its `type` and custom
+/// properties kept for whichever subsystem handles that type.
+#[derive(Debug, Clone)]
+pub struct MapObject {
+    pub id: u64,
+    pub kind: String,
+    pub name: String,
+    pub transform: Transform,
+    properties: Map<String, Value>,
+}
+
+impl MapObject {
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let field = |name: &str| {
+            value
+                .get(name)
+                .and_then(Value::as_f64)
+                .ok_or_else(|| format!("map object without `{}`", name))
+        };
+        let text = |name: &str| {
+            value
+                .get(name)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let mut transform = Transform::new();
+        transform.set_position(field("x")?, field("y")?);
+        transform.set_size(field("width")?, field("height")?);
+
+        Ok(Self {
+            id: value.get("id").and_then(Value::as_u64).unwrap_or_default(),
+            kind: text("type"),
+            name: text("name"),
+            transform,
+            properties: read_properties(value),
+        })
+    }
+
+    pub fn property(&self, name: &str) -> Option<&Value> {
+        self.properties.get(name)
+    }
+}
+
+/// Collects the custom `properties` of a map or map object by name.
+pub fn read_properties(value: &Value) -> Map<String, Value> {
+    let mut properties = Map::new();
+    for property in value
+        .get("properties")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        if let (Some(name), Some(value)) = (
+            property.get("name").and_then(Value::as_str),
+            property.get("value"),
+        ) {
+            properties.insert(name.to_string(), value.clone());
+        }
+    }
+    properties
+}
+
+/// Reads every object of the named object layer. A missing layer is not an
+/// error since most maps only use some of the entity types.
+pub fn read_object_layer(map: &Value, layer: &str) -> Result<Vec<MapObject>, String> {
+    let layer = map
+        .get("layers")
+        .and_then(Value::as_array)
+        .ok_or("missing `layers`")?
+        .iter()
+        .find(|x| x.get("name").and_then(Value::as_str) == Some(layer));
+
+    match layer
+        .and_then(|x| x.get("objects"))
+        .and_then(Value::as_array)
+    {
+        Some(objects) => objects.iter().map(MapObject::from_json).collect(),
+        None => Ok(Vec::new()),
+    }
+}
@@ -0,0 +1,141 @@
+use graphics::ImageSize;
+
+use super::{
+    entities::MapObject,
+    player::Player,
+    timer::LevelTimer,
+    transform::{Rect, Transform},
+};
+
+const SLIDE_SPEED: f64 = 4.0;
+const SLIDE_PAUSE: f64 = 0.5;
+const TALLY_UNITS_PER_TICK: u32 = 4;
+const SCORE_PER_TIME_UNIT: u32 = 50;
+const TALLY_PAUSE: f64 = 1.0;
+
+/// Scores awarded by how high up the pole the player grabbed it, from the
+/// top down, as `(fraction of the pole height, score)`.
+const GRAB_SCORES: [(f64, u32); 5] = [
+    (0.9, 5000),
+    (0.7, 2000),
+    (0.5, 800),
+    (0.25, 400),
+    (0.0, 100),
+];
+
+/// The pole ending a level, read from a `flagpole` object. The optional
+/// `castle` object marks the door the player walks into afterwards.
+pub struct Flagpole {
+    pole: Transform,
+    castle_x: f64,
+}
+
+impl Flagpole {
+    pub fn from_objects(objects: &[MapObject]) -> Option<Self> {
+        let pole = objects.iter().find(|x| x.kind == "flagpole")?.transform;
+        let castle_x = objects
+            .iter()
+            .find(|x| x.kind == "castle")
+            .map(|x| x.transform.center_xw())
+            .unwrap_or(pole.xw() + 96.0);
+        Some(Self { pole, castle_x })
+    }
+
+    pub fn get_transform(&self) -> &Transform {
+        &self.pole
+    }
+
+    pub fn score_for_height(&self, y: f64) -> u32 {
+        let height = ((self.pole.yh() - y) / self.pole.h()).clamp(0.0, 1.0);
+        GRAB_SCORES
+            .iter()
+            .find(|(min, _)| height >= *min)
+            .map_or(100, |(_, score)| *score)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LevelEndPhase {
+    Slide,
+    Pause(f64),
+    Walk,
+    Tally,
+    Done(f64),
+}
+
+/// The scripted end of a level: slide down the pole, walk into the castle,
+/// then count the remaining time into the score.
+pub struct LevelEnd {
+    phase: LevelEndPhase,
+    pole_bottom: f64,
+    pole_x: f64,
+    castle_x: f64,
+}
+
+impl LevelEnd {
+    pub fn new(flagpole: &Flagpole) -> Self {
+        Self {
+            phase: LevelEndPhase::Slide,
+            pole_bottom: flagpole.pole.yh(),
+            pole_x: flagpole.pole.x(),
+            castle_x: flagpole.castle_x,
+        }
+    }
+
+    /// Whether the player should be simulated normally this tick. The slide
+    /// and tally phases move the player directly instead.
+    pub fn player_moves(&self) -> bool {
+        self.phase == LevelEndPhase::Walk
+    }
+
+    /// Advances the sequence and returns `true` once it is over and the next
+    /// level should load.
+    pub fn update<I: ImageSize>(
+        &mut self,
+        dt: f64,
+        player: &mut Player<I>,
+        timer: &mut LevelTimer,
+        score: &mut u32,
+    ) -> bool {
+        match self.phase {
+            LevelEndPhase::Slide => {
+                let feet = player.get_transform().yh();
+                let step = SLIDE_SPEED.min(self.pole_bottom - feet).max(0.0);
+                let x = self.pole_x - player.get_transform().w() + 1.0;
+                player.set_position(x, player.get_transform().y());
+                player.climb(step, dt);
+                if step < SLIDE_SPEED {
+                    self.phase = LevelEndPhase::Pause(SLIDE_PAUSE);
+                }
+            }
+            LevelEndPhase::Pause(time) if time > dt => {
+                self.phase = LevelEndPhase::Pause(time - dt);
+            }
+            LevelEndPhase::Pause(_) => {
+                let y = player.get_transform().y();
+                player.set_position(self.pole_x + 1.0, y);
+                player.walk_right();
+                self.phase = LevelEndPhase::Walk;
+            }
+            LevelEndPhase::Walk => {
+                if player.get_transform().center_xw() >= self.castle_x {
+                    player.stop();
+                    player.set_visible(false);
+                    self.phase = LevelEndPhase::Tally;
+                }
+            }
+            LevelEndPhase::Tally => {
+                let units = timer.take(TALLY_UNITS_PER_TICK);
+                *score += units * SCORE_PER_TIME_UNIT;
+                if units == 0 {
+                    self.phase = LevelEndPhase::Done(TALLY_PAUSE);
+                }
+            }
+            LevelEndPhase::Done(time) if time > dt => {
+                self.phase = LevelEndPhase::Done(time - dt);
+            }
+            LevelEndPhase::Done(_) => return true,
+        }
+        false
+    }
+}
@@ -0,0 +1,61 @@
+use piston_window::{Button, ButtonArgs, ButtonState, ControllerButton};
+use sdl2::controller::{self, GameController};
+use sdl2::event::Event;
+use sdl2::{EventPump, GameControllerSubsystem, Sdl};
+
+/// Game controllers read through SDL, since the glutin window doesn't
+/// report them. Buttons come out as piston button events numbered like
+/// SDL's game controller buttons.
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    events: EventPump,
+    controllers: Vec<GameController>,
+}
+
+impl Gamepads {
+    pub fn new(sdl: &Sdl) -> Result<Self, String> {
+        // The focused window belongs to glutin, so SDL never sees it as ours.
+        sdl2::hint::set("SDL_JOYSTICK_ALLOW_BACKGROUND_EVENTS", "1");
+        Ok(Self {
+            subsystem: sdl.game_controller()?,
+            events: sdl.event_pump()?,
+            controllers: Vec::new(),
+        })
+    }
+
+    /// Opens pads as they are plugged in and returns the buttons pressed
+    /// and released since the last poll.
+    pub fn poll(&mut self) -> Vec<ButtonArgs> {
+        let mut buttons = Vec::new();
+        for event in self.events.poll_iter() {
+            match event {
+                Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                    Ok(controller) => self.controllers.push(controller),
+                    Err(e) => eprintln!("Failed to open gamepad {}: {}", which, e),
+                },
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.controllers.retain(|x| x.instance_id() != which)
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    buttons.push(button_args(which, button, ButtonState::Press))
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    buttons.push(button_args(which, button, ButtonState::Release))
+                }
+                _ => {}
+            }
+        }
+        buttons
+    }
+}
+
+fn button_args(id: u32, button: controller::Button, state: ButtonState) -> ButtonArgs {
+    ButtonArgs {
+        state,
+        button: Button::Controller(ControllerButton {
+            id,
+            button: button as i32 as u8,
+        }),
+        scancode: None,
+    }
+}
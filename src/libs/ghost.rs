@@ -0,0 +1,178 @@
+use piston_window::ImageSize;
+use serde_json::{json, Value};
+
+use super::player::{Player, PlayerForm, PlayerState};
+use super::snapshot;
+use super::transform::{Rect, Trans};
+
+const GHOST_OPACITY: f32 = 0.4;
+
+/// Where the player was at one moment of a run.
+struct GhostFrame {
+    time: f64,
+    /// Index into the run's maps.
+    map: usize,
+    x: f64,
+    y: f64,
+    state: PlayerState,
+    form: PlayerForm,
+    facing_left: bool,
+}
+
+/// The path of one run through a level, frame by frame from its start,
+/// with the times checkpoints were reached and the level was finished.
+pub struct GhostRun {
+    maps: Vec<String>,
+    frames: Vec<GhostFrame>,
+    splits: Vec<f64>,
+    time: Option<f64>,
+}
+
+impl GhostRun {
+    pub fn new() -> Self {
+        Self {
+            maps: Vec::new(),
+            frames: Vec::new(),
+            splits: Vec::new(),
+            time: None,
+        }
+    }
+
+    /// Adds the player's pose at `time`, unless the run is finished.
+    pub fn record<I: ImageSize>(&mut self, time: f64, map: &str, player: &Player<I>) {
+        if self.time.is_some() {
+            return;
+        }
+        let map = match self.maps.iter().position(|x| x == map) {
+            Some(map) => map,
+            None => {
+                self.maps.push(map.to_string());
+                self.maps.len() - 1
+            }
+        };
+        let body = player.get_transform();
+        self.frames.push(GhostFrame {
+            time,
+            map,
+            x: body.x(),
+            y: body.y(),
+            state: *player.get_state(),
+            form: player.get_form(),
+            facing_left: body.is_flip_x(),
+        });
+    }
+
+    /// Notes a checkpoint reached at `time` and returns its number.
+    pub fn add_split(&mut self, time: f64) -> usize {
+        self.splits.push(time);
+        self.splits.len() - 1
+    }
+
+    pub fn split(&self, index: usize) -> Option<f64> {
+        self.splits.get(index).copied()
+    }
+
+    pub fn finish(&mut self, time: f64) {
+        self.time = Some(time);
+    }
+
+    /// The time the level was finished in, if it was.
+    pub fn time(&self) -> Option<f64> {
+        self.time
+    }
+
+    /// Puts `puppet` where the run was at `time`, moving its animation on by
+    /// `dt`. Returns false when the run was in another map then or is over.
+    pub fn pose<I: ImageSize>(
+        &self,
+        time: f64,
+        map: &str,
+        puppet: &mut Player<I>,
+        dt: f64,
+    ) -> bool {
+        let index = self.frames.partition_point(|x| x.time <= time);
+        let frame = match index.checked_sub(1).and_then(|i| self.frames.get(i)) {
+            Some(frame) if index < self.frames.len() => frame,
+            _ => return false,
+        };
+        if self.maps[frame.map] != map {
+            return false;
+        }
+        if puppet.get_form() != frame.form {
+            puppet.set_form(frame.form);
+        }
+        puppet.set_pose(frame.state, frame.facing_left);
+        puppet.set_position(frame.x, frame.y);
+        puppet.update_animation(dt);
+        puppet.set_opacity(GHOST_OPACITY);
+        true
+    }
+
+    pub fn to_json(&self) -> Value {
+        let frames: Vec<Value> = self
+            .frames
+            .iter()
+            .map(|x| {
+                json!([
+                    x.time,
+                    x.map,
+                    x.x,
+                    x.y,
+                    x.state.name(),
+                    x.form.name(),
+                    x.facing_left
+                ])
+            })
+            .collect();
+        json!({
+            "maps": self.maps,
+            "frames": frames,
+            "splits": self.splits,
+            "time": self.time,
+        })
+    }
+
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let maps = snapshot::list(json, "maps")?
+            .iter()
+            .map(|x| x.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or("invalid `maps`")?;
+        let frames = snapshot::list(json, "frames")?
+            .iter()
+            .map(|x| match x.as_array().map(|x| x.as_slice()) {
+                Some([time, map, x, y, state, form, facing_left]) => Some(GhostFrame {
+                    time: time.as_f64()?,
+                    map: map.as_u64().filter(|x| (*x as usize) < maps.len())? as usize,
+                    x: x.as_f64()?,
+                    y: y.as_f64()?,
+                    state: PlayerState::from_name(state.as_str()?)?,
+                    form: PlayerForm::from_name(form.as_str()?)?,
+                    facing_left: facing_left.as_bool()?,
+                }),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or("invalid `frames`")?;
+        let splits = snapshot::list(json, "splits")?
+            .iter()
+            .map(Value::as_f64)
+            .collect::<Option<Vec<_>>>()
+            .ok_or("invalid `splits`")?;
+        Ok(Self {
+            maps,
+            frames,
+            splits,
+            time: json.get("time").and_then(Value::as_f64),
+        })
+    }
+}
+
+/// How a time compares to the ghost's, like `-1.25` when ahead.
+pub fn split_text(time: f64, best: f64) -> String {
+    let difference = time - best;
+    match difference <= 0.0 {
+        true => format!("-{:.2} AHEAD", -difference),
+        false => format!("+{:.2} BEHIND", difference),
+    }
+}
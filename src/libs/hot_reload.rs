@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Polls a folder for modified files. Polling keeps the dev build free of
+/// platform file-notification dependencies and is plenty fast for a
+/// handful of assets.
+pub struct AssetWatcher {
+    root: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    interval: f64,
+    elapsed: f64,
+}
+
+impl AssetWatcher {
+    pub fn new(root: &Path, interval: f64) -> Self {
+        let mut watcher = Self {
+            root: root.to_path_buf(),
+            modified: HashMap::new(),
+            interval,
+            elapsed: 0.0,
+        };
+        watcher.scan();
+        watcher
+    }
+
+    /// Returns the files created or modified since the previous scan. Scans
+    /// run at most once per `interval` seconds.
+    pub fn poll(&mut self, dt: f64) -> Vec<PathBuf> {
+        self.elapsed += dt;
+        if self.elapsed < self.interval {
+            return Vec::new();
+        }
+        self.elapsed = 0.0;
+        self.scan()
+    }
+
+    fn scan(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(_) => return changed,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let modified = match entry.metadata().and_then(|x| x.modified()) {
+                Ok(modified) if path.is_file() => modified,
+                _ => continue,
+            };
+            if self.modified.insert(path.clone(), modified) != Some(modified) {
+                changed.push(path);
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,96 @@
+use graphics::{Context, Transformed};
+use piston_window::{rectangle, G2d, Glyphs, Text};
+
+const HUD_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const FONT_SIZE: u32 = 22;
+
+/// Values shown along the top of the screen.
+pub struct HudInfo<'a> {
+    /// One entry per player, two in co-op games.
+    pub players: Vec<HudPlayer<'a>>,
+    pub world: &'a str,
+    pub time: Option<u32>,
+}
+
+pub struct HudPlayer<'a> {
+    pub name: &'a str,
+    pub lives: u32,
+    pub score: u32,
+    pub coins: u32,
+}
+
+pub struct Hud;
+
+impl Hud {
+    /// Draws a column for each player, then the coins, world and time,
+    /// spread over `width`, in window space.
+    pub fn draw(info: &HudInfo, glyphs: &mut Glyphs, width: f64, c: Context, g: &mut G2d) {
+        let time = match info.time {
+            Some(time) => format!("{:03}", time),
+            None => String::new(),
+        };
+        let coins: Vec<String> = info
+            .players
+            .iter()
+            .map(|x| format!("x{:02}", x.coins))
+            .collect();
+        let columns: Vec<(String, String)> = info
+            .players
+            .iter()
+            .map(|x| {
+                (
+                    format!("{} x{}", x.name.to_uppercase(), x.lives),
+                    format!("{:06}", x.score),
+                )
+            })
+            .chain([
+                ("COINS".to_string(), coins.join(" ")),
+                ("WORLD".to_string(), info.world.to_string()),
+                ("TIME".to_string(), time),
+            ])
+            .collect();
+
+        let text = Text::new_color(HUD_COLOR, FONT_SIZE);
+        let column_width = width / columns.len() as f64;
+        for (i, (title, value)) in columns.iter().enumerate() {
+            let x = 40.0 + column_width * i as f64;
+            for (row, line) in [title, value].iter().enumerate() {
+                text.draw(
+                    line,
+                    glyphs,
+                    &c.draw_state,
+                    c.transform.trans(x, 32.0 + 26.0 * row as f64),
+                    g,
+                )
+                .ok();
+            }
+        }
+    }
+
+    /// A line of text under the columns, like a mode indicator.
+    pub fn draw_notice(text: &str, glyphs: &mut Glyphs, c: Context, g: &mut G2d) {
+        Text::new_color(HUD_COLOR, FONT_SIZE)
+            .draw(
+                text,
+                glyphs,
+                &c.draw_state,
+                c.transform.trans(40.0, 110.0),
+                g,
+            )
+            .ok();
+    }
+
+    /// Blacks out the screen with "GAME OVER" in the middle.
+    pub fn draw_game_over(glyphs: &mut Glyphs, size: [f64; 2], c: Context, g: &mut G2d) {
+        rectangle(
+            [0.0, 0.0, 0.0, 1.0],
+            [0.0, 0.0, size[0], size[1]],
+            c.transform,
+            g,
+        );
+        let transform = c.transform.trans(size[0] / 2.0 - 70.0, size[1] / 2.0);
+        Text::new_color(HUD_COLOR, FONT_SIZE)
+            .draw("GAME OVER", glyphs, &c.draw_state, transform, g)
+            .ok();
+    }
+}
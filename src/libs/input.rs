@@ -0,0 +1,202 @@
+use piston_window::{Button, ControllerButton, Key};
+use serde_json::{json, Value};
+
+/// Something the player can do, independent of the key or gamepad button
+/// bound to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Left,
+    Right,
+    Up,
+    Down,
+    Jump,
+    Run,
+    Shoot,
+    Pause,
+    Confirm,
+    Back,
+    /// Held to step the game backwards, in dev mode.
+    Rewind,
+}
+
+impl Action {
+    pub const ALL: [Action; 11] = [
+        Action::Left,
+        Action::Right,
+        Action::Up,
+        Action::Down,
+        Action::Jump,
+        Action::Run,
+        Action::Shoot,
+        Action::Pause,
+        Action::Confirm,
+        Action::Back,
+        Action::Rewind,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Jump => "jump",
+            Action::Run => "run",
+            Action::Shoot => "shoot",
+            Action::Pause => "pause",
+            Action::Confirm => "confirm",
+            Action::Back => "back",
+            Action::Rewind => "rewind",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|x| x.name() == name).copied()
+    }
+
+    /// Whether the action steers the player, as opposed to menus and
+    /// game-wide controls.
+    pub fn moves_player(&self) -> bool {
+        matches!(
+            self,
+            Action::Left
+                | Action::Right
+                | Action::Up
+                | Action::Down
+                | Action::Jump
+                | Action::Run
+                | Action::Shoot
+        )
+    }
+}
+
+/// A physical input. Gamepad buttons use SDL's game controller numbering
+/// and match on any connected pad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Key(Key),
+    Gamepad(u8),
+}
+
+impl Binding {
+    /// Keys are written by name, like `"Space"`, and gamepad buttons as
+    /// `{"gamepad": 0}`.
+    pub fn from_json(value: &Value) -> Option<Self> {
+        if let Some(button) = value.get("gamepad") {
+            let button = button.as_u64().filter(|x| *x <= u8::MAX as u64)?;
+            return Some(Binding::Gamepad(button as u8));
+        }
+        match serde_json::from_value(value.clone()) {
+            Ok(Key::Unknown) | Err(_) => None,
+            Ok(key) => Some(Binding::Key(key)),
+        }
+    }
+
+    pub fn to_json(self) -> Value {
+        match self {
+            Binding::Key(key) => json!(key),
+            Binding::Gamepad(button) => json!({ "gamepad": button }),
+        }
+    }
+}
+
+const PAD_A: u8 = 0;
+const PAD_B: u8 = 1;
+const PAD_X: u8 = 2;
+const PAD_START: u8 = 6;
+const PAD_LEFT_SHOULDER: u8 = 9;
+const PAD_UP: u8 = 11;
+const PAD_DOWN: u8 = 12;
+const PAD_LEFT: u8 = 13;
+const PAD_RIGHT: u8 = 14;
+
+/// Maps inputs to actions. One input can trigger several actions, like the
+/// up arrow both jumping and moving up in menus.
+pub struct Bindings {
+    bindings: Vec<(Binding, Action)>,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        use Action::*;
+        use Binding::Gamepad;
+        let bindings = vec![
+            (Binding::Key(Key::A), Left),
+            (Binding::Key(Key::Left), Left),
+            (Gamepad(PAD_LEFT), Left),
+            (Binding::Key(Key::D), Right),
+            (Binding::Key(Key::Right), Right),
+            (Gamepad(PAD_RIGHT), Right),
+            (Binding::Key(Key::W), Up),
+            (Binding::Key(Key::Up), Up),
+            (Gamepad(PAD_UP), Up),
+            (Binding::Key(Key::S), Down),
+            (Binding::Key(Key::Down), Down),
+            (Gamepad(PAD_DOWN), Down),
+            (Binding::Key(Key::Space), Jump),
+            (Binding::Key(Key::Up), Jump),
+            (Gamepad(PAD_A), Jump),
+            (Binding::Key(Key::LShift), Run),
+            (Binding::Key(Key::RShift), Run),
+            (Gamepad(PAD_X), Run),
+            (Binding::Key(Key::X), Shoot),
+            (Gamepad(PAD_B), Shoot),
+            (Binding::Key(Key::Escape), Pause),
+            (Gamepad(PAD_START), Pause),
+            (Binding::Key(Key::Return), Confirm),
+            (Binding::Key(Key::Space), Confirm),
+            (Gamepad(PAD_A), Confirm),
+            (Binding::Key(Key::Escape), Back),
+            (Gamepad(PAD_B), Back),
+            (Binding::Key(Key::R), Rewind),
+            (Gamepad(PAD_LEFT_SHOULDER), Rewind),
+        ];
+        Self { bindings }
+    }
+
+    /// Player two's defaults for co-op, on the other side of the keyboard
+    /// from player one's. Gamepads can't be told apart, so none are bound.
+    pub fn player_two() -> Self {
+        use Action::*;
+        let bindings = vec![
+            (Binding::Key(Key::J), Left),
+            (Binding::Key(Key::L), Right),
+            (Binding::Key(Key::I), Up),
+            (Binding::Key(Key::K), Down),
+            (Binding::Key(Key::O), Jump),
+            (Binding::Key(Key::U), Run),
+            (Binding::Key(Key::P), Shoot),
+        ];
+        Self { bindings }
+    }
+
+    /// Every input bound to `action`.
+    pub fn inputs(&self, action: Action) -> Vec<Binding> {
+        self.bindings
+            .iter()
+            .filter(|(_, x)| *x == action)
+            .map(|(binding, _)| *binding)
+            .collect()
+    }
+
+    /// Replaces the inputs bound to `action`.
+    pub fn set(&mut self, action: Action, inputs: Vec<Binding>) {
+        self.bindings.retain(|(_, x)| *x != action);
+        self.bindings
+            .extend(inputs.into_iter().map(|binding| (binding, action)));
+    }
+
+    /// Every action bound to `button`.
+    pub fn actions(&self, button: Button) -> Vec<Action> {
+        let binding = match button {
+            Button::Keyboard(key) => Binding::Key(key),
+            Button::Controller(ControllerButton { button, .. }) => Binding::Gamepad(button),
+            _ => return Vec::new(),
+        };
+        self.bindings
+            .iter()
+            .filter(|(x, _)| *x == binding)
+            .map(|(_, action)| *action)
+            .collect()
+    }
+}
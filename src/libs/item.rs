@@ -0,0 +1,210 @@
+use cgmath::Vector2;
+use graphics::math::Matrix2d;
+use graphics::{Graphics, Transformed};
+use piston_window::ImageSize;
+use serde_json::{json, Value};
+
+use super::{
+    collider::{Collision, Side},
+    object::Object,
+    snapshot::{self, read_transform, write_transform},
+    sprites_manager::{AnimationSnapshot, SpriteDefinitions, SpriteManager},
+    spritesheet::SpriteSheet,
+    transform::{Rect, Trans, Transform},
+};
+
+const RISE_SPEED: f64 = 0.5;
+const SLIDE_SPEED: f64 = 1.5;
+const GRAVITY: f64 = 1.2;
+const MAX_FALL_SPEED: f64 = 10.0;
+const STAR_BOUNCE: f64 = 8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ItemKind {
+    Mushroom,
+    FireFlower,
+    Star,
+    OneUp,
+}
+
+impl ItemKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mushroom" => Some(ItemKind::Mushroom),
+            "flower" => Some(ItemKind::FireFlower),
+            "star" => Some(ItemKind::Star),
+            "1up" => Some(ItemKind::OneUp),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ItemKind::Mushroom => "mushroom",
+            ItemKind::FireFlower => "flower",
+            ItemKind::Star => "star",
+            ItemKind::OneUp => "1up",
+        }
+    }
+
+    fn animation(&self) -> &'static str {
+        match self {
+            ItemKind::Mushroom => "mushroom",
+            ItemKind::FireFlower => "flower",
+            ItemKind::Star => "star",
+            ItemKind::OneUp => "one_up",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ItemState {
+    /// Coming up out of a block, with the distance left to rise.
+    Rising(f64),
+    Moving,
+}
+
+/// A power-up that rises out of a block. Mushrooms slide and turn at walls,
+/// stars bounce along and flowers stay where they came out.
+pub struct Item<I: ImageSize> {
+    kind: ItemKind,
+    sprites: SpriteManager<I>,
+    transform: Transform,
+    velocity: Vector2<f64>,
+    state: ItemState,
+}
+
+impl<I: ImageSize> Item<I> {
+    /// Starts the item inside `block`, from where it rises to sit on top.
+    pub fn new(
+        kind: ItemKind,
+        sprite_sheet: SpriteSheet<I>,
+        definitions: SpriteDefinitions,
+        block: &Transform,
+    ) -> Self {
+        let mut sprites = SpriteManager::new();
+        sprites.set_spritesheet(sprite_sheet);
+        sprites.set_definitions(definitions);
+        sprites.play_animation(kind.animation());
+
+        let mut transform = Transform::new();
+        transform.set_position(block.x(), block.y());
+
+        Self {
+            kind,
+            sprites,
+            transform,
+            velocity: Vector2::new(0.0, 0.0),
+            state: ItemState::Rising(transform.h()),
+        }
+    }
+
+    pub fn get_kind(&self) -> ItemKind {
+        self.kind
+    }
+
+    pub fn get_transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn snapshot(&self) -> Value {
+        let rising = match self.state {
+            ItemState::Rising(distance) => Some(distance),
+            ItemState::Moving => None,
+        };
+        json!({
+            "kind": self.kind.name(),
+            "transform": write_transform(&self.transform),
+            "velocity": [self.velocity.x, self.velocity.y],
+            "rising": rising,
+            "animation": self.sprites.animation().to_json(),
+        })
+    }
+
+    /// Restores an item made with `new` of the same kind.
+    pub fn restore(&mut self, json: &Value) -> Result<(), String> {
+        let (x, y) = snapshot::pair(json, "velocity")?;
+        self.velocity = Vector2::new(x, y);
+        self.transform = read_transform(json, "transform")?;
+        self.state = match json.get("rising").and_then(Value::as_f64) {
+            Some(distance) => ItemState::Rising(distance),
+            None => ItemState::Moving,
+        };
+        if let Some(animation) = AnimationSnapshot::read(json)? {
+            self.sprites.restore_animation(&animation);
+        }
+        Ok(())
+    }
+
+    /// Items can only be picked up once they are out of their block.
+    pub fn is_collectable(&self) -> bool {
+        self.state == ItemState::Moving
+    }
+
+    pub fn collide_with(&mut self, obj: &Object<I>) {
+        if !self.is_collectable() {
+            return;
+        }
+        let (collide, side) = Collision::aabb(&self.transform, &obj.get_transform());
+        if !collide {
+            return;
+        }
+        let other = obj.get_transform();
+        match side {
+            Some(Side::RIGHT) => {
+                self.transform
+                    .translate(other.x() - self.transform.xw(), 0.0);
+                self.velocity.x = -self.velocity.x.abs();
+            }
+            Some(Side::LEFT) => {
+                self.transform
+                    .translate(other.xw() - self.transform.x(), 0.0);
+                self.velocity.x = self.velocity.x.abs();
+            }
+            Some(Side::TOP) => {
+                self.transform
+                    .translate(0.0, other.yh() - self.transform.y());
+                self.velocity.y = 0.0;
+            }
+            Some(Side::BOTTOM) => {
+                self.transform
+                    .translate(0.0, other.y() - self.transform.yh());
+                self.velocity.y = match self.kind {
+                    ItemKind::Star => -STAR_BOUNCE,
+                    _ => 0.0,
+                };
+            }
+            None => {}
+        }
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        match self.state {
+            ItemState::Rising(distance) => {
+                let step = RISE_SPEED.min(distance);
+                self.transform.translate(0.0, -step);
+                if distance <= RISE_SPEED {
+                    self.state = ItemState::Moving;
+                    self.velocity = match self.kind {
+                        ItemKind::FireFlower => Vector2::new(0.0, 0.0),
+                        ItemKind::Star => Vector2::new(SLIDE_SPEED, -STAR_BOUNCE),
+                        _ => Vector2::new(SLIDE_SPEED, 0.0),
+                    };
+                } else {
+                    self.state = ItemState::Rising(distance - step);
+                }
+            }
+            ItemState::Moving if self.kind != ItemKind::FireFlower => {
+                self.velocity.y = (self.velocity.y + GRAVITY).min(MAX_FALL_SPEED);
+                self.transform.translate(self.velocity.x, self.velocity.y);
+            }
+            ItemState::Moving => {}
+        }
+        self.sprites.update(dt);
+    }
+
+    pub fn draw<B: Graphics<Texture = I>>(&mut self, t: Matrix2d, b: &mut B) {
+        let position = self.transform.get_position();
+        self.sprites.draw(t.trans(position.x, position.y), b);
+    }
+}
@@ -0,0 +1,219 @@
+use serde_json::Value;
+use std::path::Path;
+
+use super::assets::{read_json, AssetError};
+use super::console::Console;
+
+/// One stage of the game as listed in `levels.json`.
+#[derive(Debug, Clone)]
+pub struct LevelInfo {
+    pub id: String,
+    pub map: String,
+    pub music: String,
+    pub time: u32,
+    pub background: [f32; 4],
+}
+
+impl LevelInfo {
+    /// Parses `{ "id": "1-1", "map": "...", "music": "...", "time": 400,
+    /// "background": [r, g, b] }`.
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let text = |name: &str| -> Result<String, String> {
+            value
+                .get(name)
+                .and_then(Value::as_str)
+                .map(String::from)
+                .ok_or_else(|| format!("missing or invalid field `{}`", name))
+        };
+        let id = text("id")?;
+        let error = |e: String| format!("level `{}`: {}", id, e);
+
+        let time = value
+            .get("time")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| error("missing or invalid field `time`".to_string()))?;
+        let background = match value.get("background").and_then(Value::as_array) {
+            Some(color) if color.len() == 3 => {
+                let channel = |i: usize| color[i].as_f64().map(|x| x as f32);
+                match (channel(0), channel(1), channel(2)) {
+                    (Some(r), Some(g), Some(b)) => [r, g, b, 1.0],
+                    _ => return Err(error("`background` must be [r, g, b]".to_string())),
+                }
+            }
+            Some(_) => return Err(error("`background` must be [r, g, b]".to_string())),
+            None => [0.0, 0.0, 0.0, 1.0],
+        };
+
+        Ok(Self {
+            map: text("map").map_err(error)?,
+            music: text("music").map_err(error)?,
+            time: time as u32,
+            background,
+            id,
+        })
+    }
+}
+
+/// Hosts that play through a level registry.
+pub trait LevelHost {
+    fn levels(&self) -> &LevelRegistry;
+    /// Index of the level being played.
+    fn level(&self) -> usize;
+    /// Loads the level at `index` from its start.
+    fn start_level(&mut self, index: usize) -> Result<(), AssetError>;
+    /// Finishes the current level and loads the next one.
+    fn finish_level(&mut self) -> Result<(), AssetError>;
+}
+
+/// Registers `level`.
+pub fn register_commands<C: LevelHost>(console: &mut Console<C>) {
+    console.register(
+        "level",
+        "[id|next] - list levels or jump to one",
+        |host, args| {
+            let result = match args {
+                [] => {
+                    let levels = host.levels();
+                    let current = &levels.get(host.level()).unwrap().id;
+                    let list: Vec<String> = levels
+                        .levels()
+                        .iter()
+                        .map(|x| format!("{} ({}, time {})", x.id, x.map, x.time))
+                        .collect();
+                    return Ok(format!("in {}, levels: {}", current, list.join(", ")));
+                }
+                ["next"] => host.finish_level(),
+                [id] => {
+                    let index = host
+                        .levels()
+                        .index_of(id)
+                        .ok_or_else(|| format!("unknown level `{}`", id))?;
+                    host.start_level(index)
+                }
+                _ => return Err("usage: level [id|next]".to_string()),
+            };
+            result.map_err(|e| e.to_string())?;
+            Ok(format!(
+                "loaded level {}",
+                host.levels().get(host.level()).unwrap().id
+            ))
+        },
+    );
+}
+
+/// Ordered list of stages. Finishing the last stage loops back to the first.
+pub struct LevelRegistry {
+    levels: Vec<LevelInfo>,
+}
+
+impl LevelRegistry {
+    pub fn load(path: &Path) -> Result<Self, AssetError> {
+        let json = read_json(path)?;
+        Self::from_json(&json).map_err(|e| AssetError::Parse(path.to_path_buf(), e))
+    }
+
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let levels = json
+            .get("levels")
+            .and_then(Value::as_array)
+            .ok_or("missing `levels` array")?
+            .iter()
+            .map(LevelInfo::from_json)
+            .collect::<Result<Vec<_>, _>>()?;
+        if levels.is_empty() {
+            return Err("`levels` must contain at least one level".to_string());
+        }
+        for (i, level) in levels.iter().enumerate() {
+            if levels[..i].iter().any(|x| x.id == level.id) {
+                return Err(format!("level `{}` is listed twice", level.id));
+            }
+        }
+        Ok(Self { levels })
+    }
+
+    pub fn levels(&self) -> &[LevelInfo] {
+        &self.levels
+    }
+
+    pub fn get(&self, index: usize) -> Option<&LevelInfo> {
+        self.levels.get(index)
+    }
+
+    pub fn index_of(&self, id: &str) -> Option<usize> {
+        self.levels.iter().position(|x| x.id == id)
+    }
+
+    pub fn next(&self, index: usize) -> usize {
+        (index + 1) % self.levels.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn level(id: &str) -> Value {
+        json!({ "id": id, "map": "world_1_1.tmj", "music": "main_theme.mp3", "time": 400 })
+    }
+
+    #[test]
+    fn parses_levels() {
+        let mut first = level("1-1");
+        first["background"] = json!([0.5, 0.25, 1.0]);
+        let registry =
+            LevelRegistry::from_json(&json!({ "levels": [first, level("1-2")] })).unwrap();
+        let first = registry.get(0).unwrap();
+        assert_eq!(first.time, 400);
+        assert_eq!(first.background, [0.5, 0.25, 1.0, 1.0]);
+        assert_eq!(registry.get(1).unwrap().background, [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(registry.index_of("1-2"), Some(1));
+        assert_eq!(registry.next(1), 0);
+    }
+
+    #[test]
+    fn rejects_bad_registries() {
+        let mut no_time = level("1-1");
+        no_time.as_object_mut().unwrap().remove("time");
+        let mut bad_color = level("1-1");
+        bad_color["background"] = json!([1.0, 1.0]);
+        let bad = [
+            json!({ "levels": [] }),
+            json!({ "levels": [level("1-1"), level("1-1")] }),
+            json!({ "levels": [no_time] }),
+            json!({ "levels": [bad_color] }),
+            json!({}),
+        ];
+        for value in &bad {
+            assert!(LevelRegistry::from_json(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn advances_through_the_registry_and_loops() {
+        let registry = LevelRegistry::from_json(
+            &json!({ "levels": [level("1-1"), level("1-2"), level("1-3")] }),
+        )
+        .unwrap();
+        let mut index = registry.index_of("1-1").unwrap();
+        let mut order = Vec::new();
+        for _ in 0..4 {
+            index = registry.next(index);
+            order.push(registry.get(index).unwrap().id.as_str());
+        }
+        assert_eq!(order, ["1-2", "1-3", "1-1", "1-2"]);
+    }
+
+    #[test]
+    fn shipped_levels_load() {
+        let registry = LevelRegistry::load(Path::new("assets/levels.json")).unwrap();
+        assert!(!registry.levels().is_empty());
+        for level in registry.levels() {
+            assert!(
+                Path::new("assets").join(&level.map).is_file(),
+                "{}",
+                level.map
+            );
+        }
+    }
+}
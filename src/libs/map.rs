@@ -0,0 +1,201 @@
+use graphics::math::Matrix2d;
+use graphics::{Graphics, Transformed};
+use piston_window::{G2dTexture, G2dTextureContext, Size};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+use super::{
+    assets::{AssetError, AssetManager, AssetScope},
+    entities::{read_object_layer, read_properties, MapObject},
+    object::Object,
+    spritesheet::SpriteSheet,
+    tilemap::TileLayers,
+    transform::Trans,
+};
+
+/// A loaded Tiled map: its graphics, solid objects and entities. Maps with
+/// an image layer draw that image as the whole background; maps without one
+/// draw their tile layers instead.
+pub struct GameMap {
+    pub name: String,
+    pub size: Size,
+    pub objects: Vec<Object<G2dTexture>>,
+    pub entities: Vec<MapObject>,
+    image: Option<SpriteSheet<G2dTexture>>,
+    tiles: Option<TileLayers<G2dTexture>>,
+    background: Option<[f32; 4]>,
+    properties: Map<String, Value>,
+    files: Vec<String>,
+}
+
+impl GameMap {
+    pub fn load(
+        assets: &mut AssetManager,
+        context: &mut G2dTextureContext,
+        name: &str,
+    ) -> Result<Self, AssetError> {
+        let path = assets.path(name);
+        let error = |e: String| AssetError::Parse(path.clone(), e);
+        let map = assets.load_map(name, AssetScope::Level)?;
+        let map = assets.map(map).unwrap();
+        let mut files = vec![name.to_string()];
+
+        let dimension = |field: &str| {
+            map.get(field)
+                .and_then(Value::as_f64)
+                .ok_or_else(|| error(format!("missing `{}`", field)))
+        };
+        let size = Size::from([
+            dimension("width")? * dimension("tilewidth")?,
+            dimension("height")? * dimension("tileheight")?,
+        ]);
+
+        let objects = Self::load_objects(&path, &map)?;
+        let entities = read_object_layer(&map, "entities").map_err(error)?;
+
+        let layers = map
+            .get("layers")
+            .and_then(Value::as_array)
+            .ok_or_else(|| error("missing `layers`".to_string()))?;
+        let image_layer = layers
+            .iter()
+            .find(|x| x.get("type").and_then(Value::as_str) == Some("imagelayer"))
+            .and_then(|x| x.get("image"))
+            .and_then(Value::as_str);
+
+        let (image, tiles) = match image_layer {
+            Some(image) => {
+                let texture = assets.load_texture(context, image, AssetScope::Level)?;
+                files.push(image.to_string());
+                (
+                    Some(SpriteSheet::new(assets.texture(texture).unwrap())),
+                    None,
+                )
+            }
+            None => {
+                let source = map
+                    .get("tilesets")
+                    .and_then(Value::as_array)
+                    .and_then(|x| x.first())
+                    .and_then(|x| x.get("source"))
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| error("needs an image layer or a tileset".to_string()))?;
+                let tileset = assets.load_tileset(source, AssetScope::Level)?;
+                let tileset = assets.tileset(tileset).unwrap();
+                let image = tileset
+                    .get("image")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| error(format!("tileset `{}` has no image", source)))?;
+                let texture = assets.load_texture(context, image, AssetScope::Level)?;
+                let tiles = TileLayers::from_json(&map, &tileset, assets.texture(texture).unwrap())
+                    .map_err(error)?;
+                files.push(source.to_string());
+                files.push(image.to_string());
+                (None, Some(tiles))
+            }
+        };
+
+        let background = match map.get("backgroundcolor").and_then(Value::as_str) {
+            Some(color) => Some(parse_color(color).map_err(error)?),
+            None => None,
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            size,
+            objects,
+            entities,
+            image,
+            tiles,
+            background,
+            properties: read_properties(&map),
+            files,
+        })
+    }
+
+    fn load_objects(path: &Path, map: &Value) -> Result<Vec<Object<G2dTexture>>, AssetError> {
+        let error = |e: &str| AssetError::Parse(path.to_path_buf(), e.to_string());
+        let solid_objects: &Vec<Value> = map
+            .get("layers")
+            .and_then(Value::as_array)
+            .ok_or_else(|| error("missing `layers`"))?
+            .iter()
+            .find(|x| x.get("name").and_then(Value::as_str) == Some("solid_objects"))
+            .and_then(|x| x.get("objects"))
+            .and_then(Value::as_array)
+            .ok_or_else(|| error("missing `solid_objects` layer"))?;
+
+        let mut objects: Vec<Object<G2dTexture>> = Vec::default();
+        for obj in solid_objects {
+            let field = |name: &str| {
+                obj.get(name)
+                    .and_then(Value::as_f64)
+                    .ok_or_else(|| error(&format!("solid object without `{}`", name)))
+            };
+            let x = field("x")?;
+            let y = field("y")?;
+            let w = field("width")?;
+            let h = field("height")?;
+            let mut o = Object::<G2dTexture>::new();
+            o.set_size(w, h);
+            o.set_position(x, y);
+            objects.push(o);
+        }
+
+        Ok(objects)
+    }
+
+    /// Whether the map was built from the file `name`, so it needs rebuilding
+    /// when that file changes.
+    pub fn depends_on(&self, name: &str) -> bool {
+        self.files.iter().any(|x| x == name)
+    }
+
+    /// The files the map was built from.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// The map's own `backgroundcolor`, if it sets one.
+    pub fn background(&self) -> Option<[f32; 4]> {
+        self.background
+    }
+
+    /// A custom map property.
+    pub fn property(&self, name: &str) -> Option<&Value> {
+        self.properties.get(name)
+    }
+
+    /// Draws the part of the map inside `view`, a world-space rectangle, with
+    /// `t` being the camera transform.
+    pub fn draw<G: Graphics<Texture = G2dTexture>>(
+        &mut self,
+        view: [f64; 4],
+        t: Matrix2d,
+        g: &mut G,
+    ) {
+        if let Some(image) = self.image.as_mut() {
+            let (x, y) = (view[0].max(0.0), view[1].max(0.0));
+            image
+                .get_sprite()
+                .unwrap()
+                .set_src_rect([x, y, view[2], view[3]]);
+            image.draw(t.trans(x, y), g);
+        }
+        if let Some(tiles) = self.tiles.as_mut() {
+            tiles.draw(view, t, g);
+        }
+    }
+}
+
+/// Parses Tiled's `#rrggbb` or `#aarrggbb` colors.
+fn parse_color(text: &str) -> Result<[f32; 4], String> {
+    let hex = text.trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16).map_err(|_| format!("invalid color `{}`", text))?;
+    let channel = |shift: u32| ((value >> shift) & 0xff) as f32 / 255.0;
+    match hex.len() {
+        6 => Ok([channel(16), channel(8), channel(0), 1.0]),
+        8 => Ok([channel(16), channel(8), channel(0), channel(24)]),
+        _ => Err(format!("invalid color `{}`", text)),
+    }
+}
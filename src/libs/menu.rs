@@ -0,0 +1,104 @@
+use graphics::{Context, Transformed};
+use piston_window::{rectangle, G2d, Glyphs, Text};
+
+use super::input::Action;
+
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const TITLE_SIZE: u32 = 32;
+const ITEM_SIZE: u32 = 22;
+const LINE_HEIGHT: f64 = 34.0;
+
+/// What a menu did with an action.
+pub enum MenuEvent<T> {
+    Moved,
+    Select(T),
+    /// Left or right on an item, e.g. to change a volume.
+    Adjust(T, i32),
+    Back,
+}
+
+/// A vertical list of items picked with the menu actions. `T` identifies
+/// the items to whoever handles the events.
+pub struct Menu<T: Copy> {
+    title: String,
+    items: Vec<(String, T)>,
+    selected: usize,
+    /// Whether the game behind stays visible through the menu.
+    overlay: bool,
+}
+
+impl<T: Copy + PartialEq> Menu<T> {
+    pub fn new(title: &str, items: Vec<(String, T)>, overlay: bool) -> Self {
+        Self {
+            title: title.to_string(),
+            items,
+            selected: 0,
+            overlay,
+        }
+    }
+
+    pub fn selected(&self) -> T {
+        self.items[self.selected].1
+    }
+
+    /// Changes the text of `item`, e.g. to show a new setting value.
+    pub fn set_label(&mut self, item: T, label: String) {
+        if let Some(x) = self.items.iter_mut().find(|x| x.1 == item) {
+            x.0 = label;
+        }
+    }
+
+    /// Moves the selection, wrapping around at either end, and reports
+    /// picks, adjustments and backing out.
+    pub fn action(&mut self, action: Action) -> Option<MenuEvent<T>> {
+        let count = self.items.len();
+        match action {
+            Action::Up => self.selected = (self.selected + count - 1) % count,
+            Action::Down => self.selected = (self.selected + 1) % count,
+            Action::Left => return Some(MenuEvent::Adjust(self.selected(), -1)),
+            Action::Right => return Some(MenuEvent::Adjust(self.selected(), 1)),
+            Action::Confirm => return Some(MenuEvent::Select(self.selected())),
+            Action::Back | Action::Pause => return Some(MenuEvent::Back),
+            _ => return None,
+        }
+        Some(MenuEvent::Moved)
+    }
+
+    /// Draws the menu centred in a window of `size`.
+    pub fn draw(&self, glyphs: &mut Glyphs, size: [f64; 2], c: Context, g: &mut G2d) {
+        let alpha = if self.overlay { 0.7 } else { 1.0 };
+        rectangle(
+            [0.0, 0.0, 0.0, alpha],
+            [0.0, 0.0, size[0], size[1]],
+            c.transform,
+            g,
+        );
+
+        let height = LINE_HEIGHT * (self.items.len() + 2) as f64;
+        let x = size[0] / 2.0 - 120.0;
+        let y = (size[1] - height) / 2.0 + LINE_HEIGHT;
+        Text::new_color(TEXT_COLOR, TITLE_SIZE)
+            .draw(
+                &self.title,
+                glyphs,
+                &c.draw_state,
+                c.transform.trans(x, y),
+                g,
+            )
+            .ok();
+
+        let text = Text::new_color(TEXT_COLOR, ITEM_SIZE);
+        for (i, (label, _)) in self.items.iter().enumerate() {
+            let marker = if i == self.selected { ">" } else { " " };
+            let transform = c.transform.trans(x, y + LINE_HEIGHT * (i + 2) as f64);
+            text.draw(
+                &format!("{} {}", marker, label),
+                glyphs,
+                &c.draw_state,
+                transform,
+                g,
+            )
+            .ok();
+        }
+    }
+}
@@ -0,0 +1,511 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::controller::Controller;
+use super::input::Action;
+
+/// Updates per second of a netplay game. Both sides step the simulation by
+/// the same fixed time so they stay in lockstep.
+pub const NET_UPS: u64 = 30;
+/// Frames between pressing a button and the frame it lands on. Gives the
+/// other side's input time to arrive before it's needed.
+const INPUT_DELAY: u32 = 2;
+/// How many frames the game may run ahead of the last input confirmed by
+/// the other side before it waits for them.
+const MAX_ROLLBACK: u32 = 8;
+/// Frames rolled back and played again every frame in sync-test mode.
+const SYNC_TEST_FRAMES: u32 = 2;
+/// Most inputs sent in one packet.
+const MAX_PACKET_INPUTS: usize = 128;
+/// How long the other side may go quiet before the session is given up.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One side's inputs from frame `first` on, and the next frame of the
+/// other side's inputs the sender is still waiting for.
+struct Packet {
+    ack: u32,
+    first: u32,
+    inputs: Vec<u8>,
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.inputs.len());
+        bytes.extend_from_slice(&self.ack.to_le_bytes());
+        bytes.extend_from_slice(&self.first.to_le_bytes());
+        bytes.extend_from_slice(&self.inputs);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 8 {
+            return None;
+        }
+        let word = |at: usize| {
+            u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+        Some(Self {
+            ack: word(0),
+            first: word(4),
+            inputs: bytes[8..].to_vec(),
+        })
+    }
+}
+
+/// The other player at the end of a UDP socket. Packets from anywhere
+/// else are dropped.
+pub struct Peer {
+    socket: UdpSocket,
+    remote: SocketAddr,
+}
+
+impl Peer {
+    pub fn connect(local: &str, remote: &str) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+        let remote = remote
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "no address to send to"))?;
+        Ok(Self { socket, remote })
+    }
+
+    fn send(&self, packet: &Packet) -> io::Result<()> {
+        match self.socket.send_to(&packet.encode(), self.remote) {
+            // Nobody listening yet; the inputs go out again next frame.
+            Err(e) if is_unreachable(&e) => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+
+    fn receive(&self) -> io::Result<Vec<Packet>> {
+        let mut packets = Vec::new();
+        let mut buffer = [0; 512];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) if from == self.remote => {
+                    packets.extend(Packet::decode(&buffer[..len]));
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(packets),
+                Err(e) if is_unreachable(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Notes when the other side was last heard from and fails once they have
+/// been quiet for longer than `TIMEOUT`.
+fn check_timeout(last_heard: &mut Option<Instant>, heard: bool, now: Instant) -> io::Result<()> {
+    if heard {
+        *last_heard = Some(now);
+        return Ok(());
+    }
+    match last_heard {
+        Some(last) if now.duration_since(*last) > TIMEOUT => Err(io::Error::new(
+            ErrorKind::TimedOut,
+            format!("nothing heard for {} seconds", TIMEOUT.as_secs()),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn is_unreachable(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset
+    )
+}
+
+/// Input delay plus rollback between two copies of the game. Each side
+/// plays on with a guess of the other's input, keeps the game state of
+/// every frame it may have to take back, and when the real input differs
+/// from the guess the game goes back to that frame and plays it again.
+///
+/// Without a peer it runs as a sync test instead: every frame the last
+/// few frames are rolled back and played again with the same inputs, and
+/// the states must come out the same as the first time.
+pub struct Netplay {
+    peer: Option<Peer>,
+    /// 0 for player one, 1 for player two.
+    local_player: usize,
+    /// The next frame to play.
+    frame: u32,
+    /// Buttons the local player is holding right now.
+    held: Controller,
+    local: BTreeMap<u32, u8>,
+    remote: BTreeMap<u32, u8>,
+    /// Every remote input before this frame has arrived.
+    confirmed: u32,
+    /// The other side has every local input before this frame.
+    acked: u32,
+    /// Remote inputs guessed for frames that were played before they came.
+    predicted: BTreeMap<u32, u8>,
+    /// The game state at the start of each frame that may be played again.
+    /// Frames in the middle of a transition can't be saved and are missing.
+    states: BTreeMap<u32, Value>,
+    /// Sync test only: checksums of the states the first time through.
+    checksums: BTreeMap<u32, u64>,
+    /// The first frame played with a wrong guess.
+    rollback: Option<u32>,
+    /// When the other side was last heard from. Until they are heard from
+    /// at all there is no timeout, so the first side started can wait for
+    /// the second.
+    last_heard: Option<Instant>,
+}
+
+impl Netplay {
+    pub fn online(peer: Peer, local_player: usize) -> Self {
+        let mut netplay = Self::new(Some(peer), local_player);
+        // Nothing is pressed during the delay at the start.
+        for frame in 0..INPUT_DELAY {
+            netplay.local.insert(frame, 0);
+        }
+        netplay
+    }
+
+    pub fn sync_test() -> Self {
+        Self::new(None, 0)
+    }
+
+    fn new(peer: Option<Peer>, local_player: usize) -> Self {
+        Self {
+            peer,
+            local_player,
+            frame: 0,
+            held: Controller::new(),
+            local: BTreeMap::new(),
+            remote: BTreeMap::new(),
+            confirmed: 0,
+            acked: 0,
+            predicted: BTreeMap::new(),
+            states: BTreeMap::new(),
+            checksums: BTreeMap::new(),
+            rollback: None,
+            last_heard: None,
+        }
+    }
+
+    pub fn is_sync_test(&self) -> bool {
+        self.peer.is_none()
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// A local button going down or up.
+    pub fn action(&mut self, action: Action, pressed: bool) {
+        self.held.action_event(action, pressed);
+    }
+
+    /// Lets go of every local button, e.g. when a menu takes the input.
+    pub fn clear_input(&mut self) {
+        self.held = Controller::new();
+    }
+
+    /// Takes in the other side's inputs, noting the first frame that was
+    /// played with a wrong guess, and sends ours. Fails once the other side
+    /// has gone quiet for too long.
+    pub fn exchange(&mut self) -> io::Result<()> {
+        let delay = match self.peer {
+            Some(_) => INPUT_DELAY,
+            None => 0,
+        };
+        self.local
+            .entry(self.frame + delay)
+            .or_insert(self.held.bits());
+        let packets = match self.peer.as_ref() {
+            Some(peer) => peer.receive()?,
+            None => return Ok(()),
+        };
+        check_timeout(&mut self.last_heard, !packets.is_empty(), Instant::now())?;
+        self.take_in(packets);
+        match self.peer.as_ref() {
+            Some(peer) => peer.send(&self.packet()),
+            None => Ok(()),
+        }
+    }
+
+    /// Keeps the other side's inputs that are new, noting the first frame
+    /// that was played with a wrong guess, and moves `confirmed` past every
+    /// frame that has its input.
+    fn take_in(&mut self, packets: Vec<Packet>) {
+        for packet in packets {
+            self.acked = self.acked.max(packet.ack);
+            for (frame, input) in (packet.first..).zip(packet.inputs) {
+                if frame < self.confirmed || self.remote.contains_key(&frame) {
+                    continue;
+                }
+                self.remote.insert(frame, input);
+                if self.predicted.get(&frame).is_some_and(|x| *x != input) {
+                    self.rollback = Some(self.rollback.map_or(frame, |x| x.min(frame)));
+                }
+            }
+        }
+        while self.remote.contains_key(&self.confirmed) {
+            self.confirmed += 1;
+        }
+        let confirmed = self.confirmed;
+        self.predicted.retain(|frame, _| *frame >= confirmed);
+    }
+
+    /// Our inputs the other side doesn't have yet.
+    fn packet(&self) -> Packet {
+        let inputs = self
+            .local
+            .range(self.acked..)
+            .map(|(_, x)| *x)
+            .take(MAX_PACKET_INPUTS)
+            .collect();
+        Packet {
+            ack: self.confirmed,
+            first: self.acked,
+            inputs,
+        }
+    }
+
+    /// Where to go back to, if anywhere: the latest saved state at or before
+    /// the frame to correct, and its frame.
+    pub fn take_rollback(&mut self) -> Result<Option<(u32, Value)>, String> {
+        let target = match self.peer {
+            Some(_) => match self.rollback.take() {
+                Some(frame) => frame,
+                None => return Ok(None),
+            },
+            // Transitions aren't saved, so the test skips them.
+            None => match self.frame.checked_sub(SYNC_TEST_FRAMES) {
+                Some(frame) if self.states.contains_key(&frame) => frame,
+                _ => return Ok(None),
+            },
+        };
+        match self.states.range(..=target).next_back() {
+            Some((frame, state)) => Ok(Some((*frame, state.clone()))),
+            None => Err(format!("no saved state to roll back to frame {}", target)),
+        }
+    }
+
+    /// Whether the next frame can be played now. Online, the game waits
+    /// when it is too far ahead of the other side, and during transitions,
+    /// which can't be rolled back, it waits for the real input.
+    pub fn can_advance(&self, saved: bool) -> bool {
+        if self.peer.is_none() {
+            return true;
+        }
+        let ahead = self.frame.saturating_sub(self.confirmed);
+        self.local.contains_key(&self.frame)
+            && ahead < MAX_ROLLBACK
+            && (saved || self.frame < self.confirmed)
+    }
+
+    /// Keeps the state at the start of `frame` and hands out both players'
+    /// inputs for it, player one first. In sync-test mode a state that
+    /// differs from the first time through is a desync.
+    pub fn begin_frame(&mut self, frame: u32, state: Option<Value>) -> Result<[u8; 2], String> {
+        if let Some(state) = state {
+            if self.is_sync_test() {
+                let sum = checksum(&state);
+                match self.checksums.insert(frame, sum) {
+                    Some(first) if first != sum => {
+                        return Err(format!("desync at frame {}", frame));
+                    }
+                    _ => {}
+                }
+            }
+            self.states.insert(frame, state);
+        }
+
+        let local = self.local.get(&frame).copied().unwrap_or(0);
+        let remote = match self.remote.get(&frame) {
+            Some(input) => *input,
+            None if self.is_sync_test() => 0,
+            None => {
+                // Guess the other side is still holding what they held last.
+                let guess = match self.confirmed.checked_sub(1) {
+                    Some(last) => self.remote.get(&last).copied().unwrap_or(0),
+                    None => 0,
+                };
+                self.predicted.insert(frame, guess);
+                guess
+            }
+        };
+
+        if frame >= self.frame {
+            self.frame = frame + 1;
+            self.forget();
+        }
+        Ok(match self.local_player {
+            0 => [local, remote],
+            _ => [remote, local],
+        })
+    }
+
+    /// Drops states and inputs too old to ever be played again.
+    fn forget(&mut self) {
+        let needed = match self.peer {
+            Some(_) => self.confirmed.min(self.frame),
+            None => self.frame.saturating_sub(SYNC_TEST_FRAMES),
+        };
+        // The latest state at or before the oldest frame that could still
+        // change is kept, along with everything after it.
+        let keep = match self.states.range(..=needed).next_back() {
+            Some((frame, _)) => *frame,
+            None => return,
+        };
+        self.states = self.states.split_off(&keep);
+        self.checksums = self.checksums.split_off(&keep);
+        self.local = self.local.split_off(&keep.min(self.acked));
+        // The last confirmed input is kept for guessing the next ones.
+        self.remote = self
+            .remote
+            .split_off(&keep.min(self.confirmed.saturating_sub(1)));
+    }
+}
+
+/// FNV-1a over the state's JSON. Object keys are sorted, so equal states
+/// always give the same text.
+pub fn checksum(state: &Value) -> u64 {
+    state
+        .to_string()
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A session whose peer is never sent to; packets are handed in directly.
+    fn online(local_player: usize) -> Netplay {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let remote = socket.local_addr().unwrap();
+        Netplay::online(Peer { socket, remote }, local_player)
+    }
+
+    fn inputs(first: u32, inputs: &[u8]) -> Vec<Packet> {
+        vec![Packet {
+            ack: 0,
+            first,
+            inputs: inputs.to_vec(),
+        }]
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        let packet = Packet {
+            ack: 70000,
+            first: 3,
+            inputs: vec![1, 0, 255],
+        };
+        let decoded = Packet::decode(&packet.encode()).unwrap();
+        assert_eq!(decoded.ack, 70000);
+        assert_eq!(decoded.first, 3);
+        assert_eq!(decoded.inputs, vec![1, 0, 255]);
+        assert!(Packet::decode(&[0; 7]).is_none());
+    }
+
+    #[test]
+    fn checksum_follows_the_state() {
+        let a = json!({ "x": 1, "y": [2, 3] });
+        let b = json!({ "y": [2, 3], "x": 1 });
+        assert_eq!(checksum(&a), checksum(&b));
+        assert_ne!(checksum(&a), checksum(&json!({ "x": 1, "y": [3, 2] })));
+    }
+
+    #[test]
+    fn inputs_are_placed_by_player() {
+        let mut netplay = online(1);
+        netplay.take_in(inputs(0, &[9]));
+        assert_eq!(netplay.begin_frame(0, None).unwrap(), [9, 0]);
+    }
+
+    #[test]
+    fn wrong_guess_rolls_back_to_its_frame() {
+        let mut netplay = online(0);
+        netplay.begin_frame(0, Some(json!(0))).unwrap();
+        netplay.begin_frame(1, Some(json!(1))).unwrap();
+        netplay.take_in(inputs(0, &[0, 4]));
+        assert_eq!(netplay.confirmed, 2);
+        assert!(netplay.predicted.is_empty());
+        assert_eq!(netplay.take_rollback().unwrap(), Some((1, json!(1))));
+        assert_eq!(netplay.take_rollback().unwrap(), None);
+    }
+
+    #[test]
+    fn right_guess_needs_no_rollback() {
+        let mut netplay = online(0);
+        netplay.begin_frame(0, Some(json!(0))).unwrap();
+        netplay.take_in(inputs(0, &[0]));
+        assert_eq!(netplay.take_rollback().unwrap(), None);
+    }
+
+    #[test]
+    fn confirms_only_up_to_the_first_gap() {
+        let mut netplay = online(0);
+        netplay.take_in(inputs(0, &[1, 2]));
+        netplay.take_in(inputs(3, &[4]));
+        assert_eq!(netplay.confirmed, 2);
+        netplay.take_in(inputs(2, &[3]));
+        assert_eq!(netplay.confirmed, 4);
+    }
+
+    #[test]
+    fn waits_when_too_far_ahead() {
+        let mut netplay = online(0);
+        for frame in 0..MAX_ROLLBACK {
+            netplay.local.insert(frame, 0);
+            assert!(netplay.can_advance(true));
+            netplay.begin_frame(frame, Some(json!(frame))).unwrap();
+        }
+        netplay.local.insert(MAX_ROLLBACK, 0);
+        assert!(!netplay.can_advance(true));
+        netplay.take_in(inputs(0, &[0]));
+        assert!(netplay.can_advance(true));
+    }
+
+    #[test]
+    fn forgets_what_can_no_longer_change() {
+        let mut netplay = online(0);
+        for frame in 0..3 {
+            netplay.begin_frame(frame, Some(json!(frame))).unwrap();
+        }
+        netplay.take_in(vec![Packet {
+            ack: 2,
+            first: 0,
+            inputs: vec![0, 0],
+        }]);
+        netplay.begin_frame(3, Some(json!(3))).unwrap();
+        let kept: Vec<u32> = netplay.states.keys().copied().collect();
+        assert_eq!(kept, vec![2, 3]);
+        assert_eq!(netplay.packet().first, 2);
+        // The last confirmed input stays for guessing.
+        assert!(netplay.remote.contains_key(&1));
+    }
+
+    #[test]
+    fn sync_test_catches_a_different_state() {
+        let mut netplay = Netplay::sync_test();
+        netplay.begin_frame(0, Some(json!({ "x": 1 }))).unwrap();
+        assert!(netplay.begin_frame(0, Some(json!({ "x": 1 }))).is_ok());
+        assert!(netplay.begin_frame(0, Some(json!({ "x": 2 }))).is_err());
+    }
+
+    #[test]
+    fn times_out_only_after_hearing_from_the_other_side() {
+        let start = Instant::now();
+        let later = start + TIMEOUT + Duration::from_secs(1);
+        let mut last_heard = None;
+        assert!(check_timeout(&mut last_heard, false, later).is_ok());
+        check_timeout(&mut last_heard, true, start).unwrap();
+        assert!(check_timeout(&mut last_heard, false, start + TIMEOUT).is_ok());
+        assert!(check_timeout(&mut last_heard, false, later).is_err());
+        assert!(check_timeout(&mut last_heard, true, later).is_ok());
+    }
+}
@@ -1,21 +1,243 @@
 use cgmath::Vector2;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
+use super::assets::{read_json, AssetError};
+use super::console::{parse_number, Console};
 use super::controller::Controller;
+use super::player::PlayerForm;
+
+/// Hosts whose player physics the console can tune.
+pub trait PhysicsHost {
+    /// Switches to the profile called `name`, or returns false if there is
+    /// none for the player's form.
+    fn select_physics_profile(&mut self, name: &str) -> bool;
+    /// The profile the player is using right now.
+    fn physics_profile_mut(&mut self) -> &mut PhysicsProfile;
+}
+
+/// Registers `physics`.
+pub fn register_commands<C: PhysicsHost>(console: &mut Console<C>) {
+    console.register(
+        "physics",
+        "<field> <value> | profile <name> - tune player physics",
+        |host, args| match args {
+            ["profile", name] => {
+                if !host.select_physics_profile(name) {
+                    return Err(format!("unknown physics profile `{}`", name));
+                }
+                Ok(format!("physics profile {}", name))
+            }
+            [field, value] => {
+                let value = parse_number(value)?;
+                host.physics_profile_mut().set_field(field, value)?;
+                Ok(format!(
+                    "{} = {} until the profile is reapplied",
+                    field, value
+                ))
+            }
+            _ => Err("usage: physics <field> <value> | physics profile <name>".to_string()),
+        },
+    );
+}
+
+/// One row of the jump table. The entry with the highest `min_speed` not
+/// above the horizontal speed at take-off is used for the whole jump.
+#[derive(Debug, Clone, Copy)]
+pub struct JumpProfile {
+    pub min_speed: f64,
+    pub jump_power: f64,
+    pub hold_gravity: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhysicsProfile {
+    pub ground_acceleration: f64,
+    pub air_acceleration: f64,
+    pub max_walk_speed: f64,
+    pub max_run_speed: f64,
+    pub friction: f64,
+    pub gravity: f64,
+    pub max_fall_speed: f64,
+    pub jumps: Vec<JumpProfile>,
+}
+
+impl PhysicsProfile {
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let field = |name: &str| -> Result<f64, String> {
+            value
+                .get(name)
+                .and_then(Value::as_f64)
+                .ok_or_else(|| format!("missing or invalid field `{}`", name))
+        };
+
+        let mut jumps = Vec::new();
+        for jump in value
+            .get("jumps")
+            .and_then(Value::as_array)
+            .ok_or("missing or invalid field `jumps`")?
+        {
+            let jump_field = |name: &str| -> Result<f64, String> {
+                jump.get(name)
+                    .and_then(Value::as_f64)
+                    .ok_or_else(|| format!("missing or invalid jump field `{}`", name))
+            };
+            jumps.push(JumpProfile {
+                min_speed: jump_field("min_speed")?,
+                jump_power: jump_field("jump_power")?,
+                hold_gravity: jump_field("hold_gravity")?,
+            });
+        }
+        if jumps.is_empty() {
+            return Err("`jumps` must contain at least one entry".to_string());
+        }
+        jumps.sort_by(|a, b| a.min_speed.total_cmp(&b.min_speed));
+
+        Ok(Self {
+            ground_acceleration: field("ground_acceleration")?,
+            air_acceleration: field("air_acceleration")?,
+            max_walk_speed: field("max_walk_speed")?,
+            max_run_speed: field("max_run_speed")?,
+            friction: field("friction")?,
+            gravity: field("gravity")?,
+            max_fall_speed: field("max_fall_speed")?,
+            jumps,
+        })
+    }
+
+    /// Overrides a single scalar field by name, e.g. from the console.
+    pub fn set_field(&mut self, name: &str, value: f64) -> Result<(), String> {
+        let field = match name {
+            "ground_acceleration" => &mut self.ground_acceleration,
+            "air_acceleration" => &mut self.air_acceleration,
+            "max_walk_speed" => &mut self.max_walk_speed,
+            "max_run_speed" => &mut self.max_run_speed,
+            "friction" => &mut self.friction,
+            "gravity" => &mut self.gravity,
+            "max_fall_speed" => &mut self.max_fall_speed,
+            _ => return Err(format!("unknown physics field `{}`", name)),
+        };
+        *field = value;
+        Ok(())
+    }
+
+    pub fn jump_for_speed(&self, speed: f64) -> JumpProfile {
+        self.jumps
+            .iter()
+            .rev()
+            .find(|x| speed.abs() >= x.min_speed)
+            .unwrap_or(&self.jumps[0])
+            .to_owned()
+    }
+}
+
+impl Default for PhysicsProfile {
+    fn default() -> Self {
+        Self {
+            ground_acceleration: 0.5,
+            air_acceleration: 0.35,
+            max_walk_speed: 3.0,
+            max_run_speed: 5.0,
+            friction: 0.5,
+            gravity: 1.2,
+            max_fall_speed: 10.0,
+            jumps: vec![JumpProfile {
+                min_speed: 0.0,
+                jump_power: 10.0,
+                hold_gravity: 0.8,
+            }],
+        }
+    }
+}
+
+struct FormProfiles {
+    small: PhysicsProfile,
+    big: Option<PhysicsProfile>,
+}
+
+/// Named physics profiles loaded from a JSON file. In dev mode the asset
+/// watcher reloads them whenever the file changes on disk.
+pub struct PhysicsConfig {
+    path: PathBuf,
+    default_profile: String,
+    profiles: HashMap<String, FormProfiles>,
+}
+
+impl PhysicsConfig {
+    pub fn load(path: &Path) -> Result<Self, AssetError> {
+        let mut config = Self {
+            path: path.to_path_buf(),
+            default_profile: String::new(),
+            profiles: HashMap::new(),
+        };
+        config.reload()?;
+        Ok(config)
+    }
+
+    pub fn default_profile(&self) -> &str {
+        &self.default_profile
+    }
+
+    pub fn profile(&self, name: &str, form: PlayerForm) -> Option<&PhysicsProfile> {
+        let profiles = self.profiles.get(name)?;
+        match form {
+            PlayerForm::Small => Some(&profiles.small),
+            _ => Some(profiles.big.as_ref().unwrap_or(&profiles.small)),
+        }
+    }
+
+    /// Re-reads the file. The old profiles are kept when it fails to parse.
+    pub fn reload(&mut self) -> Result<(), AssetError> {
+        let json = read_json(&self.path)?;
+        let path = self.path.clone();
+        let error = |e: String| AssetError::Parse(path.clone(), e);
+
+        let mut profiles = HashMap::new();
+        for (name, forms) in json
+            .get("profiles")
+            .and_then(Value::as_object)
+            .ok_or_else(|| error("missing `profiles` object".to_string()))?
+        {
+            let small = forms
+                .get("small")
+                .ok_or_else(|| format!("profile `{}` has no `small` form", name))
+                .and_then(PhysicsProfile::from_json)
+                .map_err(|e| error(format!("profile `{}`: {}", name, e)))?;
+            let big = match forms.get("super") {
+                Some(value) => Some(
+                    PhysicsProfile::from_json(value)
+                        .map_err(|e| error(format!("profile `{}`: {}", name, e)))?,
+                ),
+                None => None,
+            };
+            profiles.insert(name.clone(), FormProfiles { small, big });
+        }
+
+        let default_profile = json
+            .get("default")
+            .and_then(Value::as_str)
+            .unwrap_or("classic")
+            .to_string();
+        if !profiles.contains_key(&default_profile) {
+            return Err(error(format!(
+                "default profile `{}` is not defined",
+                default_profile
+            )));
+        }
+
+        self.profiles = profiles;
+        self.default_profile = default_profile;
+        Ok(())
+    }
+}
 
 #[derive(Debug)]
 pub struct Physics {
     pub velocity: Vector2<f64>,
     pub on_ground: bool,
-    pub max_jump_timer: f64,
-    pub movement_speed: f64,
-    pub max_movement_speed: f64,
-    pub gravity: f64,
-    pub max_fall_speed: f64,
-    pub friction: f64,
-    pub jump_power: f64,
-    pub jump_timer: f64,
-    pub jump_duration: f64,
-    pub jump_threshold: f64,
+    pub profile: PhysicsProfile,
+    pub jump: Option<JumpProfile>,
 }
 
 impl Physics {
@@ -23,25 +245,21 @@ impl Physics {
         Self {
             velocity: Vector2::new(0.0, 0.0),
             on_ground: false,
-            max_jump_timer: 0.5, // Adjust as needed
-            movement_speed: 5.0,
-            max_movement_speed: 5.0,
-            gravity: 1.2,
-            max_fall_speed: 10.0,
-            friction: 0.5,
-            jump_power: 10.0,
-            jump_timer: 0.0,
-            jump_duration: 0.5,
-            jump_threshold: 1.2,
+            profile: PhysicsProfile::default(),
+            jump: None,
         }
     }
 
+    pub fn set_profile(&mut self, profile: PhysicsProfile) {
+        self.profile = profile;
+    }
+
     pub fn vel_x_is_almost_zero(&self, precision: f64) -> bool {
         self.velocity.x >= -precision && self.velocity.x <= precision
     }
 
-    pub fn update(&mut self, dt: f64, input: &Controller) {
-        self.velocity.y += self.gravity;
+    pub fn update(&mut self, input: &Controller) {
+        let profile = &self.profile;
 
         let mut movement_force = 0.0;
         if input.left {
@@ -50,30 +268,109 @@ impl Physics {
         if input.right {
             movement_force = 1.0;
         }
-        self.velocity.x += movement_force * self.movement_speed;
 
-        self.velocity.x = self
-            .velocity
-            .x
-            .clamp(-self.max_movement_speed, self.max_movement_speed);
+        let acceleration = if self.on_ground {
+            profile.ground_acceleration
+        } else {
+            profile.air_acceleration
+        };
+        self.velocity.x += movement_force * acceleration;
+
+        let max_speed = if input.run {
+            profile.max_run_speed
+        } else {
+            profile.max_walk_speed
+        };
+        if self.velocity.x.abs() > max_speed {
+            // Letting go of run bleeds speed off gradually instead of snapping
+            // down to walking speed.
+            let slowed = self.velocity.x.abs() - profile.friction;
+            self.velocity.x = slowed.max(max_speed) * self.velocity.x.signum();
+        }
 
         if input.jump && self.on_ground {
-            self.velocity.y = -self.jump_power;
+            let jump = profile.jump_for_speed(self.velocity.x);
+            self.velocity.y = -jump.jump_power;
             self.on_ground = false;
-            self.jump_timer = 0.0;
-        } else if input.jump && self.jump_timer < self.jump_duration {
-            self.velocity.y -= self.jump_power * self.jump_threshold * dt;
-            self.jump_timer += dt;
+            self.jump = Some(jump);
         }
 
+        let gravity = match self.jump {
+            Some(jump) if input.jump && self.velocity.y < 0.0 => jump.hold_gravity,
+            _ => profile.gravity,
+        };
+        self.velocity.y += gravity;
+
         if self.on_ground {
-            if self.velocity.x.abs() <= self.friction {
-                self.velocity.x = 0.0;
-            } else {
-                self.velocity.x -= (1.0 - self.friction) * self.velocity.x.signum();
+            self.jump = None;
+            if movement_force == 0.0 {
+                if self.velocity.x.abs() <= profile.friction {
+                    self.velocity.x = 0.0;
+                } else {
+                    self.velocity.x -= profile.friction * self.velocity.x.signum();
+                }
             }
         }
 
-        self.velocity.y = self.velocity.y.min(self.max_fall_speed);
+        self.velocity.y = self.velocity.y.min(profile.max_fall_speed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn profile_json(jumps: Value) -> Value {
+        json!({
+            "ground_acceleration": 0.5,
+            "air_acceleration": 0.35,
+            "max_walk_speed": 3.0,
+            "max_run_speed": 5.0,
+            "friction": 0.5,
+            "gravity": 1.2,
+            "max_fall_speed": 10.0,
+            "jumps": jumps,
+        })
+    }
+
+    #[test]
+    fn jumps_are_picked_by_take_off_speed() {
+        let profile = PhysicsProfile::from_json(&profile_json(json!([
+            { "min_speed": 4.5, "jump_power": 11.0, "hold_gravity": 0.9 },
+            { "min_speed": 0.0, "jump_power": 10.0, "hold_gravity": 0.8 },
+            { "min_speed": 2.5, "jump_power": 10.5, "hold_gravity": 0.75 },
+        ])))
+        .unwrap();
+        assert_eq!(profile.jump_for_speed(0.0).jump_power, 10.0);
+        assert_eq!(profile.jump_for_speed(-3.0).jump_power, 10.5);
+        assert_eq!(profile.jump_for_speed(4.5).jump_power, 11.0);
+    }
+
+    #[test]
+    fn rejects_bad_profiles() {
+        assert!(PhysicsProfile::from_json(&profile_json(json!([]))).is_err());
+        let mut value = profile_json(json!([
+            { "min_speed": 0.0, "jump_power": 10.0, "hold_gravity": 0.8 }
+        ]));
+        value["gravity"] = json!("heavy");
+        let error = PhysicsProfile::from_json(&value).unwrap_err();
+        assert!(error.contains("gravity"), "{}", error);
+    }
+
+    #[test]
+    fn console_fields_are_checked() {
+        let mut profile = PhysicsProfile::default();
+        profile.set_field("gravity", 0.8).unwrap();
+        assert_eq!(profile.gravity, 0.8);
+        assert!(profile.set_field("jumps", 1.0).is_err());
+    }
+
+    #[test]
+    fn shipped_profiles_load() {
+        let config = PhysicsConfig::load(Path::new("assets/physics.json")).unwrap();
+        let name = config.default_profile();
+        assert!(config.profile(name, PlayerForm::Small).is_some());
+        assert!(config.profile(name, PlayerForm::Fire).is_some());
     }
 }
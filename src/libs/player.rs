@@ -1,25 +1,71 @@
+use cgmath::Vector2;
 use graphics::math::Matrix2d;
 use graphics::{Graphics, Transformed};
-use piston_window::{ButtonState, ImageSize, Key, Size};
+use piston_window::{ImageSize, Size};
+use serde_json::{json, Value};
 
 use super::collider::Side;
 use super::{
     collider::Collision,
+    console::{parse_number, Console},
     controller::Controller,
+    input::Action,
     object::{Object, Object2D},
-    physics::Physics,
-    sprites_manager::SpriteManager,
-    spritesheet::{SpriteSheet, SpriteSheetConfig},
+    physics::{JumpProfile, Physics, PhysicsProfile},
+    snapshot::{self, read_transform, write_transform},
+    sprites_manager::{AnimationEvent, AnimationSnapshot, SpriteDefinitions, SpriteManager},
+    spritesheet::SpriteSheet,
     transform::{Rect, Trans, Transform},
 };
 
+/// Hosts with a player the console can move and power up.
+pub trait PlayerHost {
+    type Texture: ImageSize;
+
+    fn player_mut(&mut self) -> &mut Player<Self::Texture>;
+    /// Called after a command changes the player's form.
+    fn form_changed(&mut self);
+}
+
+/// Registers `tp`, `power` and `god`.
+pub fn register_commands<C: PlayerHost>(console: &mut Console<C>) {
+    console.register("tp", "<x> <y> - teleport the player", |host, args| {
+        let (x, y) = match args {
+            [x, y] => (parse_number(x)?, parse_number(y)?),
+            _ => return Err("usage: tp <x> <y>".to_string()),
+        };
+        host.player_mut().set_position(x, y);
+        Ok(format!("teleported to {} {}", x, y))
+    });
+    console.register(
+        "power",
+        "<small|super|fire> - set the power-up state",
+        |host, args| {
+            let form = match args {
+                [name] => PlayerForm::from_name(name),
+                _ => None,
+            }
+            .ok_or("usage: power <small|super|fire>")?;
+            host.player_mut().set_form(form);
+            host.form_changed();
+            Ok(format!("player is now {:?}", form))
+        },
+    );
+    console.register("god", "- toggle invulnerability", |host, _| {
+        let player = host.player_mut();
+        let god_mode = !player.is_god_mode();
+        player.set_god_mode(god_mode);
+        Ok(format!("god mode {}", if god_mode { "on" } else { "off" }))
+    });
+}
+
 #[derive(PartialEq)]
 pub enum PlayerDirection {
     Left,
     Right,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum PlayerState {
     Idle,
     Walk,
@@ -28,32 +74,177 @@ pub enum PlayerState {
     Crouch,
     Fall,
     Skid,
+    Climb,
+}
+
+impl PlayerState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayerState::Idle => "idle",
+            PlayerState::Walk => "walk",
+            PlayerState::Run => "run",
+            PlayerState::Jump => "jump",
+            PlayerState::Crouch => "crouch",
+            PlayerState::Fall => "fall",
+            PlayerState::Skid => "skid",
+            PlayerState::Climb => "climb",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "idle" => Some(PlayerState::Idle),
+            "walk" => Some(PlayerState::Walk),
+            "run" => Some(PlayerState::Run),
+            "jump" => Some(PlayerState::Jump),
+            "crouch" => Some(PlayerState::Crouch),
+            "fall" => Some(PlayerState::Fall),
+            "skid" => Some(PlayerState::Skid),
+            "climb" => Some(PlayerState::Climb),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PlayerForm {
+    Small,
+    Super,
+    Fire,
+}
+
+impl PlayerForm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayerForm::Small => "small",
+            PlayerForm::Super => "super",
+            PlayerForm::Fire => "fire",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "small" => Some(PlayerForm::Small),
+            "super" => Some(PlayerForm::Super),
+            "fire" => Some(PlayerForm::Fire),
+            _ => None,
+        }
+    }
+}
+
+/// A player snapshot read back and checked, so restoring it can't fail
+/// half way.
+pub struct PlayerSnapshot {
+    transform: Transform,
+    velocity: Vector2<f64>,
+    on_ground: bool,
+    jump: Option<JumpProfile>,
+    state: PlayerState,
+    form: PlayerForm,
+    facing_left: bool,
+    invulnerable: f64,
+    star: f64,
+    star_ticks: u32,
+    input_enabled: bool,
+    visible: bool,
+    input: u8,
+    animation: Option<AnimationSnapshot>,
+}
+
+impl PlayerSnapshot {
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let state = snapshot::text(json, "state")?;
+        let state =
+            PlayerState::from_name(state).ok_or_else(|| format!("unknown state `{}`", state))?;
+        let form = snapshot::text(json, "form")?;
+        let form = PlayerForm::from_name(form).ok_or_else(|| format!("unknown form `{}`", form))?;
+        let jump = match json.get("jump") {
+            Some(Value::Null) | None => None,
+            Some(jump) => match jump.as_array().map(|x| x.as_slice()) {
+                Some([min_speed, jump_power, hold_gravity]) => Some(JumpProfile {
+                    min_speed: min_speed.as_f64().ok_or("invalid `jump`")?,
+                    jump_power: jump_power.as_f64().ok_or("invalid `jump`")?,
+                    hold_gravity: hold_gravity.as_f64().ok_or("invalid `jump`")?,
+                }),
+                _ => return Err("invalid `jump`".to_string()),
+            },
+        };
+        let (vx, vy) = snapshot::pair(json, "velocity")?;
+        Ok(Self {
+            transform: read_transform(json, "transform")?,
+            velocity: Vector2::new(vx, vy),
+            on_ground: snapshot::flag(json, "on_ground")?,
+            jump,
+            state,
+            form,
+            facing_left: snapshot::flag(json, "facing_left")?,
+            invulnerable: snapshot::number(json, "invulnerable")?,
+            star: snapshot::number(json, "star")?,
+            star_ticks: snapshot::number(json, "star_ticks")? as u32,
+            input_enabled: snapshot::flag(json, "input_enabled")?,
+            visible: snapshot::flag(json, "visible")?,
+            // Older snapshots didn't keep the held buttons.
+            input: json.get("input").and_then(Value::as_u64).unwrap_or(0) as u8,
+            animation: AnimationSnapshot::read(json)?,
+        })
+    }
 }
 
 pub struct Player<I: ImageSize> {
     sprites: SpriteManager<I>,
     physics: Physics,
     state: PlayerState,
+    form: PlayerForm,
     transform: Transform,
     direction: PlayerDirection,
     input: Controller,
+    animation_events: Vec<AnimationEvent>,
+    contacts: Vec<Side>,
+    god_mode: bool,
+    invulnerable: f64,
+    star: f64,
+    star_ticks: u32,
+    input_enabled: bool,
+    visible: bool,
+    /// The character's palette, used unless a power-up changes the colors.
+    base_palette: usize,
 }
 
+const HURT_INVULNERABLE_TIME: f64 = 2.0;
+const STOMP_BOUNCE: f64 = 8.0;
+
+/// Rows of the player sheet holding each color set of the frames.
+const FIRE_PALETTE: usize = 2;
+const STAR_PALETTES: [usize; 3] = [3, 4, 5];
+/// The star palette cycle slows down over its last seconds as a warning.
+const STAR_WARNING_TIME: f64 = 2.0;
+const STAR_WARNING_TICKS: u32 = 4;
+
 impl<I> Player<I>
 where
     I: ImageSize,
 {
-    pub fn new(player_sprite_sheet: SpriteSheet<I>) -> Player<I> {
+    pub fn new(player_sprite_sheet: SpriteSheet<I>, definitions: SpriteDefinitions) -> Player<I> {
         let mut player = Player {
             sprites: SpriteManager::new(),
             physics: Physics::new(),
             state: PlayerState::Jump,
+            form: PlayerForm::Small,
             transform: Transform::new(),
             direction: PlayerDirection::Right,
             input: Controller::new(),
+            animation_events: Vec::new(),
+            contacts: Vec::new(),
+            god_mode: false,
+            invulnerable: 0.0,
+            star: 0.0,
+            star_ticks: 0,
+            input_enabled: true,
+            visible: true,
+            base_palette: 0,
         };
         player.set_sprite_sheet(player_sprite_sheet);
-        player.set_animation();
+        player.set_definitions(definitions);
         player.set_position(20.0, 20.0);
 
         player
@@ -63,22 +254,238 @@ where
         self.sprites.set_spritesheet(sprite_sheet);
     }
 
-    pub fn set_animation(&mut self) {
-        self.add_animation("idle", vec![[0, 0]]);
-        self.add_animation("jump", vec![[0, 5]]);
-        self.add_animation("walk", vec![[0, 1], [0, 2], [0, 3]]);
+    /// Swaps in reloaded definitions, keeping the sprite config of the
+    /// current form rather than the file's default.
+    pub fn set_definitions(&mut self, definitions: SpriteDefinitions) {
+        self.sprites.set_definitions(definitions);
+        self.set_form(self.form);
+    }
+
+    pub fn get_form(&self) -> PlayerForm {
+        self.form
+    }
+
+    /// Switches form, picking the matching sprite config and resizing the
+    /// hitbox so the feet stay where they were. The caller is expected to
+    /// apply the physics profile for the new form.
+    pub fn set_form(&mut self, form: PlayerForm) {
+        self.form = form;
+        let (config, height) = match form {
+            PlayerForm::Small => ("small", 16.0),
+            PlayerForm::Super | PlayerForm::Fire => ("super", 32.0),
+        };
+        self.sprites.set_current_config(config);
+        self.update_palette();
+        self.sprites.apply_current_frame();
+
+        let size = self.transform.get_size();
+        self.transform.translate_y(size.height - height);
+        self.transform.set_size(size.width, height);
+    }
+
+    pub fn set_physics_profile(&mut self, profile: PhysicsProfile) {
+        self.physics.set_profile(profile);
+    }
+
+    /// The active profile, for tweaking values at runtime.
+    pub fn physics_profile_mut(&mut self) -> &mut PhysicsProfile {
+        &mut self.physics.profile
+    }
+
+    pub fn is_god_mode(&self) -> bool {
+        self.god_mode
+    }
+
+    pub fn set_god_mode(&mut self, value: bool) {
+        self.god_mode = value;
+    }
+
+    /// Makes the player invincible for `time` seconds.
+    pub fn start_star(&mut self, time: f64) {
+        self.star = time;
+        self.star_ticks = 0;
+    }
+
+    pub fn is_star(&self) -> bool {
+        self.star > 0.0
+    }
+
+    /// Powers up to a bigger `form`, holding the player still while the
+    /// `grow` clip plays. Definitions without one grow at once.
+    pub fn grow(&mut self, form: PlayerForm) {
+        self.set_form(form);
+        self.stop();
+        self.sprites.force_animation("grow");
+    }
+
+    pub fn is_growing(&self) -> bool {
+        self.sprites.current_animation() == Some("grow")
+    }
+
+    /// Handles an enemy hit. Big forms shrink and get a moment of
+    /// invulnerability; returns `true` if the hit was fatal.
+    pub fn hurt(&mut self) -> bool {
+        if self.god_mode || self.invulnerable > 0.0 || self.is_star() || self.is_growing() {
+            return false;
+        }
+        if self.form == PlayerForm::Small {
+            return true;
+        }
+        self.set_form(PlayerForm::Small);
+        self.invulnerable = HURT_INVULNERABLE_TIME;
+        false
+    }
+
+    /// Pops the player up after stomping an enemy.
+    pub fn bounce(&mut self) {
+        self.physics.velocity.y = -STOMP_BOUNCE;
+    }
+
+    pub fn stop(&mut self) {
+        self.physics.velocity = Vector2::new(0.0, 0.0);
+    }
+
+    /// Ignores the keyboard during cutscenes, releasing anything held.
+    pub fn set_input_enabled(&mut self, value: bool) {
+        self.input_enabled = value;
+        self.clear_input();
     }
 
-    pub fn add_animation(&mut self, name: &'static str, animations: Vec<[usize; 2]>) {
-        self.sprites.add_animation(name, animations);
+    /// Shows the death frame, dropping any power-up, star or hurt blink.
+    pub fn die(&mut self) {
+        self.set_form(PlayerForm::Small);
+        self.stop();
+        self.set_input_enabled(false);
+        self.star = 0.0;
+        self.invulnerable = 0.0;
+        self.update_palette();
+        self.sprites.force_animation("death");
     }
 
-    pub fn add_config(&mut self, name: &'static str, config: SpriteSheetConfig) {
-        self.sprites.add_config(name, config);
+    /// Puts the player back on their feet, ready for input, after a death
+    /// or a level change.
+    pub fn revive(&mut self) {
+        self.stop();
+        self.set_input_enabled(true);
+        self.set_visible(true);
+        self.state = PlayerState::Idle;
+        self.sprites.force_animation("idle");
     }
 
-    pub fn set_current_config(&mut self, name: &'static str) {
-        self.sprites.set_current_config(name);
+    pub fn set_visible(&mut self, value: bool) {
+        self.visible = value;
+    }
+
+    /// Moves down a pole or vine by `dy` without running physics.
+    pub fn climb(&mut self, dy: f64, dt: f64) {
+        self.stop();
+        self.state = PlayerState::Climb;
+        self.transform.translate_y(dy);
+        self.update_animation(dt);
+    }
+
+    /// Moves into or out of a pipe without physics or collisions.
+    pub fn pipe_move(&mut self, dx: f64, dy: f64, dt: f64) {
+        self.stop();
+        self.physics.on_ground = true;
+        self.state = if dx != 0.0 {
+            PlayerState::Walk
+        } else {
+            PlayerState::Idle
+        };
+        self.transform.translate(dx, dy);
+        self.update_animation(dt);
+    }
+
+    /// Holds right as if on the keyboard, for scripted walks.
+    pub fn walk_right(&mut self) {
+        self.clear_input();
+        self.input.right = true;
+        self.direction = PlayerDirection::Right;
+        self.state = PlayerState::Walk;
+    }
+
+    /// Strikes a recorded pose, for a puppet like a race ghost that isn't
+    /// driven by physics. It counts as on the ground unless in the air.
+    pub fn set_pose(&mut self, state: PlayerState, facing_left: bool) {
+        self.state = state;
+        self.direction = match facing_left {
+            true => PlayerDirection::Left,
+            false => PlayerDirection::Right,
+        };
+        self.physics.on_ground = !matches!(state, PlayerState::Jump | PlayerState::Fall);
+    }
+
+    pub fn set_opacity(&mut self, opacity: f32) {
+        if let Some(sprite) = self.sprites.get_sprite() {
+            sprite.set_opacity(opacity);
+        }
+    }
+
+    /// Switches to another character's colors.
+    pub fn set_base_palette(&mut self, palette: usize) {
+        self.base_palette = palette;
+        self.update_palette();
+    }
+
+    /// Releases every held input, e.g. when the console takes the keyboard.
+    pub fn clear_input(&mut self) {
+        self.input = Controller::new();
+    }
+
+    /// Everything about the player that changes during play. The physics
+    /// profile comes from the config and is left out.
+    pub fn snapshot(&self) -> Value {
+        let jump = self
+            .physics
+            .jump
+            .map(|x| json!([x.min_speed, x.jump_power, x.hold_gravity]));
+        json!({
+            "transform": write_transform(&self.transform),
+            "velocity": [self.physics.velocity.x, self.physics.velocity.y],
+            "on_ground": self.physics.on_ground,
+            "jump": jump,
+            "state": self.state.name(),
+            "form": self.form.name(),
+            "facing_left": self.direction == PlayerDirection::Left,
+            "invulnerable": self.invulnerable,
+            "star": self.star,
+            "star_ticks": self.star_ticks,
+            "input_enabled": self.input_enabled,
+            "visible": self.visible,
+            "input": self.input.bits(),
+            "animation": self.sprites.animation().to_json(),
+        })
+    }
+
+    /// Puts the player back the way a checked snapshot found them.
+    pub fn restore(&mut self, snapshot: &PlayerSnapshot) {
+        self.set_form(snapshot.form);
+        self.transform = snapshot.transform;
+        self.physics.velocity = snapshot.velocity;
+        self.physics.on_ground = snapshot.on_ground;
+        self.physics.jump = snapshot.jump;
+        self.state = snapshot.state;
+        self.direction = match snapshot.facing_left {
+            true => PlayerDirection::Left,
+            false => PlayerDirection::Right,
+        };
+        self.invulnerable = snapshot.invulnerable;
+        self.star = snapshot.star;
+        self.star_ticks = snapshot.star_ticks;
+        self.input_enabled = snapshot.input_enabled;
+        self.visible = snapshot.visible;
+        self.contacts.clear();
+        self.animation_events.clear();
+        self.input = Controller::from_bits(snapshot.input);
+        self.update_palette();
+        match &snapshot.animation {
+            Some(animation) => self.sprites.restore_animation(animation),
+            None => self.sprites.force_animation("idle"),
+        }
+        if let Some(sprite) = self.sprites.get_sprite() {
+            sprite.set_flip_x(self.transform.is_flip_x());
+        }
     }
 
     pub fn set_inside_window(&mut self, size: Size) {
@@ -96,9 +503,32 @@ where
         &self.transform
     }
 
+    pub fn get_state(&self) -> &PlayerState {
+        &self.state
+    }
+
+    pub fn get_input(&self) -> &Controller {
+        &self.input
+    }
+
+    pub fn get_velocity(&self) -> Vector2<f64> {
+        self.physics.velocity
+    }
+
+    /// Sides touched by solid objects since the last update.
+    pub fn get_contacts(&self) -> &[Side] {
+        &self.contacts
+    }
+
     pub fn collide_with(&mut self, obj: &Object<I>) {
         let (collide, side) = Collision::aabb(&self.transform, &obj.get_transform());
 
+        if let (true, Some(side)) = (collide, side) {
+            if !self.contacts.contains(&side) {
+                self.contacts.push(side);
+            }
+        }
+
         if collide {
             match side {
                 Some(Side::RIGHT) => {
@@ -139,11 +569,15 @@ where
             PlayerState::Idle => self.sprites.play_animation("idle"),
             PlayerState::Walk => {
                 if self.physics.on_ground {
-                    self.sprites.play_animation("walk")
+                    self.sprites.play_animation("walk");
+                    let walk_speed =
+                        self.physics.velocity.x.abs() / self.physics.profile.max_walk_speed;
+                    self.sprites.set_animation_speed(walk_speed.clamp(0.5, 2.5));
                 }
             }
             PlayerState::Run => self.sprites.play_animation("run"),
             PlayerState::Jump => self.sprites.play_animation("jump"),
+            PlayerState::Climb => self.sprites.play_animation("climb"),
             _ => {}
         }
 
@@ -151,23 +585,79 @@ where
             sprite.set_flip_x(self.transform.is_flip_x());
         }
 
+        self.animate(dt);
+    }
+
+    /// Advances the current clip without picking one from the state, e.g.
+    /// for the death frame.
+    pub fn animate(&mut self, dt: f64) {
         self.sprites.update(dt);
+        self.animation_events = self.sprites.drain_events();
+    }
+
+    /// Whether the named one-shot clip finished during the last update.
+    pub fn animation_finished(&self, name: &str) -> bool {
+        self.animation_events
+            .iter()
+            .any(|x| matches!(x, AnimationEvent::Finished(n) if n == name))
     }
 
-    pub fn update_input(&mut self, key: Key, state: ButtonState) {
-        if state == ButtonState::Press {
-            if key == Key::Left {
+    pub fn current_animation(&self) -> Option<&str> {
+        self.sprites.current_animation()
+    }
+
+    pub fn update_input(&mut self, action: Action, pressed: bool) {
+        if !self.input_enabled {
+            return;
+        }
+        if pressed {
+            if action == Action::Left {
                 self.direction = PlayerDirection::Left;
                 self.state = PlayerState::Walk;
             }
 
-            if key == Key::Right {
+            if action == Action::Right {
                 self.direction = PlayerDirection::Right;
                 self.state = PlayerState::Walk;
             }
         }
 
-        self.input.keyboard_event(key, state)
+        self.input.action_event(action, pressed)
+    }
+
+    /// Presses and releases whatever differs from `bits`, the held buttons
+    /// in `Controller::bits` form, as netplay hands them out every frame.
+    pub fn set_input_bits(&mut self, bits: u8) {
+        let held = Controller::from_bits(bits);
+        for action in Controller::ACTIONS {
+            if held.is_held(action) != self.input.is_held(action) {
+                self.update_input(action, held.is_held(action));
+            }
+        }
+    }
+
+    /// Picks the form's colors, or the star palette for the current tick
+    /// while invincible. Only reads the star state, so calling it again
+    /// doesn't move the cycle on.
+    fn update_palette(&mut self) {
+        let form_palette = match self.form {
+            PlayerForm::Fire => FIRE_PALETTE,
+            _ => self.base_palette,
+        };
+        let palette = if self.is_star() {
+            let rate = if self.star < STAR_WARNING_TIME {
+                STAR_WARNING_TICKS
+            } else {
+                1
+            };
+            match (self.star_ticks / rate) as usize % (STAR_PALETTES.len() + 1) {
+                0 => form_palette,
+                i => STAR_PALETTES[i - 1],
+            }
+        } else {
+            form_palette
+        };
+        self.sprites.set_palette(palette);
     }
 
     fn update_state(&mut self) {
@@ -185,6 +675,10 @@ where
     I: ImageSize,
 {
     fn draw<B: Graphics<Texture = I>>(&mut self, t: Matrix2d, b: &mut B) {
+        // Blink while invulnerable after a hit.
+        if !self.visible || (self.invulnerable * 20.0) as u32 % 2 == 1 {
+            return;
+        }
         let transformed = t.trans(
             self.transform.get_position().x,
             self.transform.get_position().y,
@@ -194,10 +688,146 @@ where
     }
 
     fn update(&mut self, dt: f64) {
-        self.physics.update(dt, &self.input);
+        self.invulnerable = (self.invulnerable - dt).max(0.0);
+        self.star = (self.star - dt).max(0.0);
+        if self.is_star() {
+            self.star_ticks += 1;
+        }
+        self.update_palette();
+        self.contacts.clear();
+        if self.is_growing() {
+            self.animate(dt);
+            return;
+        }
+        self.physics.update(&self.input);
         self.update_state();
         self.update_animation(dt);
+        // Ground contact is re-established by collisions every tick, so
+        // walking off a ledge switches to air control.
+        self.physics.on_ground = false;
         self.transform
             .translate(self.physics.velocity.x, self.physics.velocity.y);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    /// Stands in for a GPU texture; only its size is ever asked for.
+    struct TestTexture;
+
+    impl ImageSize for TestTexture {
+        fn get_size(&self) -> (u32, u32) {
+            (512, 512)
+        }
+    }
+
+    fn player() -> Player<TestTexture> {
+        let definitions =
+            SpriteDefinitions::load(Path::new("assets/player_animations.json")).unwrap();
+        Player::new(SpriteSheet::new(Rc::new(TestTexture)), definitions)
+    }
+
+    fn snapshot() -> Value {
+        let mut transform = Transform::new();
+        transform.set_size(16.0, 16.0);
+        json!({
+            "transform": write_transform(&transform),
+            "velocity": [1.0, -2.0],
+            "on_ground": false,
+            "jump": [2.5, 10.0, 0.75],
+            "state": "jump",
+            "form": "super",
+            "facing_left": true,
+            "invulnerable": 0.0,
+            "star": 3.0,
+            "star_ticks": 12,
+            "input_enabled": true,
+            "visible": true,
+            "input": 5,
+        })
+    }
+
+    #[test]
+    fn reads_player_snapshots() {
+        let player = PlayerSnapshot::from_json(&snapshot()).unwrap();
+        assert_eq!(player.form, PlayerForm::Super);
+        assert_eq!(player.state, PlayerState::Jump);
+        assert_eq!(player.jump.unwrap().jump_power, 10.0);
+        assert_eq!(player.input, 5);
+
+        let mut old = snapshot();
+        old["jump"] = Value::Null;
+        old.as_object_mut().unwrap().remove("input");
+        let player = PlayerSnapshot::from_json(&old).unwrap();
+        assert!(player.jump.is_none());
+        assert_eq!(player.input, 0);
+    }
+
+    #[test]
+    fn rejects_bad_player_snapshots() {
+        let changes = [
+            ("form", json!("giant")),
+            ("state", json!("fly")),
+            ("jump", json!([1.0, 2.0])),
+            ("velocity", json!(3.0)),
+            ("on_ground", Value::Null),
+        ];
+        for (name, value) in changes {
+            let mut json = snapshot();
+            json[name] = value;
+            assert!(PlayerSnapshot::from_json(&json).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn star_player_round_trips() {
+        let mut original = player();
+        original.start_star(10.0);
+        for _ in 0..5 {
+            original.update(1.0 / 30.0);
+        }
+        let json = original.snapshot();
+        let snapshot = PlayerSnapshot::from_json(&json).unwrap();
+
+        let mut restored = player();
+        restored.restore(&snapshot);
+        assert_eq!(restored.snapshot(), json);
+        original.restore(&snapshot);
+        assert_eq!(original.snapshot(), json);
+    }
+
+    #[test]
+    fn grow_holds_the_player_until_its_clip_finishes() {
+        let mut player = player();
+        player.grow(PlayerForm::Super);
+        let start = *player.get_transform();
+        let mut ticks = 0;
+        while player.is_growing() {
+            assert!(!player.hurt());
+            player.update(0.05);
+            ticks += 1;
+        }
+        assert_eq!(ticks, 12);
+        assert!(player.animation_finished("grow"));
+        assert_eq!(player.current_animation(), Some("idle"));
+        assert_eq!(player.get_transform().get_position(), start.get_position());
+        assert_eq!(player.get_form(), PlayerForm::Super);
+    }
+
+    #[test]
+    fn death_clip_finishes_once() {
+        let mut player = player();
+        player.die();
+        player.animate(0.25);
+        assert!(!player.animation_finished("death"));
+        player.animate(0.25);
+        assert!(player.animation_finished("death"));
+        player.animate(0.25);
+        assert!(!player.animation_finished("death"));
+        assert_eq!(player.current_animation(), Some("death"));
+    }
+}
@@ -0,0 +1,78 @@
+use serde_json::Value;
+use std::collections::VecDeque;
+
+/// Ring buffer of per-tick game snapshots, newest last. Stepping back drops
+/// the newest and hands out the one before it.
+pub struct Rewind {
+    frames: VecDeque<Value>,
+    capacity: usize,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds the latest tick, forgetting the oldest once full.
+    pub fn record(&mut self, frame: Value) {
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    /// The tick before the latest one. The oldest tick is never dropped, so
+    /// holding rewind at the start of the buffer stays there.
+    pub fn step_back(&mut self) -> Option<&Value> {
+        if self.frames.len() > 1 {
+            self.frames.pop_back();
+        }
+        self.frames.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn steps_back_one_tick_at_a_time() {
+        let mut rewind = Rewind::new(10);
+        assert!(rewind.step_back().is_none());
+        for tick in 0..3 {
+            rewind.record(json!(tick));
+        }
+        assert_eq!(rewind.step_back(), Some(&json!(1)));
+        assert_eq!(rewind.step_back(), Some(&json!(0)));
+        assert_eq!(rewind.step_back(), Some(&json!(0)));
+        rewind.record(json!(5));
+        assert_eq!(rewind.step_back(), Some(&json!(0)));
+    }
+
+    #[test]
+    fn forgets_the_oldest_tick_when_full() {
+        let mut rewind = Rewind::new(3);
+        for tick in 0..5 {
+            rewind.record(json!(tick));
+        }
+        assert_eq!(rewind.step_back(), Some(&json!(3)));
+        assert_eq!(rewind.step_back(), Some(&json!(2)));
+        assert_eq!(rewind.step_back(), Some(&json!(2)));
+    }
+
+    #[test]
+    fn clearing_starts_over() {
+        let mut rewind = Rewind::new(3);
+        rewind.record(json!(0));
+        rewind.clear();
+        assert!(rewind.step_back().is_none());
+    }
+}
@@ -0,0 +1,345 @@
+use serde_json::{json, Value};
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::assets::{read_json, AssetError};
+use super::ghost::GhostRun;
+use super::player::PlayerForm;
+use super::snapshot::SNAPSHOT_VERSION;
+
+/// Written into every save file. Files from a newer version are refused;
+/// fields added later must have defaults so older files still load.
+const SAVE_VERSION: u64 = 1;
+pub const SLOT_COUNT: usize = 3;
+const HIGH_SCORE_COUNT: usize = 10;
+
+/// A game in progress, saved when a level is finished.
+pub struct SaveData {
+    /// Id of the level to continue from.
+    pub world: String,
+    pub lives: u32,
+    pub score: u32,
+    pub coins: u32,
+    pub form: PlayerForm,
+}
+
+impl SaveData {
+    fn from_json(json: &Value) -> Result<Self, String> {
+        let number = |name: &str| {
+            json.get(name)
+                .and_then(Value::as_u64)
+                .map(|x| x as u32)
+                .ok_or_else(|| format!("missing or invalid `{}`", name))
+        };
+        let form = json.get("form").and_then(Value::as_str).unwrap_or("small");
+        Ok(Self {
+            world: json
+                .get("world")
+                .and_then(Value::as_str)
+                .ok_or("missing or invalid `world`")?
+                .to_string(),
+            lives: number("lives")?,
+            score: number("score")?,
+            coins: number("coins")?,
+            form: PlayerForm::from_name(form).ok_or_else(|| format!("unknown form `{}`", form))?,
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "version": SAVE_VERSION,
+            "world": self.world,
+            "lives": self.lives,
+            "score": self.score,
+            "coins": self.coins,
+            "form": self.form.name(),
+        })
+    }
+}
+
+pub struct HighScore {
+    pub score: u32,
+    /// Id of the level the run ended in.
+    pub world: String,
+}
+
+/// Save slots and the high-score table, kept as JSON files in the user's
+/// data directory.
+pub struct SaveStore {
+    dir: PathBuf,
+}
+
+impl SaveStore {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// `$XDG_DATA_HOME`, `~/.local/share` or `%APPDATA%`, whichever is set
+    /// first, with a folder for the game in it.
+    pub fn data_dir() -> PathBuf {
+        let base = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|x| Path::new(&x).join(".local/share")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from("."));
+        base.join("super-mario-bros")
+    }
+
+    fn slot_path(&self, slot: usize) -> PathBuf {
+        self.dir.join(format!("slot{}.json", slot + 1))
+    }
+
+    fn high_scores_path(&self) -> PathBuf {
+        self.dir.join("highscores.json")
+    }
+
+    fn quick_save_path(&self) -> PathBuf {
+        self.dir.join("quicksave.json")
+    }
+
+    /// One file per level under `ghosts`, named after the level id.
+    fn ghost_path(&self, level: &str) -> PathBuf {
+        let name: String = level
+            .chars()
+            .map(|x| {
+                if x.is_ascii_alphanumeric() || x == '-' {
+                    x
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join("ghosts").join(format!("{}.json", name))
+    }
+
+    /// The best run recorded for `level`, if there is one.
+    pub fn load_ghost(&self, level: &str) -> Result<Option<GhostRun>, AssetError> {
+        let path = self.ghost_path(level);
+        match read_versioned(&path, SAVE_VERSION)? {
+            Some(json) => GhostRun::from_json(&json)
+                .map(Some)
+                .map_err(|e| AssetError::Parse(path, e)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save_ghost(&self, level: &str, run: &GhostRun) -> Result<(), AssetError> {
+        let mut json = run.to_json();
+        json["version"] = json!(SAVE_VERSION);
+        write_atomic(&self.ghost_path(level), &json)
+    }
+
+    /// Keeps a full snapshot of the game for quick loading.
+    pub fn save_snapshot(&self, snapshot: &Value) -> Result<(), AssetError> {
+        write_atomic(&self.quick_save_path(), snapshot)
+    }
+
+    pub fn load_snapshot(&self) -> Result<Option<Value>, AssetError> {
+        read_versioned(&self.quick_save_path(), SNAPSHOT_VERSION)
+    }
+
+    pub fn load_slot(&self, slot: usize) -> Result<Option<SaveData>, AssetError> {
+        let path = self.slot_path(slot);
+        match read_versioned(&path, SAVE_VERSION)? {
+            Some(json) => SaveData::from_json(&json)
+                .map(Some)
+                .map_err(|e| AssetError::Parse(path, e)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save_slot(&self, slot: usize, data: &SaveData) -> Result<(), AssetError> {
+        write_atomic(&self.slot_path(slot), &data.to_json())
+    }
+
+    pub fn clear_slot(&self, slot: usize) -> Result<(), AssetError> {
+        let path = self.slot_path(slot);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(AssetError::Io(path, e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The table from best to worst.
+    pub fn high_scores(&self) -> Result<Vec<HighScore>, AssetError> {
+        let path = self.high_scores_path();
+        let json = match read_versioned(&path, SAVE_VERSION)? {
+            Some(json) => json,
+            None => return Ok(Vec::new()),
+        };
+        let error = |e: &str| AssetError::Parse(path.clone(), e.to_string());
+        json.get("scores")
+            .and_then(Value::as_array)
+            .ok_or_else(|| error("missing `scores`"))?
+            .iter()
+            .map(|x| {
+                Some(HighScore {
+                    score: x.get("score").and_then(Value::as_u64)? as u32,
+                    world: x.get("world").and_then(Value::as_str)?.to_string(),
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| error("invalid entry in `scores`"))
+    }
+
+    /// Adds `entry` to the table if it beats one of the scores there, and
+    /// returns its place.
+    pub fn add_high_score(&self, entry: HighScore) -> Result<Option<usize>, AssetError> {
+        let mut scores = self.high_scores()?;
+        let place = scores
+            .iter()
+            .position(|x| entry.score > x.score)
+            .unwrap_or(scores.len());
+        if place >= HIGH_SCORE_COUNT || entry.score == 0 {
+            return Ok(None);
+        }
+        scores.insert(place, entry);
+        scores.truncate(HIGH_SCORE_COUNT);
+        let scores: Vec<Value> = scores
+            .iter()
+            .map(|x| json!({ "score": x.score, "world": x.world }))
+            .collect();
+        let json = json!({ "version": SAVE_VERSION, "scores": scores });
+        write_atomic(&self.high_scores_path(), &json)?;
+        Ok(Some(place))
+    }
+}
+
+/// Reads a save file, or `None` if there isn't one. Files written by a
+/// version newer than `newest` are refused.
+fn read_versioned(path: &Path, newest: u64) -> Result<Option<Value>, AssetError> {
+    let json = match read_json(path) {
+        Ok(json) => json,
+        Err(AssetError::NotFound(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    match json.get("version").and_then(Value::as_u64) {
+        Some(version) if version <= newest => Ok(Some(json)),
+        Some(version) => Err(AssetError::Parse(
+            path.to_path_buf(),
+            format!("saved by a newer version ({})", version),
+        )),
+        None => Err(AssetError::Parse(
+            path.to_path_buf(),
+            "missing `version`".to_string(),
+        )),
+    }
+}
+
+/// Writes to a temporary file first, flushes it to disk and renames it over
+/// `path`, so a crash mid-write never leaves a half-written save behind.
+pub fn write_atomic(path: &Path, json: &Value) -> Result<(), AssetError> {
+    let io_error = |e| AssetError::Io(path.to_path_buf(), e);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    let text = serde_json::to_string_pretty(json)
+        .map_err(|e| AssetError::Parse(path.to_path_buf(), e.to_string()))?;
+    let temp = path.with_extension("json.tmp");
+    let mut file = File::create(&temp).map_err(io_error)?;
+    file.write_all(text.as_bytes()).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+    fs::rename(&temp, path).map_err(io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    /// A store in a fresh temporary directory, removed when dropped.
+    struct TempStore(SaveStore);
+
+    impl TempStore {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("smb-test-{}-{}", process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            Self(SaveStore::new(&dir))
+        }
+    }
+
+    impl Drop for TempStore {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0.dir);
+        }
+    }
+
+    fn entry(score: u32, world: &str) -> HighScore {
+        HighScore {
+            score,
+            world: world.to_string(),
+        }
+    }
+
+    #[test]
+    fn high_scores_stay_ordered() {
+        let store = TempStore::new("ordered");
+        assert_eq!(store.0.add_high_score(entry(500, "1-1")).unwrap(), Some(0));
+        assert_eq!(store.0.add_high_score(entry(900, "1-2")).unwrap(), Some(0));
+        assert_eq!(store.0.add_high_score(entry(700, "1-1")).unwrap(), Some(1));
+        // A tie goes below the score already there.
+        assert_eq!(store.0.add_high_score(entry(500, "1-2")).unwrap(), Some(3));
+        assert_eq!(store.0.add_high_score(entry(0, "1-1")).unwrap(), None);
+
+        let scores = store.0.high_scores().unwrap();
+        let table: Vec<(u32, &str)> = scores.iter().map(|x| (x.score, &x.world[..])).collect();
+        assert_eq!(
+            table,
+            vec![(900, "1-2"), (700, "1-1"), (500, "1-1"), (500, "1-2")]
+        );
+    }
+
+    #[test]
+    fn high_score_table_is_capped() {
+        let store = TempStore::new("capped");
+        for score in 1..=HIGH_SCORE_COUNT as u32 {
+            store.0.add_high_score(entry(score * 100, "1-1")).unwrap();
+        }
+        assert_eq!(store.0.add_high_score(entry(50, "1-1")).unwrap(), None);
+        assert_eq!(store.0.add_high_score(entry(150, "1-1")).unwrap(), Some(9));
+        let scores = store.0.high_scores().unwrap();
+        assert_eq!(scores.len(), HIGH_SCORE_COUNT);
+        assert_eq!(scores.last().unwrap().score, 150);
+    }
+
+    #[test]
+    fn slots_round_trip_and_clear() {
+        let store = TempStore::new("slots");
+        assert!(store.0.load_slot(0).unwrap().is_none());
+        let data = SaveData {
+            world: "1-2".to_string(),
+            lives: 4,
+            score: 1200,
+            coins: 17,
+            form: PlayerForm::Fire,
+        };
+        store.0.save_slot(0, &data).unwrap();
+        let loaded = store.0.load_slot(0).unwrap().unwrap();
+        assert_eq!(loaded.world, "1-2");
+        assert_eq!((loaded.lives, loaded.score, loaded.coins), (4, 1200, 17));
+        assert_eq!(loaded.form, PlayerForm::Fire);
+        store.0.clear_slot(0).unwrap();
+        store.0.clear_slot(0).unwrap();
+        assert!(store.0.load_slot(0).unwrap().is_none());
+    }
+
+    #[test]
+    fn newer_saves_are_refused() {
+        let store = TempStore::new("newer");
+        let data = SaveData {
+            world: "1-1".to_string(),
+            lives: 3,
+            score: 0,
+            coins: 0,
+            form: PlayerForm::Small,
+        };
+        let mut json = data.to_json();
+        json["version"] = json!(SAVE_VERSION + 1);
+        write_atomic(&store.0.slot_path(1), &json).unwrap();
+        assert!(store.0.load_slot(1).is_err());
+    }
+}
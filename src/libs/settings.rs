@@ -0,0 +1,211 @@
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+
+use super::assets::{read_json, AssetError};
+use super::input::{Action, Binding, Bindings};
+use super::save::write_atomic;
+
+pub const MAX_SCALE: f64 = 6.0;
+const MIN_UPS: u64 = 10;
+const MAX_UPS: u64 = 240;
+
+/// Player preferences kept in `settings.json`. Fields missing from the file
+/// keep their defaults; window settings take effect on the next start.
+pub struct Settings {
+    pub scale: f64,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub ups: u64,
+    pub music_volume: f64,
+    pub bindings: Bindings,
+    /// Player two's controls in co-op games.
+    pub bindings_two: Bindings,
+    path: PathBuf,
+}
+
+impl Settings {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            scale: 3.0,
+            fullscreen: false,
+            vsync: false,
+            ups: 30,
+            music_volume: 1.0,
+            bindings: Bindings::new(),
+            bindings_two: Bindings::player_two(),
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Reads the file at `path`, or gives the defaults if there isn't one.
+    pub fn load(path: &Path) -> Result<Self, AssetError> {
+        let mut settings = Self::new(path);
+        let json = match read_json(path) {
+            Ok(json) => json,
+            Err(AssetError::NotFound(_)) => return Ok(settings),
+            Err(e) => return Err(e),
+        };
+        settings
+            .read(&json)
+            .map_err(|e| AssetError::Parse(path.to_path_buf(), e))?;
+        Ok(settings)
+    }
+
+    fn read(&mut self, json: &Value) -> Result<(), String> {
+        let number = |name: &str, min: f64, max: f64| -> Result<Option<f64>, String> {
+            match json.get(name) {
+                None => Ok(None),
+                Some(value) => match value.as_f64() {
+                    Some(x) if (min..=max).contains(&x) => Ok(Some(x)),
+                    _ => Err(format!(
+                        "`{}` must be a number from {} to {}",
+                        name, min, max
+                    )),
+                },
+            }
+        };
+        let flag = |name: &str| -> Result<Option<bool>, String> {
+            match json.get(name) {
+                None => Ok(None),
+                Some(value) => value
+                    .as_bool()
+                    .map(Some)
+                    .ok_or_else(|| format!("`{}` must be true or false", name)),
+            }
+        };
+
+        if let Some(scale) = number("scale", 1.0, MAX_SCALE)? {
+            self.scale = scale;
+        }
+        if let Some(ups) = number("ups", MIN_UPS as f64, MAX_UPS as f64)? {
+            self.ups = ups as u64;
+        }
+        if let Some(volume) = number("music_volume", 0.0, 1.0)? {
+            self.music_volume = volume;
+        }
+        if let Some(fullscreen) = flag("fullscreen")? {
+            self.fullscreen = fullscreen;
+        }
+        if let Some(vsync) = flag("vsync")? {
+            self.vsync = vsync;
+        }
+
+        read_bindings(json, "bindings", &mut self.bindings)?;
+        read_bindings(json, "bindings_two", &mut self.bindings_two)?;
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<(), AssetError> {
+        let json = json!({
+            "scale": self.scale,
+            "fullscreen": self.fullscreen,
+            "vsync": self.vsync,
+            "ups": self.ups,
+            "music_volume": self.music_volume,
+            "bindings": write_bindings(&self.bindings),
+            "bindings_two": write_bindings(&self.bindings_two),
+        });
+        write_atomic(&self.path, &json)
+    }
+}
+
+/// Overrides the actions listed under `key`, leaving the others bound as
+/// they were.
+fn read_bindings(json: &Value, key: &str, bindings: &mut Bindings) -> Result<(), String> {
+    let map = match json.get(key) {
+        Some(map) => map
+            .as_object()
+            .ok_or_else(|| format!("`{}` must map actions to lists of inputs", key))?,
+        None => return Ok(()),
+    };
+    for (name, inputs) in map {
+        let action = Action::from_name(name)
+            .ok_or_else(|| format!("unknown action `{}` in `{}`", name, key))?;
+        let inputs = inputs
+            .as_array()
+            .ok_or_else(|| format!("bindings for `{}` must be a list", name))?
+            .iter()
+            .map(|x| Binding::from_json(x).ok_or(format!("invalid input {} for `{}`", x, name)))
+            .collect::<Result<Vec<_>, _>>()?;
+        bindings.set(action, inputs);
+    }
+    Ok(())
+}
+
+fn write_bindings(bindings: &Bindings) -> Value {
+    let mut map = Map::new();
+    for action in Action::ALL.iter() {
+        let inputs = bindings
+            .inputs(*action)
+            .into_iter()
+            .map(Binding::to_json)
+            .collect();
+        map.insert(action.name().to_string(), Value::Array(inputs));
+    }
+    Value::Object(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piston_window::Key;
+
+    fn read(json: Value) -> Result<Settings, String> {
+        let mut settings = Settings::new(Path::new("settings.json"));
+        settings.read(&json)?;
+        Ok(settings)
+    }
+
+    #[test]
+    fn missing_fields_keep_their_defaults() {
+        let settings = read(json!({ "scale": 2, "vsync": true })).unwrap();
+        assert_eq!(settings.scale, 2.0);
+        assert!(settings.vsync);
+        assert_eq!(settings.ups, 30);
+        assert_eq!(settings.music_volume, 1.0);
+        assert!(!settings.fullscreen);
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        let bad = [
+            json!({ "scale": 0.5 }),
+            json!({ "scale": MAX_SCALE + 1.0 }),
+            json!({ "ups": 5 }),
+            json!({ "ups": 1000 }),
+            json!({ "music_volume": 1.5 }),
+            json!({ "fullscreen": "yes" }),
+            json!({ "scale": "big" }),
+        ];
+        for value in bad {
+            assert!(read(value.clone()).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn bindings_replace_only_the_listed_actions() {
+        let settings = read(json!({ "bindings": { "jump": ["Z", { "gamepad": 3 }] } })).unwrap();
+        assert_eq!(
+            settings.bindings.inputs(Action::Jump),
+            vec![Binding::Key(Key::Z), Binding::Gamepad(3)]
+        );
+        assert_eq!(
+            settings.bindings.inputs(Action::Left),
+            Bindings::new().inputs(Action::Left)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_bindings() {
+        let bad = [
+            json!({ "bindings": { "fly": ["Z"] } }),
+            json!({ "bindings": { "jump": "Z" } }),
+            json!({ "bindings": { "jump": ["NoSuchKey"] } }),
+            json!({ "bindings_two": { "jump": [{ "gamepad": 300 }] } }),
+            json!({ "bindings": ["jump"] }),
+        ];
+        for value in bad {
+            assert!(read(value.clone()).is_err(), "{}", value);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use serde_json::{json, Value};
+
+use super::transform::{Rect, Trans, Transform};
+
+/// Written into snapshot files; bumped whenever the layout changes in a way
+/// older snapshots can't be read with.
+pub const SNAPSHOT_VERSION: u64 = 1;
+
+pub fn number(json: &Value, name: &str) -> Result<f64, String> {
+    json.get(name)
+        .and_then(Value::as_f64)
+        .ok_or_else(|| format!("missing or invalid `{}`", name))
+}
+
+pub fn flag(json: &Value, name: &str) -> Result<bool, String> {
+    json.get(name)
+        .and_then(Value::as_bool)
+        .ok_or_else(|| format!("missing or invalid `{}`", name))
+}
+
+pub fn text<'a>(json: &'a Value, name: &str) -> Result<&'a str, String> {
+    json.get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("missing or invalid `{}`", name))
+}
+
+pub fn list<'a>(json: &'a Value, name: &str) -> Result<&'a Vec<Value>, String> {
+    json.get(name)
+        .and_then(Value::as_array)
+        .ok_or_else(|| format!("missing or invalid `{}`", name))
+}
+
+/// A `[x, y]` pair, as velocities are stored.
+pub fn pair(json: &Value, name: &str) -> Result<(f64, f64), String> {
+    match list(json, name)?.as_slice() {
+        [x, y] => match (x.as_f64(), y.as_f64()) {
+            (Some(x), Some(y)) => Ok((x, y)),
+            _ => Err(format!("invalid `{}`", name)),
+        },
+        _ => Err(format!("`{}` must have two numbers", name)),
+    }
+}
+
+pub fn write_transform(transform: &Transform) -> Value {
+    json!({
+        "x": transform.x(),
+        "y": transform.y(),
+        "w": transform.w(),
+        "h": transform.h(),
+        "flip_x": transform.is_flip_x(),
+        "flip_y": transform.is_flip_y(),
+    })
+}
+
+pub fn read_transform(json: &Value, name: &str) -> Result<Transform, String> {
+    let json = json
+        .get(name)
+        .ok_or_else(|| format!("missing `{}`", name))?;
+    let mut transform = Transform::new();
+    transform.set_position(number(json, "x")?, number(json, "y")?);
+    transform.set_size(number(json, "w")?, number(json, "h")?);
+    transform.set_flip_x(flag(json, "flip_x")?);
+    transform.set_flip_y(flag(json, "flip_y")?);
+    Ok(transform)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms_round_trip() {
+        let mut transform = Transform::new();
+        transform.set_position(12.5, -3.0);
+        transform.set_size(16.0, 32.0);
+        transform.set_flip_x(true);
+        let json = json!({ "transform": write_transform(&transform) });
+        let read = read_transform(&json, "transform").unwrap();
+        assert_eq!(
+            (read.x(), read.y(), read.w(), read.h()),
+            (12.5, -3.0, 16.0, 32.0)
+        );
+        assert!(read.is_flip_x() && !read.is_flip_y());
+        assert!(read_transform(&json!({}), "transform").is_err());
+    }
+
+    #[test]
+    fn pairs_need_two_numbers() {
+        let json = json!({ "a": [1.0, 2.0], "b": [1.0], "c": [1.0, "2"] });
+        assert_eq!(pair(&json, "a").unwrap(), (1.0, 2.0));
+        assert!(pair(&json, "b").is_err());
+        assert!(pair(&json, "c").is_err());
+        assert!(pair(&json, "d").is_err());
+    }
+}
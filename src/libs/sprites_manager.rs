@@ -1,50 +1,163 @@
 use super::{
-    animations::SpriteAnimation,
+    animations::{AnimationProgress, AnimationStateMachine, SpriteAnimation},
+    assets::{read_json, AssetError},
+    snapshot,
     spritesheet::{SpriteSheet, SpriteSheetConfig},
 };
 use piston_window::math::Matrix2d;
-use piston_window::{
-    Filter, Flip, G2dTexture, G2dTextureContext, Graphics, ImageSize, Texture, TextureSettings,
-};
+use piston_window::{Graphics, ImageSize};
+use serde_json::{json, Value};
 use sprite::Sprite;
-use std::path::PathBuf;
-use std::rc::Rc;
+use std::path::Path;
 
 pub trait SpriteManagerFn<I: ImageSize> {
     fn set_sprite_sheet(&mut self, sprite_sheet: SpriteSheet<I>);
 }
 
+#[derive(Clone)]
 pub struct SpriteConfig {
-    pub name: &'static str,
+    pub name: String,
     config: SpriteSheetConfig,
 }
 
+/// Sprite sheet configs and animation clips described by a JSON asset.
+#[derive(Clone)]
+pub struct SpriteDefinitions {
+    pub texture: String,
+    pub default_config: String,
+    configs: Vec<SpriteConfig>,
+    animations: Vec<SpriteAnimation>,
+    state_machine: AnimationStateMachine,
+}
+
+impl SpriteDefinitions {
+    pub fn load(path: &Path) -> Result<Self, AssetError> {
+        let json = read_json(path)?;
+        Self::from_json(&json).map_err(|e| AssetError::Parse(path.to_path_buf(), e))
+    }
+
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let texture = json
+            .get("texture")
+            .and_then(Value::as_str)
+            .ok_or("missing `texture`")?
+            .to_string();
+
+        let mut configs = Vec::new();
+        for (name, value) in json
+            .get("configs")
+            .and_then(Value::as_object)
+            .ok_or("missing `configs` object")?
+        {
+            let config = SpriteSheetConfig::from_json(value)
+                .map_err(|e| format!("config `{}`: {}", name, e))?;
+            configs.push(SpriteConfig {
+                name: name.clone(),
+                config,
+            });
+        }
+
+        let default_config = json
+            .get("default_config")
+            .and_then(Value::as_str)
+            .ok_or("missing `default_config`")?
+            .to_string();
+        if !configs.iter().any(|x| x.name == default_config) {
+            return Err(format!(
+                "default config `{}` is not defined",
+                default_config
+            ));
+        }
+
+        let mut animations = Vec::new();
+        let mut state_machine = AnimationStateMachine::new();
+        for (name, value) in json
+            .get("animations")
+            .and_then(Value::as_object)
+            .ok_or("missing `animations` object")?
+        {
+            animations.push(SpriteAnimation::from_json(name, value)?);
+            state_machine.add_state_json(name, value)?;
+        }
+        state_machine.validate(&animations)?;
+
+        Ok(Self {
+            texture,
+            default_config,
+            configs,
+            animations,
+            state_machine,
+        })
+    }
+}
+
+/// The clip a sprite was playing and how far it had got, so a restored
+/// sprite carries on mid-clip.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationSnapshot {
+    clip: Option<String>,
+    progress: Option<AnimationProgress>,
+}
+
+impl AnimationSnapshot {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "clip": self.clip,
+            "progress": self.progress.as_ref().map(AnimationProgress::to_json),
+        })
+    }
+
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let clip = match json.get("clip") {
+            Some(Value::Null) | None => None,
+            Some(_) => Some(snapshot::text(json, "clip")?.to_string()),
+        };
+        let progress = match json.get("progress") {
+            Some(Value::Null) | None => None,
+            Some(x) => Some(AnimationProgress::from_json(x)?),
+        };
+        Ok(Self { clip, progress })
+    }
+
+    /// The optional `animation` field of an entity snapshot. Snapshots from
+    /// before animations were kept don't have it.
+    pub fn read(json: &Value) -> Result<Option<Self>, String> {
+        match json.get("animation") {
+            Some(Value::Null) | None => Ok(None),
+            Some(x) => Self::from_json(x).map(Some),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AnimationEvent {
+    /// A one-shot clip played its last frame.
+    Finished(String),
+}
+
 pub struct SpriteManager<I: ImageSize> {
     sprite_sheet: Option<SpriteSheet<I>>,
     sprite_configs: Vec<SpriteConfig>,
-    current_config_name: Option<&'static str>,
+    current_config_name: Option<String>,
+    palette: usize,
     animations: Vec<SpriteAnimation>,
-    current_animation_name: Option<&'static str>,
+    state_machine: AnimationStateMachine,
+    events: Vec<AnimationEvent>,
 }
 
 impl<I> SpriteManager<I>
 where
     I: ImageSize,
 {
-    pub fn load_texture(mut context: &mut G2dTextureContext, p: &PathBuf) -> Rc<G2dTexture> {
-        let mut texture_settings = TextureSettings::new();
-        texture_settings.set_mag(Filter::Nearest);
-        let texture = Texture::from_path(&mut context, p, Flip::None, &texture_settings).unwrap();
-        Rc::new(texture)
-    }
-
     pub fn new() -> Self {
         Self {
             sprite_sheet: None,
             animations: Vec::default(),
             sprite_configs: Vec::default(),
             current_config_name: None,
-            current_animation_name: None,
+            palette: 0,
+            state_machine: AnimationStateMachine::new(),
+            events: Vec::default(),
         }
     }
 
@@ -59,23 +172,19 @@ where
         None
     }
 
-    pub fn add_animation(&mut self, name: &'static str, animations: Vec<[usize; 2]>) {
-        let an = SpriteAnimation::new(name, animations);
-        self.animations.push(an);
+    /// Replaces all configs and animations with the given definitions and
+    /// switches to their default config.
+    pub fn set_definitions(&mut self, definitions: SpriteDefinitions) {
+        self.sprite_configs = definitions.configs;
+        self.animations = definitions.animations;
+        self.state_machine = definitions.state_machine;
+        self.set_current_config(&definitions.default_config);
     }
 
-    pub fn add_config(&mut self, name: &'static str, options: SpriteSheetConfig) {
-        let sprite_config = SpriteConfig {
-            name: name,
-            config: options,
-        };
-        self.sprite_configs.push(sprite_config);
-    }
-
-    pub fn set_current_config(&mut self, name: &'static str) {
+    pub fn set_current_config(&mut self, name: &str) {
         if let Some(sprite_sheet) = &mut self.sprite_sheet {
             if let Some(sprite_config) = self.sprite_configs.iter().find(|x| x.name == name) {
-                self.current_config_name = Some(name);
+                self.current_config_name = Some(name.to_string());
                 sprite_sheet.set_config(&sprite_config.config);
             } else {
                 self.current_config_name = None
@@ -83,11 +192,101 @@ where
         }
     }
 
-    pub fn play_animation(&mut self, name: &'static str) {
-        if let Some(animation) = self.animations.iter_mut().find(|x| x.name == name) {
-            self.current_animation_name = Some(name);
+    /// Shifts every frame down by `palette` rows, for sheets that repeat
+    /// the same frames in other colors.
+    pub fn set_palette(&mut self, palette: usize) {
+        if self.palette != palette {
+            self.palette = palette;
+            self.apply_current_frame();
+        }
+    }
+
+    /// Requests a switch to `name`. The clip restarts only when the state
+    /// machine actually changes state; requesting the current clip again
+    /// keeps its progress.
+    pub fn play_animation(&mut self, name: &str) {
+        if !self.animations.iter().any(|x| x.name == name) {
+            return;
+        }
+        if self.state_machine.transition(name) {
+            self.restart_current();
+        }
+    }
+
+    /// Switches to `name` and restarts it, ignoring the transition table.
+    pub fn force_animation(&mut self, name: &str) {
+        if self.animations.iter().any(|x| x.name == name) {
+            self.state_machine.force(name);
+            self.restart_current();
+        }
+    }
+
+    pub fn animation(&self) -> AnimationSnapshot {
+        let clip = self.state_machine.current();
+        AnimationSnapshot {
+            clip: clip.map(String::from),
+            progress: clip
+                .and_then(|name| self.animations.iter().find(|x| x.name == name))
+                .map(SpriteAnimation::progress),
+        }
+    }
+
+    /// Puts the current clip and its progress back as `snapshot` found
+    /// them. Clips that no longer exist are left alone.
+    pub fn restore_animation(&mut self, snapshot: &AnimationSnapshot) {
+        let name = match &snapshot.clip {
+            Some(name) if self.animations.iter().any(|x| &x.name == name) => name,
+            _ => return,
+        };
+        self.state_machine.force(name);
+        if let (Some(animation), Some(progress)) =
+            (self.current_animation_mut(), &snapshot.progress)
+        {
+            animation.set_progress(progress);
+        }
+        self.apply_current_frame();
+    }
+
+    pub fn current_animation(&self) -> Option<&str> {
+        self.state_machine.current()
+    }
+
+    /// Returns the animation events raised since the last call.
+    pub fn drain_events(&mut self) -> Vec<AnimationEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn set_animation_speed(&mut self, speed: f64) {
+        if let Some(animation) = self.current_animation_mut() {
+            animation.set_speed(speed);
+        }
+    }
+
+    fn current_animation_mut(&mut self) -> Option<&mut SpriteAnimation> {
+        let name = self.state_machine.current()?;
+        self.animations.iter_mut().find(|x| x.name == name)
+    }
+
+    fn restart_current(&mut self) {
+        if let Some(animation) = self.current_animation_mut() {
+            animation.stop();
             animation.play();
         }
+        self.apply_current_frame();
+    }
+
+    pub fn apply_current_frame(&mut self) {
+        let tile = match self.state_machine.current() {
+            Some(name) => self
+                .animations
+                .iter()
+                .find(|x| x.name == name)
+                .and_then(|x| x.get_animation().copied()),
+            None => None,
+        };
+        if let (Some(sprite_sheet), Some(tile)) = (&mut self.sprite_sheet, tile) {
+            sprite_sheet.set_current_tiles(tile[0] + self.palette, tile[1]);
+        }
     }
 
     pub fn stop_animation(&mut self) {
@@ -99,37 +298,91 @@ where
     pub fn draw<B: Graphics<Texture = I>>(&mut self, t: Matrix2d, b: &mut B) {
         if let Some(sprite_sheet) = &mut self.sprite_sheet {
             sprite_sheet.draw(t, b);
+        }
+    }
 
-            if let Some(animation_name) = self.current_animation_name {
-                if let Some(animation) = self
-                    .animations
-                    .iter_mut()
-                    .find(|x| x.name == animation_name)
-                {
-                    if let Some(animations) = animation.get_animation() {
-                        sprite_sheet.set_current_tiles(animations[0], animations[1]);
-                    }
-                }
+    /// Advances the current clip. A one-shot clip that finishes raises
+    /// `AnimationEvent::Finished` and hands over to its `next` clip, if it
+    /// names one.
+    pub fn update(&mut self, dt: f64) {
+        let finished = match self.current_animation_mut() {
+            Some(animation) => animation.update(dt).then(|| animation.name.clone()),
+            None => None,
+        };
+
+        if let Some(name) = finished {
+            let next = self.state_machine.next(&name).map(String::from);
+            self.events.push(AnimationEvent::Finished(name));
+            if let Some(next) = next {
+                self.force_animation(&next);
             }
         }
+
+        self.apply_current_frame();
     }
+}
 
-    pub fn update(&mut self, dt: f64) {
-        if let Some(animation_name) = self.current_animation_name {
-            if let Some(animation) = self
-                .animations
-                .iter_mut()
-                .find(|x| x.name == animation_name)
-            {
-                animation.update(dt);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn definitions(default_config: &str) -> Value {
+        json!({
+            "texture": "player.png",
+            "default_config": default_config,
+            "configs": {
+                "small": {
+                    "offset": [0.0, 0.0],
+                    "spacing": [1.0, 1.0],
+                    "grid": [2, 2],
+                    "sprite_size": [16.0, 16.0]
+                }
+            },
+            "animations": {
+                "idle": { "frames": [{ "tile": [0, 0], "duration": 1.0 }] }
             }
+        })
+    }
 
-            self.animations
-                .iter_mut()
-                .filter(|x| x.name != animation_name)
-                .for_each(|x| {
-                    x.stop();
-                });
+    #[test]
+    fn parses_definitions() {
+        let definitions = SpriteDefinitions::from_json(&definitions("small")).unwrap();
+        assert_eq!(definitions.texture, "player.png");
+        assert_eq!(definitions.configs.len(), 1);
+        assert_eq!(definitions.animations[0].name, "idle");
+    }
+
+    #[test]
+    fn default_config_must_exist() {
+        assert!(SpriteDefinitions::from_json(&definitions("super")).is_err());
+    }
+
+    #[test]
+    fn shipped_definitions_load() {
+        for file in [
+            "player_animations.json",
+            "goomba.json",
+            "coin.json",
+            "block.json",
+            "items.json",
+        ] {
+            let path = Path::new("assets").join(file);
+            if let Err(e) = SpriteDefinitions::load(&path) {
+                panic!("{}", e);
+            }
         }
     }
+
+    #[test]
+    fn animation_snapshots_are_optional() {
+        assert_eq!(AnimationSnapshot::read(&json!({})).unwrap(), None);
+        let snapshot = AnimationSnapshot {
+            clip: Some("walk".to_string()),
+            progress: None,
+        };
+        let json = json!({ "animation": snapshot.to_json() });
+        assert_eq!(AnimationSnapshot::read(&json).unwrap(), Some(snapshot));
+        assert!(AnimationSnapshot::read(&json!({ "animation": { "clip": 3 } })).is_err());
+    }
 }
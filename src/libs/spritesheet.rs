@@ -2,9 +2,11 @@ use cgmath::Vector2;
 use graphics::math::Matrix2d;
 use graphics::Graphics;
 use piston_window::{ImageSize, Size};
+use serde_json::Value;
 use sprite::Sprite;
 use std::rc::Rc;
 
+#[derive(Clone, Copy)]
 pub struct SpriteSheetConfig {
     pub grid: [usize; 2],
     pub sprite_size: Size,
@@ -12,12 +14,40 @@ pub struct SpriteSheetConfig {
     pub offset: Vector2<f64>,
 }
 
+impl SpriteSheetConfig {
+    /// Parses `{ "offset": [x, y], "spacing": [x, y], "grid": [rows, cols], "sprite_size": [w, h] }`.
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let pair = |name: &str| -> Result<[f64; 2], String> {
+            value
+                .get(name)
+                .and_then(Value::as_array)
+                .filter(|x| x.len() == 2)
+                .and_then(|x| Some([x[0].as_f64()?, x[1].as_f64()?]))
+                .ok_or_else(|| format!("missing or invalid field `{}`", name))
+        };
+
+        let grid = pair("grid")?;
+        if grid[0] < 1.0 || grid[1] < 1.0 {
+            return Err("`grid` must be at least [1, 1]".to_string());
+        }
+
+        Ok(Self {
+            grid: [grid[0] as usize, grid[1] as usize],
+            sprite_size: Size::from(pair("sprite_size")?),
+            spacing: Vector2::from(pair("spacing")?),
+            offset: Vector2::from(pair("offset")?),
+        })
+    }
+}
+
 pub struct SpriteSheet<I: ImageSize> {
     sprite: Sprite<I>,
     grid: [usize; 2],
     sprite_size: Size,
     spacing: Vector2<f64>,
     offset: Vector2<f64>,
+    /// Top left of the sheet in the texture, for sheets packed in an atlas.
+    origin: Vector2<f64>,
 }
 
 impl<I: ImageSize> SpriteSheet<I> {
@@ -32,11 +62,22 @@ impl<I: ImageSize> SpriteSheet<I> {
             sprite_size: Size::from(size),
             spacing: Vector2::from([0.0, 0.0]),
             offset: Vector2::from([0.0, 0.0]),
+            origin: Vector2::from([0.0, 0.0]),
         };
         sprite.set_current_tiles(0, 0);
         sprite
     }
 
+    /// A sheet cut from the `[x, y, w, h]` part of `texture`, such as one
+    /// image of an atlas. Config offsets count from the region's corner.
+    pub fn from_region(texture: Rc<I>, region: [f64; 4]) -> Self {
+        let mut sheet = Self::new(texture);
+        sheet.origin = Vector2::new(region[0], region[1]);
+        sheet.sprite_size = Size::from([region[2], region[3]]);
+        sheet.set_current_tiles(0, 0);
+        sheet
+    }
+
     pub fn set_config(&mut self, config: &SpriteSheetConfig) {
         self.grid = config.grid;
         self.sprite_size = config.sprite_size;
@@ -61,8 +102,8 @@ impl<I: ImageSize> SpriteSheet<I> {
         let sprite_width_with_spacing = self.sprite_size.width + self.spacing.x;
         let sprite_height_with_spacing = self.sprite_size.height + self.spacing.y;
         let src_rect = From::from([
-            self.offset.x + sprite_width_with_spacing * col as f64,
-            self.offset.y + sprite_height_with_spacing * row as f64,
+            self.origin.x + self.offset.x + sprite_width_with_spacing * col as f64,
+            self.origin.y + self.offset.y + sprite_height_with_spacing * row as f64,
             self.sprite_size.width,
             self.sprite_size.height,
         ]);
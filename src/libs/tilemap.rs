@@ -0,0 +1,123 @@
+use graphics::math::Matrix2d;
+use graphics::{Graphics, Transformed};
+use piston_window::{ImageSize, Size};
+use serde_json::Value;
+use std::rc::Rc;
+
+use super::spritesheet::{SpriteSheet, SpriteSheetConfig};
+
+/// Tiled stores flip flags in the top bits of each gid.
+const GID_MASK: u32 = 0x0fff_ffff;
+
+struct TileLayer {
+    width: usize,
+    data: Vec<u32>,
+}
+
+/// The visible tile layers of a Tiled map, drawn from a single tileset.
+pub struct TileLayers<I: ImageSize> {
+    layers: Vec<TileLayer>,
+    tiles: SpriteSheet<I>,
+    columns: usize,
+    first_gid: u32,
+    tile_size: Size,
+}
+
+impl<I: ImageSize> TileLayers<I> {
+    /// Builds the layers of `map` using `tileset`, the parsed `.tsj` file of
+    /// its first tileset, and that tileset's image.
+    pub fn from_json(map: &Value, tileset: &Value, texture: Rc<I>) -> Result<Self, String> {
+        let number = |value: &Value, name: &str| {
+            value
+                .get(name)
+                .and_then(Value::as_u64)
+                .map(|x| x as usize)
+                .ok_or_else(|| format!("missing or invalid field `{}`", name))
+        };
+
+        let first_gid = map
+            .get("tilesets")
+            .and_then(Value::as_array)
+            .and_then(|x| x.first())
+            .and_then(|x| x.get("firstgid"))
+            .and_then(Value::as_u64)
+            .ok_or("missing tileset `firstgid`")? as u32;
+        let columns = number(tileset, "columns")?.max(1);
+        let tile_count = number(tileset, "tilecount")?;
+        let tile_size = Size::from([
+            number(tileset, "tilewidth")? as f64,
+            number(tileset, "tileheight")? as f64,
+        ]);
+
+        let mut tiles = SpriteSheet::new(texture);
+        tiles.set_config(&SpriteSheetConfig {
+            grid: [tile_count.div_ceil(columns).max(1), columns],
+            sprite_size: tile_size,
+            spacing: [0.0, 0.0].into(),
+            offset: [0.0, 0.0].into(),
+        });
+
+        let mut layers = Vec::new();
+        for layer in map
+            .get("layers")
+            .and_then(Value::as_array)
+            .ok_or("missing `layers`")?
+        {
+            let is_tiles = layer.get("type").and_then(Value::as_str) == Some("tilelayer");
+            let visible = layer
+                .get("visible")
+                .and_then(Value::as_bool)
+                .unwrap_or(true);
+            if !is_tiles || !visible {
+                continue;
+            }
+            let data = layer
+                .get("data")
+                .and_then(Value::as_array)
+                .ok_or("tile layer without `data`, only uncompressed CSV maps are supported")?
+                .iter()
+                .map(|x| x.as_u64().map(|x| x as u32 & GID_MASK))
+                .collect::<Option<Vec<u32>>>()
+                .ok_or("tile layer `data` must list tile ids")?;
+            layers.push(TileLayer {
+                width: number(layer, "width")?.max(1),
+                data,
+            });
+        }
+
+        Ok(Self {
+            layers,
+            tiles,
+            columns,
+            first_gid,
+            tile_size,
+        })
+    }
+
+    /// Draws the tiles overlapping `view`, a world-space rectangle, with `t`
+    /// being the camera transform.
+    pub fn draw<B: Graphics<Texture = I>>(&mut self, view: [f64; 4], t: Matrix2d, b: &mut B) {
+        let (tw, th) = (self.tile_size.width, self.tile_size.height);
+        let first_col = (view[0] / tw).floor().max(0.0) as usize;
+        let first_row = (view[1] / th).floor().max(0.0) as usize;
+        let last_col = ((view[0] + view[2]) / tw).ceil().max(0.0) as usize;
+        let last_row = ((view[1] + view[3]) / th).ceil().max(0.0) as usize;
+
+        for layer in &self.layers {
+            let rows = layer.data.len() / layer.width;
+            for row in first_row..last_row.min(rows) {
+                for col in first_col..last_col.min(layer.width) {
+                    let gid = layer.data[row * layer.width + col];
+                    if gid < self.first_gid {
+                        continue;
+                    }
+                    let id = (gid - self.first_gid) as usize;
+                    self.tiles
+                        .set_current_tiles(id / self.columns, id % self.columns);
+                    self.tiles
+                        .draw(t.trans(col as f64 * tw, row as f64 * th), b);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use serde_json::{json, Value};
+
+use super::console::{parse_number, Console};
+use super::snapshot;
+
+/// Seconds per unit of level time; the original clock ticks a little faster
+/// than real seconds.
+const UNIT_DURATION: f64 = 0.4;
+/// Units left when the hurry-up music kicks in.
+const HURRY_TIME: f64 = 100.0;
+
+/// Hosts that own the level clock.
+pub trait TimerHost {
+    fn timer_mut(&mut self) -> &mut LevelTimer;
+}
+
+/// Registers `time`.
+pub fn register_commands<C: TimerHost>(console: &mut Console<C>) {
+    console.register(
+        "time",
+        "[units] - show or set the time left",
+        |host, args| {
+            let timer = host.timer_mut();
+            match args {
+                [] => {}
+                [units] => {
+                    let units = parse_number(units)?;
+                    if units < 1.0 {
+                        return Err("time must be at least 1".to_string());
+                    }
+                    *timer = LevelTimer::new(units as u32);
+                }
+                _ => return Err("usage: time [units]".to_string()),
+            }
+            Ok(format!("{} time left", timer.remaining()))
+        },
+    );
+}
+
+/// Countdown of the time left in a level, measured in game-time units.
+pub struct LevelTimer {
+    remaining: f64,
+    frozen: bool,
+}
+
+impl LevelTimer {
+    pub fn new(units: u32) -> Self {
+        Self {
+            remaining: units as f64,
+            frozen: false,
+        }
+    }
+
+    /// Remaining units as shown on the HUD.
+    pub fn remaining(&self) -> u32 {
+        self.remaining.ceil() as u32
+    }
+
+    /// Whether the clock is down to its last hundred units.
+    pub fn is_hurry(&self) -> bool {
+        self.remaining <= HURRY_TIME
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.remaining <= 0.0
+    }
+
+    pub fn set_frozen(&mut self, value: bool) {
+        self.frozen = value;
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        if !self.frozen {
+            self.remaining = (self.remaining - dt / UNIT_DURATION).max(0.0);
+        }
+    }
+
+    pub fn snapshot(&self) -> Value {
+        json!({ "remaining": self.remaining, "frozen": self.frozen })
+    }
+
+    pub fn restore(json: &Value) -> Result<Self, String> {
+        Ok(Self {
+            remaining: snapshot::number(json, "remaining")?,
+            frozen: snapshot::flag(json, "frozen")?,
+        })
+    }
+
+    /// Removes up to `units` whole units, e.g. to tally them into the
+    /// score, and returns how many were taken.
+    pub fn take(&mut self, units: u32) -> u32 {
+        let taken = units.min(self.remaining());
+        self.remaining = (self.remaining - taken as f64).max(0.0).floor();
+        taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hurries_at_a_hundred_units() {
+        let mut timer = LevelTimer::new(101);
+        assert!(!timer.is_hurry());
+        timer.update(UNIT_DURATION / 2.0);
+        assert_eq!(timer.remaining(), 101);
+        assert!(!timer.is_hurry());
+        timer.update(UNIT_DURATION / 2.0);
+        assert_eq!(timer.remaining(), 100);
+        assert!(timer.is_hurry());
+    }
+
+    #[test]
+    fn runs_out_and_stops_at_zero() {
+        let mut timer = LevelTimer::new(2);
+        timer.update(UNIT_DURATION * 5.0);
+        assert!(timer.is_up());
+        assert_eq!(timer.remaining(), 0);
+    }
+
+    #[test]
+    fn frozen_clock_stands_still() {
+        let mut timer = LevelTimer::new(10);
+        timer.set_frozen(true);
+        timer.update(UNIT_DURATION * 3.0);
+        assert_eq!(timer.remaining(), 10);
+    }
+
+    #[test]
+    fn tally_takes_whole_units() {
+        let mut timer = LevelTimer::new(5);
+        timer.update(UNIT_DURATION / 2.0);
+        assert_eq!(timer.take(3), 3);
+        assert_eq!(timer.remaining(), 1);
+        assert_eq!(timer.take(3), 1);
+        assert_eq!(timer.take(3), 0);
+        assert!(timer.is_up());
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let mut timer = LevelTimer::new(300);
+        timer.update(1.0);
+        timer.set_frozen(true);
+        let restored = LevelTimer::restore(&timer.snapshot()).unwrap();
+        assert_eq!(restored.remaining, timer.remaining);
+        assert!(restored.frozen);
+    }
+}
@@ -0,0 +1,320 @@
+use graphics::ImageSize;
+use serde_json::Value;
+
+use super::{
+    collider::Side,
+    entities::MapObject,
+    player::Player,
+    transform::{Rect, Trans, Transform},
+};
+
+/// Pixels per second.
+const PIPE_SPEED: f64 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PipeDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl PipeDirection {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "up" => Some(PipeDirection::Up),
+            "down" => Some(PipeDirection::Down),
+            "left" => Some(PipeDirection::Left),
+            "right" => Some(PipeDirection::Right),
+            _ => None,
+        }
+    }
+
+    fn opposite(&self) -> Self {
+        match self {
+            PipeDirection::Up => PipeDirection::Down,
+            PipeDirection::Down => PipeDirection::Up,
+            PipeDirection::Left => PipeDirection::Right,
+            PipeDirection::Right => PipeDirection::Left,
+        }
+    }
+
+    /// The coordinate of the side of `body` facing this way.
+    fn edge(&self, body: &Transform) -> f64 {
+        match self {
+            PipeDirection::Up => body.y(),
+            PipeDirection::Down => body.yh(),
+            PipeDirection::Left => body.x(),
+            PipeDirection::Right => body.xw(),
+        }
+    }
+
+    fn offset(&self) -> (f64, f64) {
+        match self {
+            PipeDirection::Up => (0.0, -1.0),
+            PipeDirection::Down => (0.0, 1.0),
+            PipeDirection::Left => (-1.0, 0.0),
+            PipeDirection::Right => (1.0, 0.0),
+        }
+    }
+}
+
+/// A pipe mouth from a `warp` object. `direction` is the way the player
+/// moves to go in: `down` for pipes entered by crouching on top, `right` or
+/// `left` for pipes entered by walking into them. `target` names a
+/// `warp_exit` object in `target_map`, or in the current map when that is
+/// not set.
+#[derive(Debug, Clone)]
+pub struct Warp {
+    pub trigger: Transform,
+    pub direction: PipeDirection,
+    pub target_map: Option<String>,
+    pub target: String,
+}
+
+/// Where a warp comes out. Exits with a `direction` push the player out of
+/// a pipe that way, the others just place the player.
+#[derive(Debug, Clone)]
+pub struct WarpExit {
+    pub name: String,
+    pub position: Transform,
+    pub direction: Option<PipeDirection>,
+}
+
+fn direction_property(object: &MapObject) -> Result<Option<PipeDirection>, String> {
+    match object.property("direction").and_then(Value::as_str) {
+        Some(name) => PipeDirection::from_name(name)
+            .map(Some)
+            .ok_or_else(|| format!("object {}: unknown direction `{}`", object.id, name)),
+        None => Ok(None),
+    }
+}
+
+impl Warp {
+    pub fn from_objects(objects: &[MapObject]) -> Result<Vec<Self>, String> {
+        let mut warps = Vec::new();
+        for object in objects.iter().filter(|x| x.kind == "warp") {
+            let direction = direction_property(object)?
+                .ok_or_else(|| format!("warp {} has no `direction`", object.id))?;
+            let target = object
+                .property("target")
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("warp {} has no `target`", object.id))?;
+            warps.push(Self {
+                trigger: object.transform,
+                direction,
+                target_map: object
+                    .property("target_map")
+                    .and_then(Value::as_str)
+                    .map(String::from),
+                target: target.to_string(),
+            });
+        }
+        Ok(warps)
+    }
+
+    /// Whether the player is standing at the mouth pushing into the pipe.
+    pub fn can_enter<I: ImageSize>(&self, player: &Player<I>) -> bool {
+        let body = player.get_transform();
+        let input = player.get_input();
+        let on_ground = player.get_contacts().contains(&Side::BOTTOM);
+        let pipe = &self.trigger;
+
+        match self.direction {
+            PipeDirection::Down => {
+                on_ground
+                    && input.crouch
+                    && body.x() >= pipe.x()
+                    && body.xw() <= pipe.xw()
+                    && (body.yh() - pipe.y()).abs() < 2.0
+            }
+            PipeDirection::Right | PipeDirection::Left => {
+                let pushing = match self.direction {
+                    PipeDirection::Right => input.right && (body.xw() - pipe.x()).abs() < 2.0,
+                    _ => input.left && (body.x() - pipe.xw()).abs() < 2.0,
+                };
+                on_ground && pushing && body.y() >= pipe.y() && body.yh() <= pipe.yh() + 1.0
+            }
+            PipeDirection::Up => {
+                input.jump
+                    && body.x() >= pipe.x()
+                    && body.xw() <= pipe.xw()
+                    && (body.y() - pipe.yh()).abs() < 2.0
+            }
+        }
+    }
+}
+
+impl WarpExit {
+    pub fn from_objects(objects: &[MapObject]) -> Result<Vec<Self>, String> {
+        objects
+            .iter()
+            .filter(|x| x.kind == "warp_exit")
+            .map(|object| {
+                Ok(Self {
+                    name: object.name.clone(),
+                    position: object.transform,
+                    direction: direction_property(object)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Top-left player position when it has fully come out, feet on the
+    /// bottom edge of the exit object and centered on it.
+    fn end_position(&self, size: (f64, f64)) -> (f64, f64) {
+        let x = self.position.center_xw() - size.0 / 2.0;
+        (x, self.position.yh() - size.1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarpPhase {
+    Enter(f64),
+    Exit(f64),
+}
+
+/// Moves the player into a pipe, then out of the exit once the game has
+/// switched areas.
+pub struct WarpTransition {
+    pub warp: Warp,
+    phase: WarpPhase,
+    exit_direction: Option<PipeDirection>,
+    /// The side of the pipe mouth the player disappears into, and where the
+    /// mouth is along that axis.
+    mouth: Option<(PipeDirection, f64)>,
+}
+
+impl WarpTransition {
+    pub fn new<I: ImageSize>(warp: Warp, player: &Player<I>) -> Self {
+        let body = player.get_transform();
+        let distance = match warp.direction {
+            PipeDirection::Up | PipeDirection::Down => body.h(),
+            PipeDirection::Left | PipeDirection::Right => body.w(),
+        };
+        Self {
+            mouth: Some((warp.direction, warp.direction.edge(body))),
+            warp,
+            phase: WarpPhase::Enter(distance),
+            exit_direction: None,
+        }
+    }
+
+    /// Places the player inside the exit pipe, or directly at the exit when
+    /// it has no direction.
+    pub fn start_exit<I: ImageSize>(&mut self, exit: &WarpExit, player: &mut Player<I>) {
+        let body = player.get_transform();
+        let size = (body.w(), body.h());
+        let (x, y) = exit.end_position(size);
+        match exit.direction {
+            Some(direction) => {
+                let distance = match direction {
+                    PipeDirection::Up | PipeDirection::Down => size.1,
+                    PipeDirection::Left | PipeDirection::Right => size.0,
+                };
+                let (dx, dy) = direction.offset();
+                let mut end = *player.get_transform();
+                end.set_position(x, y);
+                let hidden = direction.opposite();
+                self.mouth = Some((hidden, hidden.edge(&end)));
+                player.set_position(x - dx * distance, y - dy * distance);
+                self.phase = WarpPhase::Exit(distance);
+                self.exit_direction = Some(direction);
+            }
+            None => {
+                player.set_position(x, y);
+                self.mouth = None;
+                self.phase = WarpPhase::Exit(0.0);
+            }
+        }
+    }
+
+    /// The part around the player that lies inside the pipe. Drawing the map
+    /// over it again makes the player slide behind the pipe.
+    pub fn hidden_area<I: ImageSize>(&self, player: &Player<I>) -> Option<[f64; 4]> {
+        let (side, mouth) = self.mouth?;
+        let body = player.get_transform();
+        let (x, y, xw, yh) = (
+            body.x() - 2.0,
+            body.y() - 2.0,
+            body.xw() + 2.0,
+            body.yh() + 2.0,
+        );
+        let (x, y, xw, yh) = match side {
+            PipeDirection::Up => (x, y, xw, yh.min(mouth)),
+            PipeDirection::Down => (x, y.max(mouth), xw, yh),
+            PipeDirection::Left => (x, y, xw.min(mouth), yh),
+            PipeDirection::Right => (x.max(mouth), y, xw, yh),
+        };
+        if xw <= x || yh <= y {
+            return None;
+        }
+        Some([x, y, xw - x, yh - y])
+    }
+
+    /// Advances the animation. Returns the phase that just completed, if
+    /// any: `Enter` means the area should switch now, `Exit` that the
+    /// warp is over.
+    pub fn update<I: ImageSize>(&mut self, dt: f64, player: &mut Player<I>) -> Option<WarpPhase> {
+        let (direction, remaining) = match self.phase {
+            WarpPhase::Enter(remaining) => (Some(self.warp.direction), remaining),
+            WarpPhase::Exit(remaining) => (self.exit_direction, remaining),
+        };
+        if remaining <= 0.0 {
+            return Some(self.phase);
+        }
+
+        let step = (PIPE_SPEED * dt).min(remaining);
+        if let Some(direction) = direction {
+            let (dx, dy) = direction.offset();
+            player.pipe_move(dx * step, dy * step, dt);
+        }
+        self.phase = match self.phase {
+            WarpPhase::Enter(x) => WarpPhase::Enter(x - step),
+            WarpPhase::Exit(x) => WarpPhase::Exit(x - step),
+        };
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::{sprites_manager::SpriteDefinitions, spritesheet::SpriteSheet};
+    use std::path::Path;
+    use std::rc::Rc;
+
+    struct TestTexture;
+
+    impl ImageSize for TestTexture {
+        fn get_size(&self) -> (u32, u32) {
+            (512, 512)
+        }
+    }
+
+    /// Seconds until the player is fully inside a `down` pipe.
+    fn enter_time(dt: f64) -> f64 {
+        let definitions =
+            SpriteDefinitions::load(Path::new("assets/player_animations.json")).unwrap();
+        let mut player = Player::new(SpriteSheet::new(Rc::new(TestTexture)), definitions);
+        let warp = Warp {
+            trigger: Transform::new(),
+            direction: PipeDirection::Down,
+            target_map: None,
+            target: "exit".to_string(),
+        };
+        let mut transition = WarpTransition::new(warp, &player);
+        let mut time = 0.0;
+        while transition.update(dt, &mut player).is_none() {
+            time += dt;
+        }
+        time
+    }
+
+    #[test]
+    fn pipe_speed_follows_the_update_rate() {
+        let (slow, fast) = (enter_time(1.0 / 30.0), enter_time(1.0 / 60.0));
+        assert!((slow - fast).abs() < 1.0 / 30.0, "{} vs {}", slow, fast);
+        assert!((slow - 16.0 / PIPE_SPEED).abs() < 1.0 / 30.0);
+    }
+}
@@ -1,146 +1,707 @@
 use cgmath::Vector2;
-use find_folder::Search;
-use fps_counter::FPSCounter;
 use graphics::Transformed;
 use piston_window::{
-    clear, Button, ButtonArgs, ButtonEvent, EventLoop, G2dTexture, G2dTextureContext, GenericEvent,
-    ImageSize, PistonWindow, RenderEvent, Size, UpdateEvent, WindowSettings,
+    clear, rectangle, Button, ButtonArgs, ButtonEvent, ButtonState, EventLoop, G2dTexture,
+    G2dTextureContext, GenericEvent, Key, PistonWindow, RenderEvent, Size, Text, TextEvent,
+    UpdateEvent, Window, WindowSettings,
 };
-use serde_json::{from_reader, Value};
-use std::fs::File;
-use std::rc::Rc;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 mod libs {
     pub mod animations;
+    pub mod assets;
+    pub mod atlas;
+    pub mod block;
     pub mod camera;
+    pub mod character;
+    pub mod coin;
     pub mod collider;
+    pub mod console;
     pub mod controller;
+    pub mod coop;
+    pub mod death;
+    pub mod debug;
+    pub mod enemy;
+    pub mod entities;
+    pub mod flagpole;
+    pub mod gamepad;
+    pub mod ghost;
+    pub mod hot_reload;
+    pub mod hud;
+    pub mod input;
+    pub mod item;
+    pub mod level;
+    pub mod map;
+    pub mod menu;
+    pub mod netplay;
     pub mod object;
     pub mod physics;
     pub mod player;
+    pub mod rewind;
+    pub mod save;
+    pub mod settings;
+    pub mod snapshot;
     pub mod sprites_manager;
     pub mod spritesheet;
+    pub mod tilemap;
+    pub mod timer;
     pub mod transform;
+    pub mod warp;
 }
 
+use libs::assets::{AssetError, AssetManager, AssetScope, Font, Handle};
+use libs::atlas::Atlas;
+use libs::block::{Block, BlockSnapshot};
 use libs::camera::Camera;
-use libs::object::{Object, Object2D};
-use libs::player::Player;
-use libs::sprites_manager::SpriteManager;
-use libs::spritesheet::{SpriteSheet, SpriteSheetConfig};
-use libs::transform::{Rect, Trans};
+use libs::character::{load_characters, Character};
+use libs::coin::{Coin, CoinPop};
+use libs::collider::{Collision, Side};
+use libs::console::{parse_number, Console, ConsoleHost};
+use libs::coop::{
+    all_out, bounce_players, default_lead, place_beside, player_ids, player_mut, players_mut,
+    Partner, PartnerSnapshot, PlayerId,
+};
+use libs::death::PlayerDeath;
+use libs::debug::{DebugHost, DebugOverlay};
+use libs::enemy::{Enemy, EnemyKind};
+use libs::flagpole::{Flagpole, LevelEnd};
+use libs::gamepad::Gamepads;
+use libs::ghost::{split_text, GhostRun};
+use libs::hot_reload::AssetWatcher;
+use libs::hud::{Hud, HudInfo, HudPlayer};
+use libs::input::Action;
+use libs::item::{Item, ItemKind};
+use libs::level::{LevelHost, LevelRegistry};
+use libs::map::GameMap;
+use libs::menu::{Menu, MenuEvent};
+use libs::netplay::{Netplay, Peer, NET_UPS};
+use libs::object::Object2D;
+use libs::physics::{PhysicsConfig, PhysicsHost, PhysicsProfile};
+use libs::player::{Player, PlayerForm, PlayerHost, PlayerSnapshot};
+use libs::rewind::Rewind;
+use libs::save::{HighScore, SaveData, SaveStore, SLOT_COUNT};
+use libs::settings::{Settings, MAX_SCALE};
+use libs::snapshot::{self, read_transform, write_transform, SNAPSHOT_VERSION};
+use libs::sprites_manager::SpriteDefinitions;
+use libs::spritesheet::SpriteSheet;
+use libs::timer::{LevelTimer, TimerHost};
+use libs::transform::{Rect, Trans, Transform};
+use libs::warp::{Warp, WarpExit, WarpPhase, WarpTransition};
 
 #[derive(Copy, Clone, Hash, PartialEq, Eq)]
 enum Music {
-    World1_1,
+    Track(usize),
 }
 
 #[derive(Copy, Clone, Hash, PartialEq, Eq)]
 enum Sound {}
 
+#[derive(Copy, Clone, PartialEq)]
+enum MenuChoice {
+    Start,
+    TwoPlayers,
+    Coop,
+    Slot(usize),
+    HighScores,
+    Resume,
+    Restart,
+    Options,
+    MusicVolume,
+    Scale,
+    Fullscreen,
+    VSync,
+    Back,
+    QuitToTitle,
+    Quit,
+}
+
+const COINS_PER_LIFE: u32 = 100;
+const START_LIVES: u32 = 3;
+const STAR_TIME: f64 = 10.0;
+const STAR_MUSIC: &str = "star_theme.mp3";
+const DEATH_MUSIC: &str = "death.mp3";
+const HURRY_MUSIC: &str = "hurry_theme.mp3";
+const PLAYER_START: (f64, f64) = (20.0, 20.0);
+const GAME_OVER_TIME: f64 = 4.0;
+const VOLUME_STEPS: f64 = 10.0;
+const SETTINGS_FILE: &str = "settings.json";
+const REWIND_SECONDS: u64 = 10;
+const SPLIT_NOTICE_TIME: f64 = 3.0;
+
+/// The last `checkpoint` object the player passed in the current level,
+/// where they come back after dying.
+#[derive(Clone)]
+struct Checkpoint {
+    map: String,
+    position: Transform,
+}
+
+impl Checkpoint {
+    fn to_json(&self) -> Value {
+        json!({ "map": self.map, "transform": write_transform(&self.position) })
+    }
+
+    fn from_json(json: &Value) -> Result<Option<Self>, String> {
+        match json {
+            Value::Null => Ok(None),
+            x => Ok(Some(Self {
+                map: snapshot::text(x, "map")?.to_string(),
+                position: read_transform(x, "transform")?,
+            })),
+        }
+    }
+}
+
+/// The entities of a map, built before switching to it so a map that fails
+/// to load leaves the current one in place.
+struct AreaEntities {
+    flagpole: Option<Flagpole>,
+    warps: Vec<Warp>,
+    warp_exits: Vec<WarpExit>,
+    checkpoints: Vec<Transform>,
+    blocks: Vec<Block<G2dTexture>>,
+    coins: Vec<Coin<G2dTexture>>,
+}
+
+/// A snapshot read back and checked against the game, ready to be put in
+/// place. `area` is only set when the snapshot is in another map; otherwise
+/// the current area is restored in place.
+struct GameSnapshot {
+    level: usize,
+    area: Option<(GameMap, AreaEntities)>,
+    /// Coins of the current map that are back in play, e.g. when rewinding
+    /// to before they were picked up.
+    returning_coins: Vec<Coin<G2dTexture>>,
+    collected: HashSet<(String, u64)>,
+    emptying: HashMap<(String, u64), f64>,
+    checkpoint: Option<Checkpoint>,
+    character: usize,
+    waiting: Vec<Turn>,
+    blocks: Vec<BlockSnapshot>,
+    items: Vec<Item<G2dTexture>>,
+    enemies: Vec<Enemy<G2dTexture>>,
+    coin_pops: Vec<CoinPop<G2dTexture>>,
+    player: PlayerSnapshot,
+    partner: Option<PartnerSnapshot<G2dTexture>>,
+    camera: Vector2<f64>,
+    timer: LevelTimer,
+    score: u32,
+    coins: u32,
+    lives: u32,
+}
+
+/// A player waiting for their turn in a two-player game, with their own
+/// lives, score and progress.
+struct Turn {
+    character: usize,
+    lives: u32,
+    score: u32,
+    coins: u32,
+    level: usize,
+    form: PlayerForm,
+    checkpoint: Option<Checkpoint>,
+}
+
+impl Turn {
+    fn new(character: usize) -> Self {
+        Self {
+            character,
+            lives: START_LIVES,
+            score: 0,
+            coins: 0,
+            level: 0,
+            form: PlayerForm::Small,
+            checkpoint: None,
+        }
+    }
+}
+
+/// Sprite definitions of the goomba, coin, block and item entities.
+const ENTITY_SPRITES: [&str; 4] = ["goomba.json", "coin.json", "block.json", "items.json"];
+
+/// Sprite definitions of an entity type and the atlas the image they cut
+/// from is packed in.
+struct EntitySprites {
+    definitions: SpriteDefinitions,
+    atlas: Handle<Atlas>,
+}
+
+impl EntitySprites {
+    /// Loads every file of `ENTITY_SPRITES`, in that order, and packs the
+    /// images they name into one atlas shared by all entities.
+    fn load_all(
+        assets: &mut AssetManager,
+        context: &mut G2dTextureContext,
+    ) -> Result<[Self; 4], AssetError> {
+        let definitions = ENTITY_SPRITES
+            .iter()
+            .map(|x| SpriteDefinitions::load(&assets.path(x)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut images: Vec<String> = definitions.iter().map(|x| x.texture.clone()).collect();
+        images.sort();
+        images.dedup();
+        let atlas = assets.load_atlas(context, "entities", &images, AssetScope::Global)?;
+        let mut sprites = definitions
+            .into_iter()
+            .map(|definitions| Self { definitions, atlas });
+        Ok([(); 4].map(|_| sprites.next().unwrap()))
+    }
+
+    fn sprite_sheet(&self, assets: &AssetManager) -> SpriteSheet<G2dTexture> {
+        let atlas = assets.atlas(self.atlas).unwrap();
+        let region = atlas.region(&self.definitions.texture).unwrap();
+        SpriteSheet::from_region(atlas.texture(), region)
+    }
+}
+
 pub struct Game {
     window: PistonWindow,
     size: Size,
+    assets: AssetManager,
+    texture_context: G2dTextureContext,
+    font: Handle<Font>,
     camera: Camera,
     player: Player<G2dTexture>,
-    tilemap: SpriteSheet<G2dTexture>,
-    objects: Vec<Object<G2dTexture>>,
+    player_texture: String,
+    levels: LevelRegistry,
+    level: usize,
+    map: GameMap,
+    physics_config: PhysicsConfig,
+    physics_profile: String,
+    watcher: Option<AssetWatcher>,
+    dev_error: Option<String>,
+    debug: DebugOverlay,
+    console: Console<Game>,
+    enemies: Vec<Enemy<G2dTexture>>,
+    goomba_sprites: EntitySprites,
+    coin_sprites: EntitySprites,
+    block_sprites: EntitySprites,
+    item_sprites: EntitySprites,
+    blocks: Vec<Block<G2dTexture>>,
+    items: Vec<Item<G2dTexture>>,
+    coins: Vec<Coin<G2dTexture>>,
+    coin_pops: Vec<CoinPop<G2dTexture>>,
+    /// Coins taken and blocks used up in the current level, by map name and
+    /// object id, so they stay gone when the player comes back to a map.
+    collected: HashSet<(String, u64)>,
+    /// Time left on multi-coin blocks that were hit in areas the player has
+    /// since left, by map and object id.
+    emptying: HashMap<(String, u64), f64>,
+    coin_count: u32,
+    lives: u32,
+    characters: Vec<Character>,
+    /// Index into `characters` of the one playing now.
+    character: usize,
+    /// The other players in a two-player game, next up first.
+    waiting: Vec<Turn>,
+    /// Player two in a co-op game.
+    partner: Option<Partner<G2dTexture>>,
+    /// Whoever the camera, deaths and level starts go by: player one, or
+    /// player two once player one is out of lives, or whoever is taking a
+    /// pipe or the flagpole.
+    lead: PlayerId,
+    checkpoints: Vec<Transform>,
+    checkpoint: Option<Checkpoint>,
+    death: Option<PlayerDeath>,
+    game_over: Option<f64>,
+    flagpole: Option<Flagpole>,
+    warps: Vec<Warp>,
+    warp_exits: Vec<WarpExit>,
+    warp: Option<WarpTransition>,
+    level_end: Option<LevelEnd>,
+    timer: LevelTimer,
+    score: u32,
+    music_tracks: Vec<String>,
+    /// Optional tracks that aren't there and are skipped.
+    missing_tracks: HashSet<String>,
+    current_track: Option<usize>,
+    star_music: bool,
+    hurry_music: bool,
+    settings: Settings,
+    /// Open menus, the top one taking input. The game is paused while any
+    /// menu is open.
+    menus: Vec<Menu<MenuChoice>>,
+    on_title: bool,
+    saves: SaveStore,
+    /// The save slot the current game is written to.
+    slot: Option<usize>,
+    /// Recent ticks to step back through, kept in dev mode only.
+    rewind: Option<Rewind>,
+    rewinding: bool,
+    /// An online game, or the sync test.
+    netplay: Option<Netplay>,
+    /// The best run of the current level, raced as a ghost.
+    ghost: Option<GhostRun>,
+    /// Draws the ghost, posed from `ghost` every tick.
+    ghost_player: Player<G2dTexture>,
+    ghost_visible: bool,
+    /// The run being recorded. Only runs from the start of a level in a
+    /// one-player game are, not ones from a checkpoint or a snapshot.
+    attempt: Option<GhostRun>,
+    attempt_time: f64,
+    /// How the last checkpoint or finish compared to the ghost, and how
+    /// much longer to show it.
+    split_notice: Option<(String, f64)>,
 }
 impl Game {
-    pub fn new(size: Size, viewport_size: Size) -> Self {
+    pub fn new(
+        viewport_size: Size,
+        settings: Settings,
+        dev_mode: bool,
+    ) -> Result<Self, AssetError> {
+        let size = Size::from([
+            viewport_size.width * settings.scale,
+            viewport_size.height * settings.scale,
+        ]);
         let mut window: PistonWindow = WindowSettings::new("Super Goomba Bros", size)
-            .exit_on_esc(true)
+            .exit_on_esc(false)
+            .fullscreen(settings.fullscreen)
+            .vsync(settings.vsync)
             .build()
             .unwrap_or_else(|e| panic!("Failed to build PistonWindow: {}", e));
-        window.set_ups(30);
+        window.set_ups(settings.ups);
+        // A fullscreen window is as big as the screen, not the requested size,
+        // so the view is scaled up to fill as much of it as fits.
+        let size = window.size();
+        let scale = match settings.fullscreen {
+            true => (size.width / viewport_size.width)
+                .min(size.height / viewport_size.height)
+                .floor()
+                .max(1.0),
+            false => settings.scale,
+        };
 
+        let mut assets = AssetManager::new()?;
         let mut context = window.create_texture_context();
+        let font = assets.load_font(
+            window.create_texture_context(),
+            "DejaVuSansMono.ttf",
+            AssetScope::Global,
+        )?;
 
-        let map_texture = Self::load_texture(&mut context, "world_1.png");
-        let map_size = map_texture.get_size();
-        let tilemap = SpriteSheet::new(map_texture);
+        let levels = LevelRegistry::load(&assets.path("levels.json"))?;
+        let map_name = levels.get(0).unwrap().map.clone();
+        let levels_time = levels.get(0).unwrap().time;
+        let map = GameMap::load(&mut assets, &mut context, &map_name)?;
 
-        let player = Self::load_player(&mut context);
+        let (player, player_texture) = Self::load_player(&mut assets, &mut context)?;
+        let (ghost_player, _) = Self::load_player(&mut assets, &mut context)?;
+        let characters = load_characters(&assets.path("characters.json"))?;
 
         let camera = Camera::new(
             viewport_size.width,
             viewport_size.height,
-            map_size.0.into(),
-            map_size.1 as f64,
-            3.0,
+            map.size.width,
+            map.size.height,
+            scale,
         );
 
-        let objects = Self::load_objects();
+        let [goomba_sprites, coin_sprites, block_sprites, item_sprites] =
+            EntitySprites::load_all(&mut assets, &mut context)?;
+
+        let physics_config = PhysicsConfig::load(&assets.path("physics.json"))?;
+        let physics_profile = physics_config.default_profile().to_string();
+
+        let watcher = match dev_mode {
+            true => Some(AssetWatcher::new(assets.root(), 0.5)),
+            false => None,
+        };
+        let rewind = match dev_mode {
+            true => Some(Rewind::new((REWIND_SECONDS * settings.ups) as usize)),
+            false => None,
+        };
 
-        let game = Self {
+        let mut game = Self {
             window,
             size,
+            assets,
+            texture_context: context,
+            font,
             camera,
             player,
-            tilemap,
-            objects,
+            player_texture,
+            levels,
+            level: 0,
+            map,
+            physics_config,
+            physics_profile,
+            watcher,
+            dev_error: None,
+            debug: DebugOverlay::new(),
+            console: Console::new(),
+            enemies: Vec::new(),
+            goomba_sprites,
+            coin_sprites,
+            block_sprites,
+            item_sprites,
+            blocks: Vec::new(),
+            items: Vec::new(),
+            coins: Vec::new(),
+            coin_pops: Vec::new(),
+            collected: HashSet::new(),
+            emptying: HashMap::new(),
+            coin_count: 0,
+            lives: START_LIVES,
+            characters,
+            character: 0,
+            waiting: Vec::new(),
+            partner: None,
+            lead: PlayerId::One,
+            checkpoints: Vec::new(),
+            checkpoint: None,
+            death: None,
+            game_over: None,
+            flagpole: None,
+            warps: Vec::new(),
+            warp_exits: Vec::new(),
+            warp: None,
+            level_end: None,
+            timer: LevelTimer::new(levels_time),
+            score: 0,
+            music_tracks: Vec::new(),
+            missing_tracks: HashSet::new(),
+            current_track: None,
+            star_music: false,
+            hurry_music: false,
+            settings,
+            menus: vec![title_menu()],
+            on_title: true,
+            saves: SaveStore::new(&SaveStore::data_dir()),
+            slot: None,
+            rewind,
+            rewinding: false,
+            netplay: None,
+            ghost: None,
+            ghost_player,
+            ghost_visible: false,
+            attempt: None,
+            attempt_time: 0.0,
+            split_notice: None,
+        };
+        game.read_entities()?;
+        game.set_character(0);
+        game.apply_physics_profile();
+        game.register_commands();
+
+        Ok(game)
+    }
+
+    fn apply_physics_profile(&mut self) {
+        self.apply_player_profile(PlayerId::One);
+        if self.partner.is_some() {
+            self.apply_player_profile(PlayerId::Two);
+        }
+    }
+
+    /// Gives one player the profile for their form, e.g. after a power-up.
+    /// Console tweaks to the other player's profile are left alone.
+    fn apply_player_profile(&mut self, id: PlayerId) {
+        let form = self.player(id).get_form();
+        if let Some(profile) = self.physics_config.profile(&self.physics_profile, form) {
+            let profile = profile.clone();
+            self.player_mut(id).set_physics_profile(profile);
+        }
+    }
+
+    /// Picks the new profiles up after physics.json changed, falling back to
+    /// the default profile if the one in use is gone.
+    fn reload_physics_profiles(&mut self) -> Result<(), AssetError> {
+        self.physics_config.reload()?;
+        if self
+            .physics_config
+            .profile(&self.physics_profile, self.player.get_form())
+            .is_none()
+        {
+            self.physics_profile = self.physics_config.default_profile().to_string();
+        }
+        self.apply_physics_profile();
+        Ok(())
+    }
+
+    fn load_player(
+        assets: &mut AssetManager,
+        context: &mut G2dTextureContext,
+    ) -> Result<(Player<G2dTexture>, String), AssetError> {
+        let definitions = SpriteDefinitions::load(&assets.path("player_animations.json"))?;
+        let texture_name = definitions.texture.clone();
+        let player_texture = assets.load_texture(context, &texture_name, AssetScope::Global)?;
+        let player_sprite_sheet = SpriteSheet::new(assets.texture(player_texture).unwrap());
+
+        Ok((Player::new(player_sprite_sheet, definitions), texture_name))
+    }
+
+    /// Sets up the flagpole, warps, blocks and coins of the current map.
+    fn read_entities(&mut self) -> Result<(), AssetError> {
+        let entities = self.parse_entities(&self.map, &self.collected, &self.emptying)?;
+        self.set_entities(entities);
+        Ok(())
+    }
+
+    /// Builds the entities of `map`, leaving out or using up the coins and
+    /// blocks in `collected` and picking up multi-coin blocks in `emptying`.
+    fn parse_entities(
+        &self,
+        map: &GameMap,
+        collected: &HashSet<(String, u64)>,
+        emptying: &HashMap<(String, u64), f64>,
+    ) -> Result<AreaEntities, AssetError> {
+        let path = self.assets.path(&map.name);
+        let error = |e| AssetError::Parse(path.clone(), e);
+        let mut entities = AreaEntities {
+            flagpole: Flagpole::from_objects(&map.entities),
+            warps: Warp::from_objects(&map.entities).map_err(error)?,
+            warp_exits: WarpExit::from_objects(&map.entities).map_err(error)?,
+            checkpoints: map
+                .entities
+                .iter()
+                .filter(|x| x.kind == "checkpoint")
+                .map(|x| x.transform)
+                .collect(),
+            blocks: Vec::new(),
+            coins: Vec::new(),
         };
 
-        game
+        let palette = map
+            .property("palette")
+            .and_then(Value::as_str)
+            .unwrap_or("overworld");
+        for object in &map.entities {
+            let key = (map.name.clone(), object.id);
+            let collected = collected.contains(&key);
+            let emptying = emptying.get(&key);
+            match object.kind.as_str() {
+                "block" => {
+                    let mut block = Block::from_object(
+                        object,
+                        self.block_sprites.sprite_sheet(&self.assets),
+                        self.block_sprites.definitions.clone(),
+                        palette,
+                    )
+                    .map_err(error)?;
+                    if collected {
+                        block.set_used();
+                    } else if let Some(&time) = emptying {
+                        block.set_emptying(time);
+                    }
+                    entities.blocks.push(block);
+                }
+                "coin" if !collected => entities.coins.push(Coin::new(
+                    object.id,
+                    self.coin_sprites.sprite_sheet(&self.assets),
+                    self.coin_sprites.definitions.clone(),
+                    palette,
+                    &object.transform,
+                )),
+                _ => {}
+            }
+        }
+        Ok(entities)
     }
 
-    fn load_texture(context: &mut G2dTextureContext, path: &'static str) -> Rc<G2dTexture> {
-        let assets = Search::Parents(1).for_folder("assets").unwrap();
-        let path = assets.join(path);
-        SpriteManager::<G2dTexture>::load_texture(context, &path)
+    fn set_entities(&mut self, entities: AreaEntities) {
+        self.flagpole = entities.flagpole;
+        self.warps = entities.warps;
+        self.warp_exits = entities.warp_exits;
+        self.checkpoints = entities.checkpoints;
+        self.blocks = entities.blocks;
+        self.coins = entities.coins;
+        self.items.clear();
+        self.coin_pops.clear();
     }
 
-    fn load_player(context: &mut G2dTextureContext) -> Player<G2dTexture> {
-        let player_texture = Self::load_texture(context, "player.png");
-        let player_sprite_sheet = SpriteSheet::new(player_texture);
-        let player_config_default = SpriteSheetConfig {
-            offset: Vector2::from([80.0, 34.0]),
-            spacing: Vector2::from([1.0, 47.0]),
-            grid: [21, 11],
-            sprite_size: Size::from([16.0, 16.0]),
+    /// Re-reads changed assets in dev mode and rebuilds whatever depends on
+    /// them. Errors are kept for display instead of stopping the game.
+    /// Changes wait until a netplay session is over, since reloading on one
+    /// side only would desync it.
+    fn reload_changed_assets(&mut self, dt: f64) {
+        if self.netplay.is_some() {
+            return;
+        }
+        let changed = match self.watcher.as_mut() {
+            Some(watcher) => watcher.poll(dt),
+            None => return,
         };
-        let mut player = Player::new(player_sprite_sheet);
-        player.add_config("default", player_config_default);
-        player.set_current_config("default");
+        if changed.is_empty() {
+            return;
+        }
 
-        player
+        let result = self.reload_assets(&changed);
+        if let Err(e) = &result {
+            eprintln!("Hot reload failed: {}", e);
+        }
+        self.dev_error = result.err().map(|e| e.to_string());
     }
 
-    fn load_objects() -> Vec<Object<G2dTexture>> {
-        let assets = Search::Parents(1).for_folder("assets").unwrap();
-        let path = assets.join("world_1_1.tmj");
-        let file = File::open(path).unwrap();
-        let json_objects: Value = from_reader(file).unwrap();
-        let layers: &Vec<Value> = json_objects
-            .as_object()
-            .unwrap()
-            .get("layers")
-            .unwrap()
-            .as_array()
-            .unwrap();
-        let solid_objects: &Vec<Value> = layers
+    fn reload_assets(&mut self, changed: &[PathBuf]) -> Result<(), AssetError> {
+        for path in changed {
+            self.assets.reload(&mut self.texture_context, path)?;
+        }
+
+        let is_changed = |name: &str| changed.iter().any(|x| x.file_name() == Some(name.as_ref()));
+        let map = &self.map;
+        let map_changed = changed
             .iter()
-            .find(|x| x.get("name").unwrap() == "solid_objects")
-            .unwrap()
-            .get("objects")
-            .unwrap()
-            .as_array()
-            .unwrap();
+            .filter_map(|x| x.file_name().and_then(|x| x.to_str()))
+            .any(|x| map.depends_on(x))
+            || changed
+                .iter()
+                .any(|x| x.extension() == Some("tsj".as_ref()));
+        let player_changed =
+            is_changed("player_animations.json") || is_changed(&self.player_texture);
+
+        if map_changed {
+            let name = self.map.name.clone();
+            self.map = GameMap::load(&mut self.assets, &mut self.texture_context, &name)?;
+            self.read_entities()?;
+            let size = self.map.size;
+            self.camera.set_world_size(size.width, size.height);
+
+            // Keep the player where they were unless the map shrank under them.
+            let position = self.player.get_transform().get_position();
+            let x = position.x.clamp(0.0, (size.width - 16.0).max(0.0));
+            let y = position.y.clamp(0.0, (size.height - 16.0).max(0.0));
+            self.player.set_position(x, y);
+        }
+
+        if is_changed("physics.json") {
+            self.reload_physics_profiles()?;
+        }
+        if ENTITY_SPRITES.iter().any(|x| is_changed(x)) {
+            let [goomba, coin, block, item] =
+                EntitySprites::load_all(&mut self.assets, &mut self.texture_context)?;
+            self.goomba_sprites = goomba;
+            self.coin_sprites = coin;
+            self.block_sprites = block;
+            self.item_sprites = item;
+            self.read_entities()?;
+        }
 
-        let mut objects: Vec<Object<G2dTexture>> = Vec::default();
-        for obj in solid_objects {
-            let json_obj = obj.as_object().unwrap();
-            let x = json_obj.get("x").unwrap().as_f64().unwrap();
-            let y = json_obj.get("y").unwrap().as_f64().unwrap();
-            let w = json_obj.get("width").unwrap().as_f64().unwrap();
-            let h = json_obj.get("height").unwrap().as_f64().unwrap();
-            let mut o = Object::<G2dTexture>::new();
-            o.set_size(w, h);
-            o.set_position(x, y);
-            objects.push(o);
+        if player_changed {
+            let definitions = SpriteDefinitions::load(&self.assets.path("player_animations.json"))?;
+            let texture = self.assets.load_texture(
+                &mut self.texture_context,
+                &definitions.texture,
+                AssetScope::Global,
+            )?;
+            self.player
+                .set_sprite_sheet(SpriteSheet::new(self.assets.texture(texture).unwrap()));
+            if let Some(partner) = self.partner.as_mut() {
+                partner
+                    .player
+                    .set_sprite_sheet(SpriteSheet::new(self.assets.texture(texture).unwrap()));
+                partner.player.set_definitions(definitions.clone());
+            }
+            self.ghost_player
+                .set_sprite_sheet(SpriteSheet::new(self.assets.texture(texture).unwrap()));
+            self.ghost_player.set_definitions(definitions.clone());
+            self.player_texture = definitions.texture.clone();
+            self.player.set_definitions(definitions);
         }
 
-        objects
+        Ok(())
     }
 
     pub fn render<E>(&mut self, e: &E)
@@ -148,6 +709,10 @@ impl Game {
         E: GenericEvent,
     {
         let window_size = self.size;
+        let background = self
+            .map
+            .background()
+            .unwrap_or(self.levels.get(self.level).unwrap().background);
         let camera = &mut self.camera;
         let camera_width = camera.viewport_width;
         let camera_height = camera.viewport_height;
@@ -157,31 +722,25 @@ impl Game {
         let translate_y = (window_size.height - scaled_height) / (2.0 * camera.scale);
 
         let player = &mut self.player;
-        let objects = &mut self.objects;
-
-        let tilemap = &mut self.tilemap;
-
-        tilemap.get_sprite().unwrap().set_src_rect([
-            camera.position.x.max(0.0),
-            0.0,
-            camera_width,
-            camera_height,
-        ]);
+        let partner = &mut self.partner;
+        let ghost = match self.ghost_visible {
+            true => Some(&mut self.ghost_player),
+            false => None,
+        };
+        let map = &mut self.map;
+        let debug = &mut self.debug;
+        let enemies = &mut self.enemies;
+        let blocks = &mut self.blocks;
+        let items = &mut self.items;
+        let coins = &mut self.coins;
+        let coin_pops = &mut self.coin_pops;
+        let warp = &self.warp;
 
         self.window.draw_2d(e, |_, g, _d| {
-            clear([0.0, 0.0, 0.0, 0.5], g);
-        });
-
-        // render map
-        self.window.draw_2d(e, |c, g, _d| {
-            let map_transform = c
-                .scale(camera.scale, camera.scale)
-                .trans(translate_x, translate_y)
-                .transform;
-            tilemap.draw(map_transform, g);
+            clear(background, g);
         });
 
-        // render player and objects
+        // render map, player and objects
         self.window.draw_2d(e, |c, g, _d| {
             let transform = c
                 .scale(camera.scale, camera.scale)
@@ -190,6 +749,28 @@ impl Game {
                     -camera.position.y + translate_y,
                 )
                 .transform;
+            let view = [
+                camera.position.x,
+                camera.position.y,
+                camera_width,
+                camera_height,
+            ];
+            map.draw(view, transform, g);
+            // Items rise out from behind their block.
+            for item in items.iter_mut() {
+                item.draw(transform, g);
+            }
+            for block in blocks.iter_mut() {
+                block.draw(transform, g);
+            }
+            for coin in coins.iter_mut() {
+                coin.draw(transform, g);
+            }
+            for pop in coin_pops.iter_mut() {
+                pop.draw(transform, g);
+            }
+
+            let objects = &mut map.objects;
             for object in objects.iter_mut() {
                 let obj = object.get_transform();
                 if obj.x() < window_size.width && obj.xw() >= 0.0 {
@@ -197,49 +778,2259 @@ impl Game {
                 }
             }
 
+            for enemy in enemies.iter_mut() {
+                enemy.draw(transform, g);
+            }
+
+            if let Some(ghost) = ghost {
+                ghost.draw(transform, g);
+            }
+            if let Some(partner) = partner.as_mut() {
+                partner.player.draw(transform, g);
+            }
             player.draw(transform, g);
+            if let Some(area) = warp.as_ref().and_then(|x| x.hidden_area(player)) {
+                map.draw(area, transform, g);
+            }
+
+            if debug.enabled {
+                debug.draw_world(&map.objects, player, camera, transform, g);
+            }
+        });
+
+        let font = self.assets.font(self.font).unwrap();
+        let mut players = vec![HudPlayer {
+            name: &self.characters[self.character].name,
+            lives: self.lives,
+            score: self.score,
+            coins: self.coin_count,
+        }];
+        if let Some(partner) = self.partner.as_ref() {
+            players.push(HudPlayer {
+                name: &self.characters[partner.character].name,
+                lives: partner.lives,
+                score: partner.score,
+                coins: partner.coins,
+            });
+        }
+        let hud = HudInfo {
+            players,
+            world: &self.levels.get(self.level).unwrap().id,
+            time: Some(self.timer.remaining()),
+        };
+        let game_over = self.game_over.is_some();
+        let rewinding = self.rewinding;
+        let split = self.split_notice.as_ref().map(|(text, _)| text.as_str());
+        let menu = self.menus.last();
+        self.window.draw_2d(e, |c, g, device| {
+            let mut glyphs = font.borrow_mut();
+            let size = [window_size.width, window_size.height];
+            if game_over {
+                Hud::draw_game_over(&mut glyphs, size, c, g);
+            }
+            Hud::draw(&hud, &mut glyphs, window_size.width, c, g);
+            if rewinding {
+                Hud::draw_notice("<< REWIND", &mut glyphs, c, g);
+            } else if let Some(split) = split {
+                Hud::draw_notice(split, &mut glyphs, c, g);
+            }
+            if let Some(menu) = menu {
+                menu.draw(&mut glyphs, size, c, g);
+            }
+            glyphs.factory.encoder.flush(device);
         });
+
+        debug.frame();
+        if debug.enabled {
+            let font = self.assets.font(self.font).unwrap();
+            let mut glyphs = font.borrow_mut();
+            self.window.draw_2d(e, |c, g, device| {
+                debug.draw_text(player, &mut glyphs, c, g);
+                glyphs.factory.encoder.flush(device);
+            });
+        }
+
+        if self.console.open {
+            let font = self.assets.font(self.font).unwrap();
+            let mut glyphs = font.borrow_mut();
+            let console = &self.console;
+            self.window.draw_2d(e, |c, g, device| {
+                console.draw(&mut glyphs, window_size.width, c, g);
+                glyphs.factory.encoder.flush(device);
+            });
+        }
+
+        if let Some(error) = &self.dev_error {
+            let font = self.assets.font(self.font).unwrap();
+            let mut glyphs = font.borrow_mut();
+            let chars: Vec<char> = error.chars().collect();
+            let lines = std::iter::once("Asset reload failed:".to_string())
+                .chain(chars.chunks(100).map(|x| x.iter().collect::<String>()));
+            self.window.draw_2d(e, |c, g, device| {
+                rectangle(
+                    [0.0, 0.0, 0.0, 0.8],
+                    [0.0, 0.0, window_size.width, 110.0],
+                    c.transform,
+                    g,
+                );
+                let text = Text::new_color([1.0, 0.4, 0.4, 1.0], 16);
+                for (i, line) in lines.take(5).enumerate() {
+                    text.draw(
+                        &line,
+                        &mut *glyphs,
+                        &c.draw_state,
+                        c.transform.trans(10.0, 24.0 + 20.0 * i as f64),
+                        g,
+                    )
+                    .ok();
+                }
+                glyphs.factory.encoder.flush(device);
+            });
+        }
     }
 
     pub fn update(&mut self, dt: f64) {
-        self.player.update(dt);
+        let tick_start = Instant::now();
+        self.reload_changed_assets(dt);
+        if self.netplay.is_some() {
+            self.update_netplay(self.menus.is_empty());
+        } else if self.menus.is_empty() {
+            match self.rewinding {
+                true => self.step_back(),
+                false => {
+                    self.tick(dt);
+                    self.record_tick();
+                }
+            }
+        }
+        self.debug.record_tick(tick_start.elapsed());
+    }
 
-        for object in self.objects.iter() {
-            self.player.collide_with(object);
+    /// Keeps the tick for rewinding. Ticks in the middle of a transition
+    /// can't be snapshotted and are skipped.
+    fn record_tick(&mut self) {
+        if self.rewind.is_none() {
+            return;
+        }
+        if let Ok(frame) = self.snapshot() {
+            self.rewind.as_mut().unwrap().record(frame);
         }
+    }
 
-        self.camera.follow_player(&self.player);
+    fn step_back(&mut self) {
+        let frame = match self.rewind.as_mut().and_then(Rewind::step_back) {
+            Some(frame) => frame.clone(),
+            None => return,
+        };
+        if let Err(e) = self.restore_snapshot(&frame) {
+            eprintln!("Rewind failed: {}", e);
+            self.rewinding = false;
+        }
     }
 
-    pub fn update_input(&mut self, args: ButtonArgs) {
-        if let Button::Keyboard(key) = args.button {
-            self.player.update_input(key, args.state);
+    /// Goes back and plays again any frames played with a wrong guess of
+    /// the other side's input, then plays the next frame if the game isn't
+    /// too far ahead. In the sync test every frame is played twice. With a
+    /// menu open inputs are still exchanged but nothing is played, so the
+    /// other side doesn't take the pause for a lost connection.
+    fn update_netplay(&mut self, play: bool) {
+        let result = self
+            .netplay
+            .as_mut()
+            .unwrap()
+            .exchange()
+            .map_err(|e| format!("connection failed: {}", e))
+            .and_then(|()| match play {
+                true => self.roll_back().and_then(|()| self.advance_netplay()),
+                false => Ok(()),
+            });
+        if let Err(e) = result {
+            eprintln!("Netplay stopped: {}", e);
+            self.dev_error = Some(format!("Netplay stopped: {}", e));
+            self.netplay = None;
         }
     }
-}
 
-fn main() {
-    let scale = 3.0;
-    let width = 352.0;
-    let height = 224.0;
-    let window_size: Size = Size::from([width * scale, height * scale]);
-    let viewport_size: Size = Size::from([width, height]);
-    let mut timer = FPSCounter::default();
+    fn roll_back(&mut self) -> Result<(), String> {
+        let netplay = self.netplay.as_mut().unwrap();
+        let (start, state) = match netplay.take_rollback()? {
+            Some(rollback) => rollback,
+            None => return Ok(()),
+        };
+        let end = netplay.frame();
+        self.restore_snapshot(&state)?;
+        for frame in start..end {
+            let state = self.snapshot().ok();
+            self.play_frame(frame, state)?;
+        }
+        Ok(())
+    }
+
+    fn advance_netplay(&mut self) -> Result<(), String> {
+        let state = self.snapshot().ok();
+        let netplay = self.netplay.as_ref().unwrap();
+        if !netplay.can_advance(state.is_some()) {
+            return Ok(());
+        }
+        let frame = netplay.frame();
+        self.play_frame(frame, state)
+    }
 
-    let mut game: Game = Game::new(window_size, viewport_size);
+    /// Plays one netplay frame with both players' inputs for it.
+    fn play_frame(&mut self, frame: u32, state: Option<Value>) -> Result<(), String> {
+        let inputs = self.netplay.as_mut().unwrap().begin_frame(frame, state)?;
+        self.player.set_input_bits(inputs[0]);
+        if let Some(partner) = self.partner.as_mut() {
+            partner.player.set_input_bits(inputs[1]);
+        }
+        self.tick(1.0 / NET_UPS as f64);
+        Ok(())
+    }
 
-    music::start::<Music, Sound, _>(16, || {
-        music::bind_music_file(Music::World1_1, "./assets/main_theme.mp3");
-        music::set_volume(music::MAX_VOLUME);
-        music::play_music(&Music::World1_1, music::Repeat::Forever);
+    fn tick(&mut self, dt: f64) {
+        if self.game_over.is_some() {
+            self.update_game_over(dt);
+            return;
+        }
+        if self.death.is_some() {
+            self.update_death(dt);
+            return;
+        }
+        self.update_ghost(dt);
 
-        while let Some(e) = game.window.next() {
-            if let Some(_) = e.render_args() {
-                game.render(&e);
+        if self.warp.is_some() {
+            self.update_warp(dt);
+            self.follow_camera();
+            return;
+        }
+
+        self.timer.update(dt);
+        if self.timer.is_up() && self.level_end.is_none() {
+            for id in player_ids(self.partner.as_ref(), self.lives > 0) {
+                self.kill(id, false);
             }
+            return;
+        }
 
-            if let Some(u) = e.update_args() {
-                game.update(u.dt);
+        // The others are carried along out of sight during the level end.
+        let lead_moves = self.level_end.as_ref().is_none_or(LevelEnd::player_moves);
+        let moves = |id| match id == self.lead {
+            true => lead_moves,
+            false => self.level_end.is_none(),
+        };
+        let (one_moves, two_moves) = (moves(PlayerId::One), moves(PlayerId::Two));
+        if one_moves && self.lives > 0 {
+            self.player.update(dt);
+            for object in self.map.objects.iter() {
+                self.player.collide_with(object);
+            }
+        }
+        self.update_partner(dt, two_moves);
+        if self.lives > 0 && self.player.get_transform().y() > self.map.size.height {
+            self.kill(PlayerId::One, true);
+        }
+        if self.death.is_some() {
+            return;
+        }
+
+        self.update_star_music();
+        self.update_hurry_music();
+        self.update_blocks(dt);
+        self.update_items(dt);
+        self.collect_coins(dt);
+
+        if self.level_end.is_some() {
+            self.update_level_end(dt);
+        } else {
+            self.update_enemies(dt);
+            self.bounce_players();
+            self.check_checkpoints();
+            self.check_flagpole();
+            self.check_warps();
+        }
+
+        self.follow_camera();
+    }
+
+    /// A death of the lead restarts the level once it is over. Player two
+    /// dies on their own while player one leads.
+    fn kill(&mut self, id: PlayerId, fell: bool) {
+        match id {
+            PlayerId::Two if self.lead != PlayerId::Two => self.kill_partner(fell),
+            _ => self.kill_player(id, fell),
+        }
+    }
+
+    /// Starts the death animation and jingle, with `id` as the lead.
+    fn kill_player(&mut self, id: PlayerId, fell: bool) {
+        self.lead = id;
+        self.player_mut(id).die();
+        self.death = Some(PlayerDeath::new(fell));
+        self.timer.set_frozen(true);
+        self.star_music = false;
+        if self.has_track(DEATH_MUSIC) {
+            if let Err(e) = self.play_music(DEATH_MUSIC, music::Repeat::Times(1)) {
+                eprintln!("Failed to play music: {}", e);
+            }
+        }
+    }
+
+    /// Player two's own death in co-op. Play goes on around them.
+    fn kill_partner(&mut self, fell: bool) {
+        if let Some(partner) = self.partner.as_mut() {
+            partner.kill(fell);
+        }
+    }
+
+    /// Runs player two's physics, or their death and return.
+    fn update_partner(&mut self, dt: f64, moves: bool) {
+        let partner = match self.partner.as_mut() {
+            Some(partner) => partner,
+            None => return,
+        };
+        if let Some(death) = partner.death.as_mut() {
+            // During the level end they come back with the next level.
+            if death.update(dt, &mut partner.player) && self.level_end.is_none() {
+                self.rejoin_partner();
+            }
+            return;
+        }
+        if partner.lives == 0 || !moves {
+            return;
+        }
+        partner.player.update(dt);
+        for object in self.map.objects.iter() {
+            partner.player.collide_with(object);
+        }
+        if partner.player.get_transform().y() > self.map.size.height {
+            self.kill(PlayerId::Two, true);
+        }
+    }
+
+    /// Puts player two back next to player one after a death, a warp or a
+    /// level load.
+    fn rejoin_partner(&mut self) {
+        let lead = *self.player.get_transform();
+        if self.partner.as_mut().is_some_and(|x| x.rejoin(&lead)) {
+            self.apply_player_profile(PlayerId::Two);
+        }
+    }
+
+    /// Puts whoever isn't leading back next to the lead. Player one sits
+    /// the rest of a co-op game out once out of lives.
+    fn regroup(&mut self) {
+        if self.lead == PlayerId::One {
+            self.rejoin_partner();
+            return;
+        }
+        let lead = *self.player(PlayerId::Two).get_transform();
+        if self.lives == 0 {
+            self.player.set_visible(false);
+            self.player.set_input_enabled(false);
+            return;
+        }
+        self.player.revive();
+        place_beside(&mut self.player, &lead);
+    }
+
+    /// Player one leads again, or player two once player one is out of
+    /// lives.
+    fn reset_lead(&mut self) {
+        self.lead = default_lead(self.lives, self.partner.as_ref());
+    }
+
+    /// Takes whoever isn't leading out of sight while the lead goes through
+    /// a pipe or down the flagpole. `regroup` brings them back.
+    fn hide_others(&mut self) {
+        let others = match (self.lead, self.partner.as_mut()) {
+            (PlayerId::One, Some(partner)) => &mut partner.player,
+            (PlayerId::Two, _) => &mut self.player,
+            (PlayerId::One, None) => return,
+        };
+        others.set_visible(false);
+        others.set_input_enabled(false);
+    }
+
+    fn bounce_players(&mut self) {
+        if let [(_, one), (_, two)] =
+            players_mut(&mut self.player, &mut self.partner, self.lives > 0).as_mut_slice()
+        {
+            bounce_players(one, two);
+        }
+    }
+
+    /// Follows the lead. In co-op the view frames the other player as well
+    /// when it can, and drags them along when they fall behind.
+    fn follow_camera(&mut self) {
+        let lead = self.lead;
+        let mut players = players_mut(&mut self.player, &mut self.partner, self.lives > 0);
+        players.sort_by_key(|(id, _)| *id != lead);
+        match players.as_mut_slice() {
+            [(_, lead), (_, other)] if self.warp.is_none() => {
+                self.camera.follow_players(lead, other);
+                self.camera.pull_into_view(other);
+            }
+            _ => {
+                let lead = player_mut(lead, &mut self.player, &mut self.partner);
+                self.camera.follow_player(lead);
+            }
+        }
+    }
+
+    /// Takes a life from the lead once the death animation is over, then
+    /// restarts the level or, once nobody has lives left, ends the game.
+    fn update_death(&mut self, dt: f64) {
+        let done = match self.death.as_mut() {
+            Some(death) => death.update(
+                dt,
+                player_mut(self.lead, &mut self.player, &mut self.partner),
+            ),
+            None => false,
+        };
+        if !done {
+            return;
+        }
+        self.death = None;
+        match (self.lead, self.partner.as_mut()) {
+            (PlayerId::Two, Some(partner)) => partner.lives = partner.lives.saturating_sub(1),
+            _ => self.lives = self.lives.saturating_sub(1),
+        }
+        // Player two dying at the same time, e.g. when time ran out, loses
+        // a life as well.
+        if let Some(partner) = self.partner.as_mut() {
+            partner.settle_death();
+        }
+        let over = all_out(self.lives, self.partner.as_ref());
+        if over {
+            self.end_saved_game();
+        }
+        match self.next_turn() {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("Failed to start the next turn: {}", e);
+                self.dev_error = Some(e.to_string());
+                return;
+            }
+        }
+        if over {
+            self.game_over = Some(GAME_OVER_TIME);
+            self.player.set_visible(false);
+            if let Some(partner) = self.partner.as_mut() {
+                partner.player.set_visible(false);
+            }
+            return;
+        }
+        if let Err(e) = self.respawn_player() {
+            eprintln!("Failed to restart the level: {}", e);
+            self.dev_error = Some(e.to_string());
+        }
+    }
+
+    fn update_game_over(&mut self, dt: f64) {
+        if let Some(time) = self.game_over.as_mut() {
+            *time -= dt;
+            if *time > 0.0 {
+                return;
+            }
+        }
+        self.game_over = None;
+        self.on_title = true;
+        self.netplay = None;
+        self.open_menu(title_menu());
+    }
+
+    /// The whole simulation as JSON: the level and area, the player,
+    /// every entity, the camera, the clock and the run's counters. Nothing
+    /// in the game is random, so there is no generator state to keep.
+    fn snapshot(&self) -> Result<Value, String> {
+        if self.warp.is_some()
+            || self.level_end.is_some()
+            || self.death.is_some()
+            || self.game_over.is_some()
+            || self.partner.as_ref().is_some_and(|x| x.death.is_some())
+        {
+            return Err("can't take a snapshot during a transition".to_string());
+        }
+        let mut collected: Vec<&(String, u64)> = self.collected.iter().collect();
+        collected.sort();
+        let mut emptying: Vec<(&(String, u64), &f64)> = self.emptying.iter().collect();
+        emptying.sort_by(|a, b| a.0.cmp(b.0));
+        let emptying: Vec<Value> = emptying
+            .iter()
+            .map(|((map, id), time)| json!([map, id, time]))
+            .collect();
+        let checkpoint = self.checkpoint.as_ref().map(Checkpoint::to_json);
+        let waiting: Vec<Value> = self
+            .waiting
+            .iter()
+            .map(|x| {
+                json!({
+                    "character": x.character,
+                    "lives": x.lives,
+                    "score": x.score,
+                    "coins": x.coins,
+                    "level": self.levels.get(x.level).unwrap().id,
+                    "form": x.form.name(),
+                    "checkpoint": x.checkpoint.as_ref().map(Checkpoint::to_json),
+                })
+            })
+            .collect();
+        let partner = self.partner.as_ref().map(Partner::snapshot);
+        Ok(json!({
+            "version": SNAPSHOT_VERSION,
+            "level": self.levels.get(self.level).unwrap().id,
+            "map": self.map.name,
+            "score": self.score,
+            "coins": self.coin_count,
+            "lives": self.lives,
+            "character": self.character,
+            "waiting": waiting,
+            "partner": partner,
+            "collected": collected,
+            "emptying": emptying,
+            "checkpoint": checkpoint,
+            "player": self.player.snapshot(),
+            "camera": [self.camera.position.x, self.camera.position.y],
+            "timer": self.timer.snapshot(),
+            "blocks": self.blocks.iter().map(Block::snapshot).collect::<Vec<_>>(),
+            "items": self.items.iter().map(Item::snapshot).collect::<Vec<_>>(),
+            "enemies": self.enemies.iter().map(Enemy::snapshot).collect::<Vec<_>>(),
+            "coin_pops": self.coin_pops.iter().map(CoinPop::snapshot).collect::<Vec<_>>(),
+        }))
+    }
+
+    /// Puts the game back the way `snapshot` found it. The map is only
+    /// reloaded when the snapshot was taken in a different one; otherwise
+    /// everything is restored in place and animations carry on where they
+    /// were. Nothing changes if the snapshot doesn't read back.
+    fn restore_snapshot(&mut self, json: &Value) -> Result<(), String> {
+        let snapshot = self.read_snapshot(json)?;
+        self.apply_snapshot(snapshot);
+        Ok(())
+    }
+
+    /// Reads and checks a whole snapshot, loading its map if it isn't the
+    /// current one, without touching the game.
+    fn read_snapshot(&mut self, json: &Value) -> Result<GameSnapshot, String> {
+        let level = snapshot::text(json, "level")?;
+        let level = self
+            .levels
+            .index_of(level)
+            .ok_or_else(|| format!("unknown level `{}`", level))?;
+        let map_name = snapshot::text(json, "map")?;
+
+        let collected = snapshot::list(json, "collected")?
+            .iter()
+            .map(|x| match x.as_array().map(|x| x.as_slice()) {
+                Some([map, id]) => Some((map.as_str()?.to_string(), id.as_u64()?)),
+                _ => None,
+            })
+            .collect::<Option<_>>()
+            .ok_or("invalid `collected`")?;
+        // Snapshots from before multi-coin blocks were kept across warps
+        // don't have it.
+        let emptying = match json.get("emptying") {
+            Some(Value::Array(blocks)) => blocks
+                .iter()
+                .map(|x| match x.as_array().map(|x| x.as_slice()) {
+                    Some([map, id, time]) => {
+                        Some(((map.as_str()?.to_string(), id.as_u64()?), time.as_f64()?))
+                    }
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .ok_or("invalid `emptying`")?,
+            _ => HashMap::new(),
+        };
+        let area = match level != self.level || map_name != self.map.name {
+            true => {
+                let map = GameMap::load(&mut self.assets, &mut self.texture_context, map_name)
+                    .map_err(|e| e.to_string())?;
+                let entities = self
+                    .parse_entities(&map, &collected, &emptying)
+                    .map_err(|e| e.to_string())?;
+                Some((map, entities))
+            }
+            false => None,
+        };
+        let checkpoint = Checkpoint::from_json(json.get("checkpoint").unwrap_or(&Value::Null))?;
+        // Snapshots from before two-player games have neither field.
+        let character = json.get("character").and_then(Value::as_u64).unwrap_or(0) as usize;
+        if character >= self.characters.len() {
+            return Err(format!("unknown character {}", character));
+        }
+        let waiting = match json.get("waiting") {
+            Some(Value::Array(turns)) => turns
+                .iter()
+                .map(|x| self.read_turn(x))
+                .collect::<Result<_, _>>()?,
+            _ => Vec::new(),
+        };
+
+        let blocks = snapshot::list(json, "blocks")?
+            .iter()
+            .map(BlockSnapshot::from_json)
+            .collect::<Result<_, _>>()?;
+        let palette = area
+            .as_ref()
+            .map_or(&self.map, |x| &x.0)
+            .property("palette")
+            .and_then(Value::as_str)
+            .unwrap_or("overworld")
+            .to_string();
+        let mut items = Vec::new();
+        for item in snapshot::list(json, "items")? {
+            let kind = snapshot::text(item, "kind")?;
+            let kind =
+                ItemKind::from_name(kind).ok_or_else(|| format!("unknown item `{}`", kind))?;
+            let mut x = Item::new(
+                kind,
+                self.item_sprites.sprite_sheet(&self.assets),
+                self.item_sprites.definitions.clone(),
+                &Transform::new(),
+            );
+            x.restore(item)?;
+            items.push(x);
+        }
+        let mut enemies = Vec::new();
+        for enemy in snapshot::list(json, "enemies")? {
+            let kind = snapshot::text(enemy, "kind")?;
+            let kind =
+                EnemyKind::from_name(kind).ok_or_else(|| format!("unknown enemy `{}`", kind))?;
+            let mut x = self.new_enemy(kind, 0.0, 0.0);
+            x.restore(enemy)?;
+            enemies.push(x);
+        }
+        let mut coin_pops = Vec::new();
+        for pop in snapshot::list(json, "coin_pops")? {
+            let mut x = CoinPop::new(
+                self.coin_sprites.sprite_sheet(&self.assets),
+                self.coin_sprites.definitions.clone(),
+                &palette,
+                &Transform::new(),
+            );
+            x.restore(pop)?;
+            coin_pops.push(x);
+        }
+        let returning_coins = match area {
+            Some(_) => Vec::new(),
+            None => self.returning_coins(&collected, &palette),
+        };
+
+        let partner = match json.get("partner") {
+            Some(Value::Null) | None => None,
+            Some(x) => Some(self.read_partner(x)?),
+        };
+        let (x, y) = snapshot::pair(json, "camera")?;
+        Ok(GameSnapshot {
+            level,
+            area,
+            returning_coins,
+            collected,
+            emptying,
+            checkpoint,
+            character,
+            waiting,
+            blocks,
+            items,
+            enemies,
+            coin_pops,
+            player: PlayerSnapshot::from_json(&json["player"])?,
+            partner,
+            camera: Vector2::new(x, y),
+            timer: LevelTimer::restore(&json["timer"])?,
+            score: snapshot::number(json, "score")? as u32,
+            coins: snapshot::number(json, "coins")? as u32,
+            lives: snapshot::number(json, "lives")? as u32,
+        })
+    }
+
+    fn apply_snapshot(&mut self, mut snapshot: GameSnapshot) {
+        match snapshot.area {
+            Some((map, entities)) => {
+                if snapshot.level != self.level {
+                    self.assets.unload_level(map.files());
+                    self.level = snapshot.level;
+                }
+                if let Err(e) = self.enter_area(map, entities) {
+                    eprintln!("Failed to play music: {}", e);
+                }
+            }
+            None => {
+                let map = &self.map.name;
+                self.coins
+                    .retain(|x| !snapshot.collected.contains(&(map.clone(), x.id)));
+                if let Some(animation) = self.coins.first().map(Coin::animation) {
+                    for coin in &mut snapshot.returning_coins {
+                        coin.set_animation(&animation);
+                    }
+                }
+                self.coins.append(&mut snapshot.returning_coins);
+            }
+        }
+        self.collected = snapshot.collected;
+        self.emptying = snapshot.emptying;
+        self.checkpoint = snapshot.checkpoint;
+        self.waiting = snapshot.waiting;
+        self.set_character(snapshot.character);
+        self.warp = None;
+        self.level_end = None;
+        self.death = None;
+        self.game_over = None;
+        self.attempt = None;
+        self.ghost_visible = false;
+
+        for block in &snapshot.blocks {
+            if let Some(x) = self.blocks.iter_mut().find(|x| x.id == block.id) {
+                x.restore(block);
+            }
+        }
+        self.items = snapshot.items;
+        self.enemies = snapshot.enemies;
+        self.coin_pops = snapshot.coin_pops;
+
+        self.player.restore(&snapshot.player);
+        let partner = self.partner.take().map(|x| x.player);
+        self.partner = snapshot.partner.and_then(|x| x.restore(partner));
+        if let Some(partner) = self.partner.as_mut() {
+            let palette = self.characters[partner.character].palette;
+            partner.player.set_base_palette(palette);
+        }
+        self.apply_physics_profile();
+        self.camera.position = snapshot.camera;
+        self.timer = snapshot.timer;
+        self.score = snapshot.score;
+        self.coin_count = snapshot.coins;
+        self.lives = snapshot.lives;
+        self.reset_lead();
+
+        // Back to the music that fits the restored state, e.g. after
+        // rewinding through a death jingle. Missing tracks were already
+        // reported when they first failed to play.
+        self.star_music = self.player.is_star();
+        self.hurry_music = self.timer.is_hurry();
+        let _ = self.play_area_music();
+    }
+
+    /// Coins of the current map that are neither collected in `collected`
+    /// nor in play.
+    fn returning_coins(
+        &self,
+        collected: &HashSet<(String, u64)>,
+        palette: &str,
+    ) -> Vec<Coin<G2dTexture>> {
+        self.map
+            .entities
+            .iter()
+            .filter(|x| x.kind == "coin")
+            .filter(|x| !collected.contains(&(self.map.name.clone(), x.id)))
+            .filter(|x| self.coins.iter().all(|coin| coin.id != x.id))
+            .map(|x| {
+                Coin::new(
+                    x.id,
+                    self.coin_sprites.sprite_sheet(&self.assets),
+                    self.coin_sprites.definitions.clone(),
+                    palette,
+                    &x.transform,
+                )
+            })
+            .collect()
+    }
+
+    /// Reads player two back from a snapshot. A player is only loaded for
+    /// them if they aren't in the game already.
+    fn read_partner(&mut self, json: &Value) -> Result<PartnerSnapshot<G2dTexture>, String> {
+        let mut partner = PartnerSnapshot::from_json(json)?;
+        if partner.character >= self.characters.len() {
+            return Err(format!("unknown character {}", partner.character));
+        }
+        if self.partner.is_none() {
+            let (player, _) = Self::load_player(&mut self.assets, &mut self.texture_context)
+                .map_err(|e| e.to_string())?;
+            partner.new_player = Some(player);
+        }
+        Ok(partner)
+    }
+
+    fn quick_save(&mut self) {
+        let result = self
+            .snapshot()
+            .and_then(|x| self.saves.save_snapshot(&x).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => self.console.print("quick saved"),
+            Err(e) => {
+                eprintln!("Quick save failed: {}", e);
+                self.console.print(&format!("quick save failed: {}", e));
+            }
+        }
+    }
+
+    fn quick_load(&mut self) {
+        let result = match self.saves.load_snapshot() {
+            Ok(Some(x)) => self.restore_snapshot(&x),
+            Ok(None) => Err("no quick save yet".to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match result {
+            Ok(()) => {
+                self.clear_input();
+                self.console.print("quick loaded");
+            }
+            Err(e) => {
+                eprintln!("Quick load failed: {}", e);
+                self.console.print(&format!("quick load failed: {}", e));
+            }
+        }
+    }
+
+    /// Enters the final score in the high-score table and empties the save
+    /// slot, since there is no game left to continue.
+    fn end_saved_game(&mut self) {
+        let entry = HighScore {
+            score: self.score,
+            world: self.levels.get(self.level).unwrap().id.clone(),
+        };
+        if let Err(e) = self.saves.add_high_score(entry) {
+            eprintln!("Failed to save the high score: {}", e);
+        }
+        if let Some(partner) = self.partner.as_ref() {
+            let entry = HighScore {
+                score: partner.score,
+                world: self.levels.get(self.level).unwrap().id.clone(),
+            };
+            if let Err(e) = self.saves.add_high_score(entry) {
+                eprintln!("Failed to save the high score: {}", e);
+            }
+        }
+        if let Some(slot) = self.slot {
+            if let Err(e) = self.saves.clear_slot(slot) {
+                eprintln!("Failed to clear save slot {}: {}", slot + 1, e);
+            }
+        }
+    }
+
+    /// Writes the reached level, lives, score, coins and power-up to the
+    /// current slot.
+    fn save_progress(&mut self) {
+        let slot = match self.slot {
+            Some(slot) => slot,
+            None => return,
+        };
+        let data = SaveData {
+            world: self.levels.get(self.level).unwrap().id.clone(),
+            lives: self.lives,
+            score: self.score,
+            coins: self.coin_count,
+            form: self.player.get_form(),
+        };
+        if let Err(e) = self.saves.save_slot(slot, &data) {
+            eprintln!("Failed to save slot {}: {}", slot + 1, e);
+        }
+    }
+
+    /// Continues the game `data` read from `slot`, or starts a new one there
+    /// if the slot is empty.
+    fn start_slot(&mut self, slot: usize, data: Option<SaveData>) -> Result<(), AssetError> {
+        self.slot = Some(slot);
+        let data = match data {
+            Some(data) => data,
+            None => return self.new_game(),
+        };
+        self.set_character(0);
+        self.waiting.clear();
+        self.partner = None;
+        self.lives = data.lives.max(1);
+        self.score = data.score;
+        self.coin_count = data.coins;
+        self.checkpoint = None;
+        self.player.set_form(data.form);
+        self.apply_physics_profile();
+        self.load_level(self.levels.index_of(&data.world).unwrap_or(0))
+    }
+
+    /// Starts over from the first level with a fresh score and lives.
+    fn new_game(&mut self) -> Result<(), AssetError> {
+        self.set_character(0);
+        self.waiting.clear();
+        self.partner = None;
+        self.lives = START_LIVES;
+        self.score = 0;
+        self.coin_count = 0;
+        self.checkpoint = None;
+        self.player.set_form(PlayerForm::Small);
+        self.apply_physics_profile();
+        self.load_level(0)
+    }
+
+    /// Restarts the level from the last checkpoint reached, if any.
+    fn respawn_player(&mut self) -> Result<(), AssetError> {
+        let checkpoint = self.checkpoint.clone();
+        self.apply_physics_profile();
+        self.load_level(self.level)?;
+        if let Some(checkpoint) = checkpoint {
+            if checkpoint.map != self.map.name {
+                self.load_area(&checkpoint.map)?;
+            }
+            let lead = self.player_mut(self.lead);
+            let (spot, body) = (checkpoint.position, *lead.get_transform());
+            lead.set_position(spot.center_xw() - body.w() / 2.0, spot.yh() - body.h());
+            // A run from a checkpoint can't be compared with a whole one.
+            self.attempt = None;
+            self.regroup();
+            self.follow_camera();
+        }
+        Ok(())
+    }
+
+    /// A game where the players take turns, the next one going whenever
+    /// the current one dies. Nothing is saved to a slot.
+    fn new_two_player_game(&mut self) -> Result<(), AssetError> {
+        self.slot = None;
+        self.new_game()?;
+        self.waiting.push(Turn::new(1));
+        Ok(())
+    }
+
+    /// Both players on screen at once, player two as the second character
+    /// with their own controls. Nothing is saved to a slot.
+    fn new_coop_game(&mut self) -> Result<(), AssetError> {
+        self.slot = None;
+        self.new_game()?;
+        let (mut player, _) = Self::load_player(&mut self.assets, &mut self.texture_context)?;
+        player.set_base_palette(self.characters[1].palette);
+        self.partner = Some(Partner::new(player, 1, START_LIVES));
+        self.rejoin_partner();
+        self.follow_camera();
+        Ok(())
+    }
+
+    /// Starts a co-op game against another copy of the game at the other
+    /// end of `peer`, this side playing as player `local_player + 1`.
+    /// Both sides start the same game and run it at the same fixed rate.
+    pub fn start_netplay(&mut self, peer: Peer, local_player: usize) -> Result<(), AssetError> {
+        self.new_coop_game()?;
+        self.start_session(Netplay::online(peer, local_player));
+        Ok(())
+    }
+
+    /// Plays a normal game with every frame rolled back and played again,
+    /// stopping at the first frame that comes out different.
+    pub fn start_sync_test(&mut self) -> Result<(), AssetError> {
+        self.slot = None;
+        self.new_game()?;
+        self.start_session(Netplay::sync_test());
+        Ok(())
+    }
+
+    fn start_session(&mut self, netplay: Netplay) {
+        self.on_title = false;
+        self.menus.clear();
+        self.apply_volume();
+        self.rewind = None;
+        self.rewinding = false;
+        self.window.set_ups(NET_UPS);
+        self.netplay = Some(netplay);
+    }
+
+    fn set_character(&mut self, character: usize) {
+        self.character = character;
+        self.player
+            .set_base_palette(self.characters[character].palette);
+    }
+
+    /// Hands the game to the next player in line, who picks up where they
+    /// left off. The current one goes to the back of the line unless they
+    /// are out of lives. Returns false when nobody is waiting.
+    fn next_turn(&mut self) -> Result<bool, AssetError> {
+        if self.waiting.is_empty() {
+            return Ok(false);
+        }
+        let next = self.waiting.remove(0);
+        if self.lives > 0 {
+            self.waiting.push(Turn {
+                character: self.character,
+                lives: self.lives,
+                score: self.score,
+                coins: self.coin_count,
+                level: self.level,
+                form: self.player.get_form(),
+                checkpoint: self.checkpoint.take(),
+            });
+        }
+        self.set_character(next.character);
+        self.lives = next.lives;
+        self.score = next.score;
+        self.coin_count = next.coins;
+        self.level = next.level;
+        self.checkpoint = next.checkpoint;
+        self.player.set_form(next.form);
+        self.respawn_player()?;
+        Ok(true)
+    }
+
+    fn read_turn(&self, json: &Value) -> Result<Turn, String> {
+        let character = snapshot::number(json, "character")? as usize;
+        if character >= self.characters.len() {
+            return Err(format!("unknown character {}", character));
+        }
+        let level = snapshot::text(json, "level")?;
+        let form = snapshot::text(json, "form")?;
+        Ok(Turn {
+            character,
+            lives: snapshot::number(json, "lives")? as u32,
+            score: snapshot::number(json, "score")? as u32,
+            coins: snapshot::number(json, "coins")? as u32,
+            level: self
+                .levels
+                .index_of(level)
+                .ok_or_else(|| format!("unknown level `{}`", level))?,
+            form: PlayerForm::from_name(form).ok_or_else(|| format!("unknown form `{}`", form))?,
+            checkpoint: Checkpoint::from_json(json.get("checkpoint").unwrap_or(&Value::Null))?,
+        })
+    }
+
+    /// Remembers the furthest checkpoint the player has walked past.
+    fn check_checkpoints(&mut self) {
+        let x = player_ids(self.partner.as_ref(), self.lives > 0)
+            .into_iter()
+            .map(|id| self.player(id).get_transform().x())
+            .fold(f64::MIN, f64::max);
+        let map = &self.map.name;
+        let mut reached = false;
+        for spot in self.checkpoints.iter().filter(|spot| x >= spot.x()) {
+            let further = match &self.checkpoint {
+                Some(checkpoint) => &checkpoint.map != map || spot.x() > checkpoint.position.x(),
+                None => true,
+            };
+            if further {
+                self.checkpoint = Some(Checkpoint {
+                    map: map.clone(),
+                    position: *spot,
+                });
+                reached = true;
+            }
+        }
+        if reached {
+            self.split();
+        }
+    }
+
+    /// Records the player's path and moves the ghost along the best run.
+    fn update_ghost(&mut self, dt: f64) {
+        if let Some((_, time)) = self.split_notice.as_mut() {
+            *time -= dt;
+            if *time <= 0.0 {
+                self.split_notice = None;
+            }
+        }
+        if self.partner.is_some() || self.netplay.is_some() {
+            self.attempt = None;
+        }
+        let attempt = match self.attempt.as_mut() {
+            Some(attempt) => attempt,
+            None => {
+                self.ghost_visible = false;
+                return;
+            }
+        };
+        let time = self.attempt_time;
+        attempt.record(time, &self.map.name, &self.player);
+        self.ghost_visible = match &self.ghost {
+            Some(ghost) => ghost.pose(time, &self.map.name, &mut self.ghost_player, dt),
+            None => false,
+        };
+        self.attempt_time += dt;
+    }
+
+    /// Starts recording a run from the start of the level and loads the
+    /// best one to race.
+    fn start_attempt(&mut self) {
+        let level = &self.levels.get(self.level).unwrap().id;
+        self.ghost = self.saves.load_ghost(level).unwrap_or_else(|e| {
+            eprintln!("Failed to load the ghost: {}", e);
+            None
+        });
+        self.attempt = Some(GhostRun::new());
+        self.attempt_time = 0.0;
+        self.ghost_visible = false;
+    }
+
+    /// Shows how reaching a checkpoint compares to the ghost.
+    fn split(&mut self) {
+        let attempt = match self.attempt.as_mut() {
+            Some(attempt) => attempt,
+            None => return,
+        };
+        let index = attempt.add_split(self.attempt_time);
+        if let Some(best) = self.ghost.as_ref().and_then(|x| x.split(index)) {
+            let text = split_text(self.attempt_time, best);
+            self.split_notice = Some((text, SPLIT_NOTICE_TIME));
+        }
+    }
+
+    /// Ends the run at the flagpole and keeps it as the new ghost if it
+    /// beat the old one.
+    fn finish_attempt(&mut self) {
+        let attempt = match self.attempt.as_mut() {
+            Some(attempt) => attempt,
+            None => return,
+        };
+        let time = self.attempt_time;
+        attempt.finish(time);
+        let best = self.ghost.as_ref().and_then(GhostRun::time);
+        if let Some(best) = best {
+            self.split_notice = Some((split_text(time, best), SPLIT_NOTICE_TIME));
+        }
+        if best.is_some_and(|best| best <= time) {
+            return;
+        }
+        let level = &self.levels.get(self.level).unwrap().id;
+        if let Err(e) = self.saves.save_ghost(level, attempt) {
+            eprintln!("Failed to save the ghost: {}", e);
+        }
+    }
+
+    fn update_enemies(&mut self, dt: f64) {
+        for enemy in self.enemies.iter_mut() {
+            enemy.update(dt);
+            for object in self.map.objects.iter() {
+                enemy.collide_with(object);
+            }
+        }
+
+        let mut scored = Vec::new();
+        let mut fatal = Vec::new();
+        let mut shrunk = Vec::new();
+        for enemy in self.enemies.iter_mut() {
+            for (id, player) in players_mut(&mut self.player, &mut self.partner, self.lives > 0) {
+                if !enemy.is_alive() {
+                    break;
+                }
+                let (collide, _) = Collision::aabb(player.get_transform(), enemy.get_transform());
+                if !collide {
+                    continue;
+                }
+                // Falling onto the top half of an enemy stomps it; any other
+                // contact hurts the player.
+                let body = player.get_transform();
+                let falling = player.get_velocity().y > 0.0;
+                if player.is_star() {
+                    enemy.knock();
+                    scored.push(id);
+                } else if falling && body.yh() < enemy.get_transform().center_yh() {
+                    enemy.stomp();
+                    player.bounce();
+                    scored.push(id);
+                } else {
+                    let form = player.get_form();
+                    if player.hurt() {
+                        fatal.push(id);
+                    } else if player.get_form() != form {
+                        shrunk.push(id);
+                    }
+                }
+            }
+        }
+        self.enemies.retain(|x| !x.is_removed());
+
+        for id in scored {
+            self.add_score(id, 100);
+        }
+        for id in shrunk {
+            self.apply_player_profile(id);
+        }
+        for id in fatal {
+            self.kill(id, false);
+        }
+    }
+
+    fn update_blocks(&mut self, dt: f64) {
+        for block in self.blocks.iter_mut() {
+            block.update(dt);
+        }
+        for pop in self.coin_pops.iter_mut() {
+            pop.update(dt);
+        }
+        self.coin_pops.retain(|x| !x.is_finished());
+
+        for id in player_ids(self.partner.as_ref(), self.lives > 0) {
+            if self.player(id).get_contacts().contains(&Side::TOP) {
+                self.hit_block(id);
+            }
+        }
+    }
+
+    /// Hits the block the player bumps their head on, the one closest to
+    /// their center when they touch several.
+    fn hit_block(&mut self, id: PlayerId) {
+        let body = *self.player(id).get_transform();
+        let form = self.player(id).get_form();
+        let distance = |block: &Block<G2dTexture>| {
+            (block.get_transform().center_xw() - body.center_xw()).abs()
+        };
+        let block = self
+            .blocks
+            .iter_mut()
+            .filter(|x| {
+                let block = x.get_transform();
+                (block.yh() - body.y()).abs() < 2.0
+                    && block.x() < body.xw()
+                    && block.xw() > body.x()
+            })
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)));
+        let block = match block {
+            Some(block) => block,
+            None => return,
+        };
+
+        let contents = block.hit();
+        let item = contents.and_then(|x| x.item(form));
+        let block_id = block.id;
+        let used = block.is_used();
+        let transform = *block.get_transform();
+        if used {
+            self.collected.insert((self.map.name.clone(), block_id));
+        }
+        match (contents, item) {
+            (_, Some(kind)) => self.items.push(Item::new(
+                kind,
+                self.item_sprites.sprite_sheet(&self.assets),
+                self.item_sprites.definitions.clone(),
+                &transform,
+            )),
+            (Some(_), None) => {
+                let palette = self
+                    .map
+                    .property("palette")
+                    .and_then(Value::as_str)
+                    .unwrap_or("overworld");
+                self.coin_pops.push(CoinPop::new(
+                    self.coin_sprites.sprite_sheet(&self.assets),
+                    self.coin_sprites.definitions.clone(),
+                    palette,
+                    &transform,
+                ));
+                self.add_coin(id);
+            }
+            (None, None) => {}
+        }
+    }
+
+    fn update_items(&mut self, dt: f64) {
+        let mut taken = Vec::new();
+        for (i, item) in self.items.iter_mut().enumerate() {
+            item.update(dt);
+            for object in self.map.objects.iter() {
+                item.collide_with(object);
+            }
+            if !item.is_collectable() {
+                continue;
+            }
+            let taker = players_mut(&mut self.player, &mut self.partner, self.lives > 0)
+                .into_iter()
+                .find(|(_, player)| {
+                    Collision::aabb(player.get_transform(), item.get_transform()).0
+                });
+            if let Some((id, _)) = taker {
+                taken.push((i, id));
+            }
+        }
+        for (i, id) in taken.into_iter().rev() {
+            let item = self.items.remove(i);
+            self.apply_item(id, item.get_kind());
+        }
+
+        let bottom = self.map.size.height;
+        self.items.retain(|x| x.get_transform().y() < bottom);
+    }
+
+    fn apply_item(&mut self, id: PlayerId, kind: ItemKind) {
+        let player = self.player_mut(id);
+        let form = player.get_form();
+        match kind {
+            ItemKind::Mushroom => {
+                if player.get_form() == PlayerForm::Small {
+                    player.grow(PlayerForm::Super);
+                }
+            }
+            ItemKind::FireFlower => {
+                if player.get_form() != PlayerForm::Fire {
+                    player.grow(PlayerForm::Fire);
+                }
+            }
+            ItemKind::Star => player.start_star(STAR_TIME),
+            ItemKind::OneUp => {
+                self.add_life(id);
+                return;
+            }
+        }
+        // Only a new form needs its profile; reapplying it otherwise would
+        // undo `physics` tweaks made in the console.
+        if self.player(id).get_form() != form {
+            self.apply_player_profile(id);
+        }
+        self.add_score(id, 1000);
+    }
+
+    fn collect_coins(&mut self, dt: f64) {
+        let mut taken = Vec::new();
+        for coin in self.coins.iter_mut() {
+            coin.update(dt);
+            let taker = players_mut(&mut self.player, &mut self.partner, self.lives > 0)
+                .into_iter()
+                .find(|(_, player)| {
+                    Collision::aabb(player.get_transform(), coin.get_transform()).0
+                });
+            if let Some((id, _)) = taker {
+                taken.push((coin.id, id));
+            }
+        }
+        for (coin, id) in taken {
+            self.coins.retain(|x| x.id != coin);
+            self.collected.insert((self.map.name.clone(), coin));
+            self.add_coin(id);
+        }
+    }
+
+    /// Switches to the star theme while the player is invincible and back
+    /// to the area's music afterwards. Without a star theme in the assets
+    /// the area music just keeps playing.
+    fn update_star_music(&mut self) {
+        let star = player_ids(self.partner.as_ref(), self.lives > 0)
+            .into_iter()
+            .any(|id| self.player(id).is_star());
+        if star == self.star_music {
+            return;
+        }
+        self.star_music = star;
+        if let Err(e) = self.play_area_music() {
+            eprintln!("Failed to play music: {}", e);
+        }
+    }
+
+    /// Speeds the music up once the clock is down to its last hundred
+    /// units. The star theme keeps playing if the player has a star.
+    fn update_hurry_music(&mut self) {
+        let hurry = self.timer.is_hurry();
+        if hurry == self.hurry_music {
+            return;
+        }
+        self.hurry_music = hurry;
+        if self.star_music {
+            return;
+        }
+        if let Err(e) = self.play_level_music() {
+            eprintln!("Failed to play music: {}", e);
+        }
+    }
+
+    /// Counts a coin, turning every hundred into an extra life.
+    fn add_coin(&mut self, id: PlayerId) {
+        self.add_score(id, 200);
+        let (coins, lives) = match (id, self.partner.as_mut()) {
+            (PlayerId::Two, Some(partner)) => (&mut partner.coins, &mut partner.lives),
+            _ => (&mut self.coin_count, &mut self.lives),
+        };
+        *coins += 1;
+        if *coins >= COINS_PER_LIFE {
+            *coins -= COINS_PER_LIFE;
+            *lives += 1;
+        }
+    }
+
+    fn add_score(&mut self, id: PlayerId, points: u32) {
+        match (id, self.partner.as_mut()) {
+            (PlayerId::Two, Some(partner)) => partner.score += points,
+            _ => self.score += points,
+        }
+    }
+
+    fn add_life(&mut self, id: PlayerId) {
+        match (id, self.partner.as_mut()) {
+            (PlayerId::Two, Some(partner)) => partner.lives += 1,
+            _ => self.lives += 1,
+        }
+    }
+
+    fn player(&self, id: PlayerId) -> &Player<G2dTexture> {
+        match (id, self.partner.as_ref()) {
+            (PlayerId::Two, Some(partner)) => &partner.player,
+            _ => &self.player,
+        }
+    }
+
+    fn player_mut(&mut self, id: PlayerId) -> &mut Player<G2dTexture> {
+        player_mut(id, &mut self.player, &mut self.partner)
+    }
+
+    /// Starts the level-end sequence when a player touches the pole,
+    /// scoring by how high they grabbed it. They lead the sequence and the
+    /// other player is carried along.
+    fn check_flagpole(&mut self) {
+        let flagpole = match &self.flagpole {
+            Some(flagpole) => flagpole,
+            None => return,
+        };
+        let pole = *flagpole.get_transform();
+        let id = match player_ids(self.partner.as_ref(), self.lives > 0)
+            .into_iter()
+            .find(|&id| Collision::aabb(self.player(id).get_transform(), &pole).0)
+        {
+            Some(id) => id,
+            None => return,
+        };
+
+        let points = flagpole.score_for_height(self.player(id).get_transform().yh());
+        self.level_end = Some(LevelEnd::new(flagpole));
+        self.add_score(id, points);
+        self.timer.set_frozen(true);
+        self.lead = id;
+        self.player_mut(id).set_input_enabled(false);
+        self.hide_others();
+        self.finish_attempt();
+    }
+
+    /// Starts going down a pipe when a player pushes into a warp. The other
+    /// player is carried through out of sight.
+    fn check_warps(&mut self) {
+        let entered = player_ids(self.partner.as_ref(), self.lives > 0)
+            .into_iter()
+            .find_map(|id| {
+                let player = self.player(id);
+                let warp = self.warps.iter().find(|x| x.can_enter(player))?;
+                Some((id, WarpTransition::new(warp.clone(), player)))
+            });
+        if let Some((id, transition)) = entered {
+            self.warp = Some(transition);
+            self.lead = id;
+            self.player_mut(id).set_input_enabled(false);
+            self.hide_others();
+        }
+    }
+
+    fn update_warp(&mut self, dt: f64) {
+        let phase = match self.warp.as_mut() {
+            Some(warp) => warp.update(
+                dt,
+                player_mut(self.lead, &mut self.player, &mut self.partner),
+            ),
+            None => return,
+        };
+        let result = match phase {
+            Some(WarpPhase::Enter(_)) => self.enter_warp_target(),
+            Some(WarpPhase::Exit(_)) => {
+                self.end_warp();
+                Ok(())
+            }
+            None => Ok(()),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to warp: {}", e);
+            self.dev_error = Some(e);
+            self.end_warp();
+        }
+    }
+
+    /// Hands control back after a warp, bringing the other player out next
+    /// to whoever took the pipe.
+    fn end_warp(&mut self) {
+        self.warp = None;
+        self.player_mut(self.lead).set_input_enabled(true);
+        self.regroup();
+        self.reset_lead();
+    }
+
+    /// Switches to the warp's target area, if it is in another map, and puts
+    /// the player into the exit pipe.
+    fn enter_warp_target(&mut self) -> Result<(), String> {
+        let warp = match &self.warp {
+            Some(transition) => transition.warp.clone(),
+            None => return Ok(()),
+        };
+        if let Some(map) = warp.target_map.as_ref().filter(|x| **x != self.map.name) {
+            self.load_area(map).map_err(|e| e.to_string())?;
+        }
+
+        let exit = self
+            .warp_exits
+            .iter()
+            .find(|x| x.name == warp.target)
+            .ok_or_else(|| format!("no warp exit `{}` in {}", warp.target, self.map.name))?;
+        if let Some(transition) = self.warp.as_mut() {
+            transition.start_exit(
+                exit,
+                player_mut(self.lead, &mut self.player, &mut self.partner),
+            );
+        }
+        self.follow_camera();
+        Ok(())
+    }
+
+    fn update_level_end(&mut self, dt: f64) {
+        let (player, score) = match (self.lead, self.partner.as_mut()) {
+            (PlayerId::Two, Some(partner)) => (&mut partner.player, &mut partner.score),
+            _ => (&mut self.player, &mut self.score),
+        };
+        let done = match self.level_end.as_mut() {
+            Some(level_end) => level_end.update(dt, player, &mut self.timer, score),
+            None => false,
+        };
+        if done {
+            if let Err(e) = self.complete_level() {
+                eprintln!("Failed to load the next level: {}", e);
+                self.dev_error = Some(e.to_string());
+                // Stay on this level rather than tallying forever.
+                self.level_end = None;
+                let lead = self.player_mut(self.lead);
+                lead.set_visible(true);
+                lead.set_input_enabled(true);
+                self.regroup();
+                self.reset_lead();
+            }
+        }
+    }
+
+    /// Swaps in another level's map and music, keeping the player's form.
+    /// The current level is left as it was if the new one fails to load.
+    fn load_level(&mut self, index: usize) -> Result<(), AssetError> {
+        let map_name = self.levels.get(index).unwrap().map.clone();
+        let map = GameMap::load(&mut self.assets, &mut self.texture_context, &map_name)?;
+        let entities = self.parse_entities(&map, &HashSet::new(), &HashMap::new())?;
+        self.bind_area_music(&map, index)?;
+
+        self.assets.unload_level(map.files());
+        self.level = index;
+        self.collected.clear();
+        self.emptying.clear();
+        self.hurry_music = false;
+        self.enter_area(map, entities)?;
+        self.warp = None;
+        self.level_end = None;
+        self.timer = LevelTimer::new(self.level_time());
+
+        self.reset_lead();
+        let lead = self.player_mut(self.lead);
+        lead.revive();
+        lead.set_position(PLAYER_START.0, PLAYER_START.1);
+        self.regroup();
+        self.follow_camera();
+        self.start_attempt();
+        Ok(())
+    }
+
+    /// Switches to another map of the current level, like a bonus room
+    /// behind a pipe. The player is left where they are.
+    fn load_area(&mut self, name: &str) -> Result<(), AssetError> {
+        let map = GameMap::load(&mut self.assets, &mut self.texture_context, name)?;
+        self.bind_area_music(&map, self.level)?;
+        for block in &self.blocks {
+            if let Some(time) = block.emptying_time() {
+                self.emptying
+                    .insert((self.map.name.clone(), block.id), time);
+            }
+        }
+        let entities = self.parse_entities(&map, &self.collected, &self.emptying)?;
+        self.enter_area(map, entities)
+    }
+
+    fn enter_area(&mut self, map: GameMap, entities: AreaEntities) -> Result<(), AssetError> {
+        self.map = map;
+        self.set_entities(entities);
+        self.enemies.clear();
+        // Rewinding stops at the edge of the area instead of reloading maps.
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+        let size = self.map.size;
+        self.camera.set_world_size(size.width, size.height);
+        // Picked up again on the next tick if the player still has a star.
+        self.star_music = false;
+        self.play_level_music()
+    }
+
+    /// Moves on to the next level in the registry, looping after the last.
+    fn complete_level(&mut self) -> Result<(), AssetError> {
+        self.checkpoint = None;
+        self.load_level(self.levels.next(self.level))?;
+        self.save_progress();
+        Ok(())
+    }
+
+    /// The level's time budget: its map's `time` property, or the time
+    /// given in the level registry.
+    fn level_time(&self) -> u32 {
+        match self.map.property("time").and_then(Value::as_u64) {
+            Some(time) => time as u32,
+            None => self.levels.get(self.level).unwrap().time,
+        }
+    }
+
+    /// Checks every level's music up front so a missing file is reported
+    /// at startup rather than when the level is reached. The star, death
+    /// and hurry themes are optional and only noted as missing.
+    fn load_level_music(&mut self) -> Result<(), AssetError> {
+        let names: Vec<String> = self
+            .levels
+            .levels()
+            .iter()
+            .map(|x| x.music.clone())
+            .collect();
+        for name in names {
+            self.assets.load_sound(&name, AssetScope::Global)?;
+        }
+        for name in [STAR_MUSIC, DEATH_MUSIC, HURRY_MUSIC] {
+            match self.assets.load_sound(name, AssetScope::Global) {
+                Ok(_) => {}
+                Err(AssetError::NotFound(_)) => {
+                    self.missing_tracks.insert(name.to_string());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `name` can be played, i.e. it isn't an optional track known
+    /// to be missing.
+    fn has_track(&self, name: &str) -> bool {
+        !self.missing_tracks.contains(name)
+    }
+
+    /// Plays the star theme while a player has a star and the area's music
+    /// otherwise.
+    fn play_area_music(&mut self) -> Result<(), AssetError> {
+        if self.star_music && self.has_track(STAR_MUSIC) {
+            return self.play_music(STAR_MUSIC, music::Repeat::Forever);
+        }
+        self.play_level_music()
+    }
+
+    /// Plays the current map's `music` property, or the level's music when
+    /// the map has none. A track that is already playing keeps going. When
+    /// time is running out the map's `hurry_music` or the default hurry
+    /// theme plays instead, if it exists. A map's missing `hurry_music` is
+    /// reported the first time only.
+    fn play_level_music(&mut self) -> Result<(), AssetError> {
+        let hurry = match self.map.property("hurry_music").and_then(Value::as_str) {
+            Some(name) => name.to_string(),
+            None => HURRY_MUSIC.to_string(),
+        };
+        if self.hurry_music && self.has_track(&hurry) {
+            match self.play_music(&hurry, music::Repeat::Forever) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    eprintln!("Failed to play music: {}", e);
+                    self.missing_tracks.insert(hurry);
+                }
+            }
+        }
+        let name = self.area_music(&self.map, self.level);
+        self.play_music(&name, music::Repeat::Forever)
+    }
+
+    /// The `music` property of `map`, or the music of the level at `level`
+    /// when the map has none or its track is missing.
+    fn area_music(&self, map: &GameMap, level: usize) -> String {
+        match map.property("music").and_then(Value::as_str) {
+            Some(name) if self.has_track(name) => name.to_string(),
+            _ => self.levels.get(level).unwrap().music.clone(),
+        }
+    }
+
+    /// Binds the track `map` is going to play, before anything is switched
+    /// over to it, so entering the map can't fail halfway. A missing map
+    /// track is reported the first time only and the level's music plays
+    /// instead.
+    fn bind_area_music(&mut self, map: &GameMap, level: usize) -> Result<(), AssetError> {
+        let name = self.area_music(map, level);
+        let own = map.property("music").and_then(Value::as_str) == Some(name.as_str());
+        match self.bind_music(&name) {
+            Err(e @ AssetError::NotFound(_)) if own => {
+                eprintln!("Failed to play music: {}", e);
+                self.missing_tracks.insert(name);
+                self.bind_music(&self.area_music(map, level))?;
+            }
+            result => {
+                result?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Binds a track to the audio backend the first time it is needed.
+    fn bind_music(&mut self, name: &str) -> Result<usize, AssetError> {
+        if let Some(track) = self.music_tracks.iter().position(|x| x == name) {
+            return Ok(track);
+        }
+        let handle = self.assets.load_sound(name, AssetScope::Global)?;
+        let path = self.assets.sound(handle).unwrap();
+        music::bind_music_file(Music::Track(self.music_tracks.len()), path.as_path());
+        self.music_tracks.push(name.to_string());
+        Ok(self.music_tracks.len() - 1)
+    }
+
+    fn play_music(&mut self, name: &str, repeat: music::Repeat) -> Result<(), AssetError> {
+        let track = self.bind_music(name)?;
+        if self.current_track != Some(track) {
+            music::play_music(&Music::Track(track), repeat);
+            self.current_track = Some(track);
+        }
+        Ok(())
+    }
+
+    fn spawn_enemy(&mut self, kind: EnemyKind, x: f64, y: f64) {
+        let enemy = self.new_enemy(kind, x, y);
+        self.enemies.push(enemy);
+    }
+
+    fn new_enemy(&self, kind: EnemyKind, x: f64, y: f64) -> Enemy<G2dTexture> {
+        let sprites = match kind {
+            EnemyKind::Goomba => &self.goomba_sprites,
+        };
+        Enemy::new(
+            kind,
+            sprites.sprite_sheet(&self.assets),
+            sprites.definitions.clone(),
+            x,
+            y,
+        )
+    }
+
+    /// Runs a line typed into the console and prints its result.
+    fn run_command(&mut self, line: &str) {
+        self.console.print(&format!("> {}", line));
+        let result = self.console.parse(line).and_then(|(run, args)| {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            run(self, &args)
+        });
+        match result {
+            Ok(text) => self.console.print(&text),
+            Err(text) => self.console.print(&format!("error: {}", text)),
+        }
+    }
+
+    fn register_commands(&mut self) {
+        let console = &mut self.console;
+        libs::console::register_commands(console);
+        libs::debug::register_commands(console);
+        libs::level::register_commands(console);
+        libs::physics::register_commands(console);
+        libs::player::register_commands(console);
+        libs::timer::register_commands(console);
+        console.register(
+            "spawn",
+            "<goomba|mushroom|flower|star|1up> [x y] - spawn an enemy or item",
+            Self::command_spawn,
+        );
+        console.register(
+            "coins",
+            "[count] - show or set the coin count",
+            Self::command_coins,
+        );
+    }
+
+    fn command_spawn(&mut self, args: &[&str]) -> Result<String, String> {
+        let (name, position) = match args {
+            [name] => {
+                let player = self.player.get_transform();
+                (*name, (player.xw() + 48.0, player.yh() - 16.0))
+            }
+            [name, x, y] => (*name, (parse_number(x)?, parse_number(y)?)),
+            _ => return Err("usage: spawn <goomba|mushroom|flower|star|1up> [x y]".to_string()),
+        };
+        if let Some(kind) = EnemyKind::from_name(name) {
+            self.spawn_enemy(kind, position.0, position.1);
+        } else if let Some(kind) = ItemKind::from_name(name) {
+            let mut at = Transform::new();
+            at.set_position(position.0, position.1);
+            self.items.push(Item::new(
+                kind,
+                self.item_sprites.sprite_sheet(&self.assets),
+                self.item_sprites.definitions.clone(),
+                &at,
+            ));
+        } else {
+            return Err(format!("unknown enemy or item `{}`", name));
+        }
+        Ok(format!("spawned {} at {} {}", name, position.0, position.1))
+    }
+
+    fn command_coins(&mut self, args: &[&str]) -> Result<String, String> {
+        match args {
+            [] => {}
+            [count] => {
+                let count = parse_number(count)?;
+                if !(0.0..COINS_PER_LIFE as f64).contains(&count) {
+                    return Err(format!("count must be below {}", COINS_PER_LIFE));
+                }
+                self.coin_count = count as u32;
+            }
+            _ => return Err("usage: coins [count]".to_string()),
+        }
+        Ok(format!("{} coins, {} lives", self.coin_count, self.lives))
+    }
+
+    pub fn text_input(&mut self, text: &str) {
+        if self.console.open {
+            self.console.text_input(text);
+        }
+    }
+
+    pub fn update_input(&mut self, args: ButtonArgs) {
+        if let Button::Keyboard(key) = args.button {
+            if args.state == ButtonState::Press {
+                if key == Key::Backquote {
+                    self.console.toggle();
+                    self.clear_input();
+                    return;
+                }
+                if self.console.open {
+                    if let Some(line) = self.console.key_input(key) {
+                        self.run_command(&line);
+                    }
+                    return;
+                }
+                if key == Key::F3 {
+                    self.debug.toggle();
+                }
+                let offline = self.menus.is_empty() && self.netplay.is_none();
+                if key == Key::F5 && offline {
+                    self.quick_save();
+                }
+                if key == Key::F9 && offline {
+                    self.quick_load();
+                }
+            }
+        }
+
+        let pressed = args.state == ButtonState::Press;
+        if let Some(partner) = self.partner.as_mut() {
+            if self.menus.is_empty() && self.netplay.is_none() {
+                for action in self.settings.bindings_two.actions(args.button) {
+                    if action.moves_player() {
+                        partner.player.update_input(action, pressed);
+                    }
+                }
+            }
+        }
+        for action in self.settings.bindings.actions(args.button) {
+            if !self.menus.is_empty() {
+                // A button bound to several actions, like space for jump and
+                // confirm, does whichever the menu understands first.
+                if pressed && self.menu_action(action) {
+                    return;
+                }
+                continue;
+            }
+            if action == Action::Pause && pressed {
+                let menu = pause_menu(self.netplay.is_none());
+                self.open_menu(menu);
+                return;
+            }
+            if action == Action::Rewind {
+                self.rewinding = pressed && self.rewind.is_some();
+                self.clear_input();
+                continue;
+            }
+            match self.netplay.as_mut() {
+                Some(netplay) => netplay.action(action, pressed),
+                None => self.player.update_input(action, pressed),
+            }
+        }
+    }
+
+    /// Lets go of everything both players were holding.
+    fn clear_input(&mut self) {
+        self.player.clear_input();
+        if let Some(partner) = self.partner.as_mut() {
+            partner.player.clear_input();
+        }
+        if let Some(netplay) = self.netplay.as_mut() {
+            netplay.clear_input();
+        }
+    }
+
+    /// Pauses the game under `menu`, or shows it over the menu already open.
+    fn open_menu(&mut self, menu: Menu<MenuChoice>) {
+        self.menus.push(menu);
+        self.clear_input();
+        self.rewinding = false;
+        self.apply_volume();
+    }
+
+    fn close_menu(&mut self) {
+        self.menus.pop();
+        self.apply_volume();
+    }
+
+    /// Music is muted while the game is paused; the mixer can't pause it.
+    /// Sets the music volume, and pauses the track while a menu is open.
+    fn apply_volume(&self) {
+        music::set_volume(self.settings.music_volume);
+        match self.menus.is_empty() {
+            true => sdl2::mixer::Music::resume(),
+            false => sdl2::mixer::Music::pause(),
+        }
+    }
+
+    /// Returns whether the top menu used the action.
+    fn menu_action(&mut self, action: Action) -> bool {
+        let event = match self.menus.last_mut().and_then(|x| x.action(action)) {
+            Some(event) => event,
+            None => return false,
+        };
+        match event {
+            MenuEvent::Select(MenuChoice::Start) => {
+                let menu = self.slot_menu();
+                self.open_menu(menu);
+            }
+            MenuEvent::Select(MenuChoice::TwoPlayers) => {
+                self.on_title = false;
+                self.menus.clear();
+                self.apply_volume();
+                if let Err(e) = self.new_two_player_game() {
+                    eprintln!("Failed to start the game: {}", e);
+                    self.dev_error = Some(e.to_string());
+                }
+            }
+            MenuEvent::Select(MenuChoice::Coop) => {
+                self.on_title = false;
+                self.menus.clear();
+                self.apply_volume();
+                if let Err(e) = self.new_coop_game() {
+                    eprintln!("Failed to start the game: {}", e);
+                    self.dev_error = Some(e.to_string());
+                }
+            }
+            MenuEvent::Select(MenuChoice::Slot(slot)) => {
+                // A slot that can't be read is left alone instead of being
+                // overwritten by a new game.
+                let data = match self.saves.load_slot(slot) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        eprintln!("Failed to load save slot {}: {}", slot + 1, e);
+                        self.dev_error = Some(e.to_string());
+                        return true;
+                    }
+                };
+                self.on_title = false;
+                self.menus.clear();
+                self.apply_volume();
+                if let Err(e) = self.start_slot(slot, data) {
+                    eprintln!("Failed to start the game: {}", e);
+                    self.dev_error = Some(e.to_string());
+                }
+            }
+            MenuEvent::Select(MenuChoice::HighScores) => {
+                let menu = self.high_score_menu();
+                self.open_menu(menu);
+            }
+            MenuEvent::Select(MenuChoice::Resume) => self.close_menu(),
+            // Backing out of the title screen would leave a game that
+            // hasn't started.
+            MenuEvent::Back if self.menus.len() > 1 || !self.on_title => self.close_menu(),
+            MenuEvent::Select(MenuChoice::Back) => self.close_menu(),
+            MenuEvent::Select(MenuChoice::Restart) => {
+                self.menus.clear();
+                self.apply_volume();
+                self.checkpoint = None;
+                if let Err(e) = self.load_level(self.level) {
+                    eprintln!("Failed to restart the level: {}", e);
+                    self.dev_error = Some(e.to_string());
+                }
+            }
+            MenuEvent::Select(MenuChoice::Options) => {
+                let menu = self.options_menu();
+                self.open_menu(menu);
+            }
+            MenuEvent::Adjust(choice, step) => self.change_option(choice, step),
+            MenuEvent::Select(choice @ MenuChoice::Fullscreen)
+            | MenuEvent::Select(choice @ MenuChoice::VSync) => self.change_option(choice, 1),
+            MenuEvent::Select(MenuChoice::QuitToTitle) => {
+                self.on_title = true;
+                self.netplay = None;
+                self.menus = vec![title_menu()];
+                self.apply_volume();
+            }
+            MenuEvent::Select(MenuChoice::Quit) => self.window.set_should_close(true),
+            _ => {}
+        }
+        true
+    }
+
+    fn slot_menu(&self) -> Menu<MenuChoice> {
+        let items = (0..SLOT_COUNT)
+            .map(|slot| {
+                let label = match self.saves.load_slot(slot) {
+                    Ok(Some(data)) => format!(
+                        "Slot {}  {}  x{}  {:06}",
+                        slot + 1,
+                        data.world,
+                        data.lives,
+                        data.score
+                    ),
+                    Ok(None) => format!("Slot {}  new game", slot + 1),
+                    Err(_) => format!("Slot {}  unreadable", slot + 1),
+                };
+                (label, MenuChoice::Slot(slot))
+            })
+            .chain(std::iter::once(("Back".to_string(), MenuChoice::Back)))
+            .collect();
+        Menu::new("SELECT FILE", items, false)
+    }
+
+    fn high_score_menu(&self) -> Menu<MenuChoice> {
+        let scores = self.saves.high_scores().unwrap_or_else(|e| {
+            eprintln!("Failed to load high scores: {}", e);
+            Vec::new()
+        });
+        let mut items: Vec<(String, MenuChoice)> = scores
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let label = format!("{:2}. {:06}  {}", i + 1, x.score, x.world);
+                (label, MenuChoice::Back)
+            })
+            .collect();
+        if items.is_empty() {
+            items.push(("No scores yet".to_string(), MenuChoice::Back));
+        }
+        Menu::new("HIGH SCORES", items, false)
+    }
+
+    fn options_menu(&self) -> Menu<MenuChoice> {
+        let items = [
+            MenuChoice::MusicVolume,
+            MenuChoice::Scale,
+            MenuChoice::Fullscreen,
+            MenuChoice::VSync,
+        ]
+        .iter()
+        .map(|x| (self.option_label(*x), *x))
+        .chain(std::iter::once(("Back".to_string(), MenuChoice::Back)))
+        .collect();
+        Menu::new("OPTIONS", items, true)
+    }
+
+    fn option_label(&self, choice: MenuChoice) -> String {
+        let settings = &self.settings;
+        let on_off = |x: bool| if x { "on" } else { "off" };
+        match choice {
+            MenuChoice::MusicVolume => format!(
+                "Music volume  < {:2} >",
+                (settings.music_volume * VOLUME_STEPS).round()
+            ),
+            MenuChoice::Scale => format!("Window scale  < {} >*", settings.scale),
+            MenuChoice::Fullscreen => format!("Fullscreen    {}*", on_off(settings.fullscreen)),
+            MenuChoice::VSync => format!("VSync         {}*", on_off(settings.vsync)),
+            _ => String::new(),
+        }
+    }
+
+    /// Steps a setting up or down and writes the settings file. Window
+    /// settings, marked with a `*`, apply on the next start.
+    fn change_option(&mut self, choice: MenuChoice, step: i32) {
+        let volume = |x: f64| {
+            let steps = (x * VOLUME_STEPS).round() + step as f64;
+            steps.clamp(0.0, VOLUME_STEPS) / VOLUME_STEPS
+        };
+        let settings = &mut self.settings;
+        match choice {
+            MenuChoice::MusicVolume => settings.music_volume = volume(settings.music_volume),
+            MenuChoice::Scale => {
+                settings.scale = (settings.scale.round() + step as f64).clamp(1.0, MAX_SCALE)
+            }
+            MenuChoice::Fullscreen => settings.fullscreen = !settings.fullscreen,
+            MenuChoice::VSync => settings.vsync = !settings.vsync,
+            _ => return,
+        }
+        let label = self.option_label(choice);
+        if let Some(menu) = self.menus.last_mut() {
+            menu.set_label(choice, label);
+        }
+        self.apply_volume();
+        if let Err(e) = self.settings.save() {
+            eprintln!("Failed to save settings: {}", e);
+        }
+    }
+}
+
+impl ConsoleHost for Game {
+    fn console(&self) -> &Console<Self> {
+        &self.console
+    }
+}
+
+impl DebugHost for Game {
+    fn debug_overlay(&mut self) -> &mut DebugOverlay {
+        &mut self.debug
+    }
+}
+
+impl LevelHost for Game {
+    fn levels(&self) -> &LevelRegistry {
+        &self.levels
+    }
+
+    fn level(&self) -> usize {
+        self.level
+    }
+
+    fn start_level(&mut self, index: usize) -> Result<(), AssetError> {
+        self.checkpoint = None;
+        self.load_level(index)
+    }
+
+    fn finish_level(&mut self) -> Result<(), AssetError> {
+        self.complete_level()
+    }
+}
+
+impl PhysicsHost for Game {
+    fn select_physics_profile(&mut self, name: &str) -> bool {
+        let form = self.player.get_form();
+        if self.physics_config.profile(name, form).is_none() {
+            return false;
+        }
+        self.physics_profile = name.to_string();
+        self.apply_physics_profile();
+        true
+    }
+
+    fn physics_profile_mut(&mut self) -> &mut PhysicsProfile {
+        self.player.physics_profile_mut()
+    }
+}
+
+impl PlayerHost for Game {
+    type Texture = G2dTexture;
+
+    fn player_mut(&mut self) -> &mut Player<G2dTexture> {
+        &mut self.player
+    }
+
+    fn form_changed(&mut self) {
+        self.apply_physics_profile();
+    }
+}
+
+impl TimerHost for Game {
+    fn timer_mut(&mut self) -> &mut LevelTimer {
+        &mut self.timer
+    }
+}
+
+fn title_menu() -> Menu<MenuChoice> {
+    let items = vec![
+        ("Start".to_string(), MenuChoice::Start),
+        ("2 players".to_string(), MenuChoice::TwoPlayers),
+        ("Co-op".to_string(), MenuChoice::Coop),
+        ("High scores".to_string(), MenuChoice::HighScores),
+        ("Options".to_string(), MenuChoice::Options),
+        ("Quit".to_string(), MenuChoice::Quit),
+    ];
+    Menu::new("SUPER GOOMBA BROS", items, false)
+}
+
+/// Restarting is left out of online games, where only one side would do it.
+fn pause_menu(restart: bool) -> Menu<MenuChoice> {
+    let mut items = vec![("Resume".to_string(), MenuChoice::Resume)];
+    if restart {
+        items.push(("Restart level".to_string(), MenuChoice::Restart));
+    }
+    items.extend([
+        ("Options".to_string(), MenuChoice::Options),
+        ("Quit to title".to_string(), MenuChoice::QuitToTitle),
+    ]);
+    Menu::new("PAUSED", items, true)
+}
+
+fn main() {
+    let viewport_size: Size = Size::from([352.0, 224.0]);
+
+    let args: Vec<String> = std::env::args().collect();
+    let dev_mode = args.iter().any(|x| x == "--dev");
+    let sync_test = args.iter().any(|x| x == "--sync-test");
+    // `--net <player> <local address> <remote address>`
+    let net = args
+        .iter()
+        .position(|x| x == "--net")
+        .map(|i| args.get(i + 1..i + 4).unwrap_or_default());
+
+    let settings_path = Path::new(SETTINGS_FILE);
+    let settings = Settings::load(settings_path).unwrap_or_else(|e| {
+        eprintln!("Using default settings: {}", e);
+        Settings::new(settings_path)
+    });
+
+    let mut game: Game = Game::new(viewport_size, settings, dev_mode).unwrap_or_else(|e| {
+        eprintln!("Failed to start: {}", e);
+        std::process::exit(1);
+    });
+    game.load_level_music().unwrap_or_else(|e| {
+        eprintln!("Failed to start: {}", e);
+        std::process::exit(1);
+    });
+
+    // The mixer and the gamepads share one SDL context.
+    let sdl = sdl2::init().unwrap_or_else(|e| {
+        eprintln!("Failed to start SDL: {}", e);
+        std::process::exit(1);
+    });
+    let mut gamepads = Gamepads::new(&sdl)
+        .map_err(|e| eprintln!("Gamepads are unavailable: {}", e))
+        .ok();
+
+    music::start_context::<Music, Sound, _>(&sdl, 16, || {
+        game.apply_volume();
+        if let Err(e) = game.play_level_music() {
+            eprintln!("Failed to play music: {}", e);
+        }
+
+        // Starting a game plays its music, so the mixer has to be up first.
+        let session = match net {
+            Some([player, local, remote]) => {
+                let player = match player.as_str() {
+                    "1" => 0,
+                    "2" => 1,
+                    _ => {
+                        eprintln!("--net: the player must be 1 or 2");
+                        std::process::exit(1);
+                    }
+                };
+                let peer = Peer::connect(local, remote).unwrap_or_else(|e| {
+                    eprintln!("Failed to open {}: {}", local, e);
+                    std::process::exit(1);
+                });
+                game.start_netplay(peer, player)
+            }
+            Some(_) => {
+                eprintln!("usage: --net <1|2> <local address> <remote address>");
+                std::process::exit(1);
+            }
+            None if sync_test => game.start_sync_test(),
+            None => Ok(()),
+        };
+        session.unwrap_or_else(|e| {
+            eprintln!("Failed to start: {}", e);
+            std::process::exit(1);
+        });
+
+        while let Some(e) = game.window.next() {
+            if let Some(_) = e.render_args() {
+                game.render(&e);
+            }
+
+            if let Some(u) = e.update_args() {
+                for args in gamepads.iter_mut().flat_map(Gamepads::poll) {
+                    game.update_input(args);
+                }
+                game.update(u.dt);
+            }
+
+            if let Some(text) = e.text_args() {
+                game.text_input(&text);
             }
 
             if let Some(args) = e.button_args() {